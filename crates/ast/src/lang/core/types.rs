@@ -761,7 +761,7 @@ fn to_type_apply<'a>(
             Err(problem) => {
                 env.problem(roc_problem::can::Problem::RuntimeError(problem));
 
-                return TypeApply::Erroneous(Problem::UnrecognizedIdent(ident.into()));
+                return TypeApply::Erroneous(Problem::UnrecognizedIdent(Loc::at(region, ident)));
             }
         }
     } else {
@@ -772,7 +772,10 @@ fn to_type_apply<'a>(
                 // it was imported but it doesn't expose this ident.
                 env.problem(roc_problem::can::Problem::RuntimeError(problem));
 
-                return TypeApply::Erroneous(Problem::UnrecognizedIdent((*ident).into()));
+                return TypeApply::Erroneous(Problem::UnrecognizedIdent(Loc::at(
+                    region,
+                    (*ident).into(),
+                )));
             }
         }
     };
@@ -22,6 +22,7 @@ interface Encode
         list,
         record,
         tag,
+        emptyRecord,
         custom,
         appendWith,
         append,
@@ -53,6 +54,7 @@ EncoderFormatting has
     list : List elem, (elem -> Encoder fmt) -> Encoder fmt | fmt has EncoderFormatting
     record : List { key : Str, value : Encoder fmt } -> Encoder fmt | fmt has EncoderFormatting
     tag : Str, List (Encoder fmt) -> Encoder fmt | fmt has EncoderFormatting
+    emptyRecord : {} -> Encoder fmt | fmt has EncoderFormatting
 
 custom : (List U8, fmt -> List U8) -> Encoder fmt | fmt has EncoderFormatting
 custom = \encoder -> @Encoder encoder
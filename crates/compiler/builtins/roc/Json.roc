@@ -39,6 +39,7 @@ Json := {} has [
              list: encodeList,
              record: encodeRecord,
              tag: encodeTag,
+             emptyRecord: encodeEmptyRecord,
          },
          DecoderFormatting {
              u8: decodeU8,
@@ -146,6 +147,9 @@ encodeRecord = \fields ->
 
         List.append bytesWithRecord (Num.toU8 '}')
 
+encodeEmptyRecord = \{} ->
+    Encode.custom \bytes, @Json {} -> List.concat bytes (Str.toUtf8 "{}")
+
 encodeTag = \name, payload ->
     Encode.custom \bytes, @Json {} ->
         # Idea: encode `A v1 v2` as `{"A": [v1, v2]}`
@@ -0,0 +1,108 @@
+//! Measures the memory held by many instantiations of one large alias, copying its body for each
+//! instantiation versus sharing it between them.
+//!
+//! Run with `cargo bench -p roc_can --bench bench_alias_instantiation`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use roc_can::annotation::{
+    instantiate_and_freshen_alias_type, instantiate_shared_alias_type, IntroducedVariables,
+};
+use roc_collections::SendMap;
+use roc_module::symbol::Symbol;
+use roc_region::all::{Loc, Region};
+use roc_types::subs::VarStore;
+use roc_types::types::{AliasVar, RecordField, SharedAliasBody, Type, TypeExtension, Variance};
+
+/// Counts the bytes currently allocated, so that we can see what a batch of instantiations holds.
+struct CountingAlloc;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const FIELDS: usize = 200;
+const INSTANTIATIONS: usize = 1_000;
+
+/// Big a : { field0 : List a, field1 : List a, ... }
+fn big_alias(var_store: &mut VarStore) -> (Vec<Loc<AliasVar>>, Type) {
+    let a = var_store.fresh();
+    let type_variables = vec![Loc::at_zero(AliasVar {
+        name: "a".into(),
+        var: a,
+        opt_bound_ability: None,
+        opt_default: None,
+        variance: Variance::Inferred,
+    })];
+
+    let mut fields = SendMap::default();
+    for i in 0..FIELDS {
+        let field = Type::Apply(Symbol::LIST_LIST, vec![Type::Variable(a)], Region::zero());
+        fields.insert(format!("field{}", i).into(), RecordField::Required(field));
+    }
+
+    (type_variables, Type::Record(fields, TypeExtension::Closed))
+}
+
+/// The bytes still allocated once `instantiate` has run [`INSTANTIATIONS`] times, with every
+/// instantiation kept alive.
+fn held_bytes<T>(mut instantiate: impl FnMut() -> T) -> usize {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let instantiations: Vec<T> = (0..INSTANTIATIONS).map(|_| instantiate()).collect();
+    let after = ALLOCATED.load(Ordering::Relaxed);
+    drop(instantiations);
+
+    after.saturating_sub(before)
+}
+
+fn main() {
+    let mut var_store = VarStore::default();
+    let mut introduced_variables = IntroducedVariables::default();
+    let (type_variables, body) = big_alias(&mut var_store);
+    let shared_body = SharedAliasBody::new(body.clone());
+
+    let copied = held_bytes(|| {
+        let arg = Type::Variable(var_store.fresh());
+        instantiate_and_freshen_alias_type(
+            &mut var_store,
+            &mut introduced_variables,
+            &type_variables,
+            vec![arg],
+            &[],
+            body.clone(),
+        )
+    });
+
+    let shared = held_bytes(|| {
+        let arg = Type::Variable(var_store.fresh());
+        instantiate_shared_alias_type(
+            &mut var_store,
+            &mut introduced_variables,
+            &type_variables,
+            vec![arg],
+            &[],
+            &shared_body,
+        )
+    });
+
+    println!(
+        "{} instantiations of an alias with {} fields:",
+        INSTANTIATIONS, FIELDS
+    );
+    println!("  copied bodies: {:>12} bytes", copied);
+    println!("  shared bodies: {:>12} bytes", shared);
+}
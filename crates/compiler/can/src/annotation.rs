@@ -1,16 +1,21 @@
 use crate::env::Env;
 use crate::procedure::References;
 use crate::scope::{PendingAbilitiesInScope, Scope};
-use roc_collections::{ImMap, MutSet, SendMap, VecMap, VecSet};
-use roc_module::ident::{Ident, Lowercase, TagName};
-use roc_module::symbol::Symbol;
-use roc_parse::ast::{AssignedField, ExtractSpaces, Pattern, Tag, TypeAnnotation, TypeHeader};
-use roc_problem::can::ShadowKind;
+use roc_collections::{ImMap, MutMap, MutSet, SendMap, VecMap, VecSet};
+use roc_error_macros::internal_error;
+use roc_module::ident::{Ident, Lowercase, ModuleName, TagName};
+use roc_module::symbol::{Interns, Symbol};
+use roc_parse::ast::{
+    AssignedField, ExtractSpaces, HasClause, Pattern, Tag, TypeAnnotation, TypeHeader,
+};
+use roc_problem::can::{ExtensionTypeKind, ShadowKind};
 use roc_region::all::{Loc, Region};
-use roc_types::subs::{VarStore, Variable};
+use roc_types::pretty_print::Parens;
+use roc_types::subs::{Content, Subs, VarStore, Variable};
 use roc_types::types::{
-    name_type_var, Alias, AliasCommon, AliasKind, AliasVar, LambdaSet, OptAbleType, OptAbleVar,
-    Problem, RecordField, Type, TypeExtension,
+    name_type_var, rename_variable, Alias, AliasCommon, AliasKind, AliasVar, InstantiatedAlias,
+    LambdaSet, OptAbleType, OptAbleVar, Problem, RecordField, SharedAliasBody, Type, TypeExtension,
+    Variance,
 };
 
 #[derive(Clone, Debug)]
@@ -19,9 +24,133 @@ pub struct Annotation {
     pub introduced_variables: IntroducedVariables,
     pub references: VecSet<Symbol>,
     pub aliases: VecMap<Symbol, Alias>,
+    /// For each alias or opaque the annotation refers to that has phantom type variables,
+    /// the names of those variables.
+    pub phantom_variables: VecMap<Symbol, Vec<Lowercase>>,
+}
+
+/// How an annotation's dependencies changed between two canonicalizations of it; see
+/// [`Annotation::diff`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AnnotationDiff {
+    pub added_references: Vec<Symbol>,
+    pub removed_references: Vec<Symbol>,
+    /// Aliases introduced by only one of the annotations, or whose definitions differ in their
+    /// kind, their type variables, or the types they refer to.
+    pub changed_aliases: Vec<Symbol>,
+}
+
+impl AnnotationDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_references.is_empty()
+            && self.removed_references.is_empty()
+            && self.changed_aliases.is_empty()
+    }
 }
 
 impl Annotation {
+    /// Builds an annotation from parts made without the canonicalizer, e.g. in tests. The
+    /// annotation has no phantom variables.
+    pub fn new(
+        typ: Type,
+        introduced_variables: IntroducedVariables,
+        references: VecSet<Symbol>,
+        aliases: VecMap<Symbol, Alias>,
+    ) -> Self {
+        debug_assert!(
+            {
+                let mut names = introduced_variables.iter_named().map(|v| v.name());
+                let mut seen = MutSet::default();
+                names.all(|name| seen.insert(name))
+            },
+            "Two named variables share a name in {:?}",
+            introduced_variables
+        );
+
+        Self {
+            typ,
+            introduced_variables,
+            references,
+            aliases,
+            phantom_variables: VecMap::default(),
+        }
+    }
+
+    /// Compares the dependencies of this annotation with those of `prev`, an earlier
+    /// canonicalization of the same annotation, so that only what depends on the changes needs
+    /// to be checked again.
+    pub fn diff(&self, prev: &Annotation) -> AnnotationDiff {
+        fn alias_shape(alias: &Alias) -> (AliasKind, Vec<&Lowercase>, Vec<Symbol>) {
+            let mut symbols = alias.typ.symbols();
+            symbols.sort();
+            symbols.dedup();
+
+            let type_variables = alias.type_variables.iter().map(|v| &v.value.name);
+
+            (alias.kind, type_variables.collect(), symbols)
+        }
+
+        let added_references = (self.references.iter())
+            .filter(|symbol| !prev.references.contains(symbol))
+            .copied()
+            .collect();
+        let removed_references = (prev.references.iter())
+            .filter(|symbol| !self.references.contains(symbol))
+            .copied()
+            .collect();
+
+        let mut changed_aliases: Vec<_> = (self.aliases.iter())
+            .filter(|(symbol, alias)| match prev.aliases.get(symbol) {
+                Some(prev_alias) => alias_shape(alias) != alias_shape(prev_alias),
+                None => true,
+            })
+            .map(|(symbol, _)| *symbol)
+            .collect();
+        changed_aliases.extend(
+            (prev.aliases.keys())
+                .filter(|symbol| !self.aliases.contains_key(symbol))
+                .copied(),
+        );
+
+        AnnotationDiff {
+            added_references,
+            removed_references,
+            changed_aliases,
+        }
+    }
+
+    /// A hash of the annotation for keying a build cache. It covers the type, what the annotation
+    /// refers to, and the names of its named variables, but not how its variables happen to be
+    /// numbered: variables are renumbered in the order they occur before hashing.
+    ///
+    /// Symbols are hashed by their ids rather than their qualified names, so hashes only agree
+    /// across compilations that number the module's identifiers the same way, like rebuilds of
+    /// unchanged sources.
+    pub fn structural_hash(&self) -> u64 {
+        use std::hash::{BuildHasher, Hash, Hasher};
+
+        let mut renaming = MutMap::default();
+        let typ = self
+            .typ
+            .rename_all_variables(&mut renaming, &mut VarStore::default());
+
+        let mut references: Vec<_> = self.references.iter().collect();
+        references.sort();
+
+        // Named variables that do not occur in the type keep no number.
+        let mut named: Vec<_> = (self.introduced_variables.iter_named())
+            .map(|v| (v.name(), renaming.get(&v.variable()).copied()))
+            .collect();
+        named.sort();
+
+        let mut state = roc_collections::default_hasher().build_hasher();
+        typ.hash(&mut state);
+        references.hash(&mut state);
+        named.hash(&mut state);
+
+        state.finish()
+    }
+
     pub fn add_to(
         &self,
         aliases: &mut VecMap<Symbol, Alias>,
@@ -40,6 +169,450 @@ impl Annotation {
             }
         }
     }
+
+    /// Substitutes `to` for every occurrence of the rigid variable named `from`. The rigid is no
+    /// longer considered introduced by this annotation afterwards.
+    pub fn rename_rigid(&mut self, from: &Lowercase, to: Type) {
+        let named = (self.introduced_variables.named.iter())
+            .find(|nv| &nv.name == from)
+            .cloned();
+
+        if let Some(named) = named {
+            let mut substitutions = ImMap::default();
+            substitutions.insert(named.variable, to);
+
+            self.typ.substitute(&substitutions);
+            self.introduced_variables.named.remove(&named);
+        }
+    }
+
+    /// The named rigid variables that actually occur in the annotated type, including those bound
+    /// to abilities, like the `a` of `a -> U64 | a has Hash`. Variables without abilities come
+    /// first, each group in the order they were introduced. These are the variables a signature
+    /// made from this annotation generalizes.
+    pub fn free_rigids_by_name(&self) -> Vec<(Lowercase, Variable)> {
+        let used = self.typ.variables();
+
+        (self.introduced_variables.iter_named())
+            .filter(|v| v.rigidity() == Rigidity::Rigid && used.contains(&v.variable()))
+            .map(|v| (v.name().clone(), v.variable()))
+            .collect()
+    }
+
+    /// Splits the annotation into the variables a scheme for it quantifies over, and its type.
+    /// Only the named rigids in [`Annotation::free_rigids_by_name`], with or without abilities,
+    /// are quantified; wildcards and lambda sets are left for the solver to fill in.
+    pub fn into_scheme(self) -> (Vec<Variable>, Type) {
+        let quantified = (self.free_rigids_by_name().into_iter())
+            .map(|(_, var)| var)
+            .collect();
+
+        (quantified, self.typ)
+    }
+
+    /// Rewrites the structural types within the annotated type that are exactly what an alias of
+    /// `aliases` stands for into that alias, like `{ x : I64, y : I64 }` into `Point`, so the type
+    /// reads the way it would be written. Where several aliases match, the most specific one, with
+    /// the fewest type variables, wins. Opaques are never folded into. This only changes how the
+    /// type is rendered, not the type it stands for.
+    pub fn fold_into_aliases(&mut self, aliases: &VecMap<Symbol, Alias>) {
+        fold_into_aliases(&mut self.typ, aliases);
+    }
+
+    /// What each wildcard in the annotation was solved to, by the region the wildcard was
+    /// written at. Only meaningful once the annotation's variables have been solved in `subs`.
+    pub fn solved_wildcards(&self, subs: &Subs) -> Vec<(Region, Content)> {
+        (self.introduced_variables.wildcards.iter())
+            .map(|wildcard| {
+                let content = *subs.get_content_without_compacting(wildcard.value);
+                (wildcard.region, content)
+            })
+            .collect()
+    }
+
+    /// Renders the annotation back as source, keeping the names the user gave its type
+    /// variables. Wildcards are rendered as `*`, and unnamed inferred variables as `_`.
+    pub fn to_source_string(&self, interns: &Interns) -> String {
+        let mut buf = String::new();
+        write_type_with_names(
+            interns,
+            &self.introduced_variables,
+            &self.typ,
+            &mut buf,
+            Parens::Unnecessary,
+        );
+
+        buf
+    }
+}
+
+fn fold_into_aliases(typ: &mut Type, aliases: &VecMap<Symbol, Alias>) {
+    use Type::*;
+
+    let fold_ext = |ext: &mut TypeExtension| {
+        if let TypeExtension::Open(ext) = ext {
+            fold_into_aliases(ext, aliases);
+        }
+    };
+
+    // Fold the parts first, so that an alias whose body refers to other aliases can match too.
+    match typ {
+        Function(args, _, ret) => {
+            for arg in args.iter_mut() {
+                fold_into_aliases(arg, aliases);
+            }
+            fold_into_aliases(ret, aliases);
+        }
+        Record(fields, ext) => {
+            for (_, field) in fields.iter_mut() {
+                fold_into_aliases(field.as_inner_mut(), aliases);
+            }
+            fold_ext(ext);
+        }
+        TagUnion(tags, ext) | RecursiveTagUnion(_, tags, ext) => {
+            for arg in tags.iter_mut().flat_map(|(_, args)| args.iter_mut()) {
+                fold_into_aliases(arg, aliases);
+            }
+            fold_ext(ext);
+        }
+        FunctionOrTagUnion(_, _, ext) => fold_ext(ext),
+        Apply(_, args, _)
+        | DelayedAlias(AliasCommon {
+            type_arguments: args,
+            ..
+        })
+        | HostExposedAlias {
+            type_arguments: args,
+            ..
+        } => {
+            for arg in args.iter_mut() {
+                fold_into_aliases(arg, aliases);
+            }
+        }
+        Alias { type_arguments, .. } => {
+            for arg in type_arguments.iter_mut() {
+                fold_into_aliases(&mut arg.typ, aliases);
+            }
+        }
+        EmptyRec
+        | EmptyTagUnion
+        | ClosureTag { .. }
+        | UnspecializedLambdaSet { .. }
+        | Variable(_)
+        | RangedNumber(_)
+        | Erroneous(_) => {}
+    }
+
+    if let Some(alias) = most_specific_alias(typ, aliases) {
+        *typ = alias;
+    }
+}
+
+/// The alias of `aliases` with the fewest type variables that stands for exactly `typ`, applied to
+/// the types its variables stand for.
+fn most_specific_alias(typ: &Type, aliases: &VecMap<Symbol, Alias>) -> Option<Type> {
+    let mut best: Option<(Symbol, &Alias, Vec<Type>)> = None;
+
+    for (&symbol, alias) in aliases.iter() {
+        // Aliases of a bare type variable stand for every type, and functions carry lambda sets
+        // that never match another type's.
+        if alias.kind == AliasKind::Opaque
+            || !alias.lambda_set_variables.is_empty()
+            || matches!(alias.typ, Type::Variable(_))
+        {
+            continue;
+        }
+
+        let params: Vec<_> = (alias.type_variables.iter())
+            .map(|loc_var| loc_var.value.var)
+            .collect();
+        let mut bindings = vec![None; params.len()];
+        if !matches_alias_body(&alias.typ, typ, &params, &mut bindings) {
+            continue;
+        }
+
+        // A variable the body doesn't use can't be recovered from the type.
+        let arguments = match bindings.into_iter().collect::<Option<Vec<_>>>() {
+            Some(arguments) => arguments,
+            None => continue,
+        };
+
+        let is_better = match &best {
+            Some((best_symbol, best_alias, _)) => {
+                (params.len(), symbol) < (best_alias.type_variables.len(), *best_symbol)
+            }
+            None => true,
+        };
+        if is_better {
+            best = Some((symbol, alias, arguments));
+        }
+    }
+
+    best.map(|(symbol, alias, arguments)| Type::Alias {
+        symbol,
+        type_arguments: (alias.type_variables.iter())
+            .zip(arguments)
+            .map(|(loc_var, typ)| OptAbleType {
+                typ,
+                opt_ability: loc_var.value.opt_bound_ability,
+            })
+            .collect(),
+        lambda_set_variables: Vec::new(),
+        actual: Box::new(typ.clone()),
+        kind: alias.kind,
+    })
+}
+
+/// Whether `typ` is what the alias body `pattern` stands for, when each of the alias's `params`
+/// stands for the type in `bindings` at the same index. Unbound params are bound as they are met.
+/// Aliases already folded into `typ` are seen through, and regions are ignored.
+fn matches_alias_body(
+    pattern: &Type,
+    typ: &Type,
+    params: &[Variable],
+    bindings: &mut [Option<Type>],
+) -> bool {
+    use Type::*;
+
+    let all_match = |patterns: &[Type], types: &[Type], bindings: &mut [Option<Type>]| {
+        patterns.len() == types.len()
+            && (patterns.iter().zip(types))
+                .all(|(pattern, typ)| matches_alias_body(pattern, typ, params, bindings))
+    };
+
+    if let Variable(var) = pattern {
+        if let Some(index) = params.iter().position(|param| param == var) {
+            return match &bindings[index] {
+                Some(bound) => bound == typ,
+                None => {
+                    bindings[index] = Some(typ.clone());
+                    true
+                }
+            };
+        }
+    }
+
+    match (pattern, typ) {
+        (
+            DelayedAlias(AliasCommon {
+                symbol: pattern_symbol,
+                type_arguments: pattern_args,
+                ..
+            }),
+            Alias {
+                symbol,
+                type_arguments: args,
+                ..
+            },
+        ) => {
+            let args: Vec<_> = args.iter().map(|arg| arg.typ.clone()).collect();
+            pattern_symbol == symbol && all_match(pattern_args, &args, bindings)
+        }
+        (_, Alias { actual, .. }) => matches_alias_body(pattern, actual, params, bindings),
+        (Apply(pattern_symbol, pattern_args, _), Apply(symbol, args, _)) => {
+            pattern_symbol == symbol && all_match(pattern_args, args, bindings)
+        }
+        (Record(pattern_fields, pattern_ext), Record(fields, ext)) => {
+            pattern_fields.len() == fields.len()
+                && pattern_fields.iter().all(|(name, pattern_field)| {
+                    fields.get(name).map_or(false, |field| {
+                        std::mem::discriminant(pattern_field) == std::mem::discriminant(field)
+                            && matches_alias_body(
+                                pattern_field.as_inner(),
+                                field.as_inner(),
+                                params,
+                                bindings,
+                            )
+                    })
+                })
+                && matches_alias_ext(pattern_ext, ext, params, bindings)
+        }
+        (TagUnion(pattern_tags, pattern_ext), TagUnion(tags, ext)) => {
+            pattern_tags.len() == tags.len()
+                && pattern_tags.iter().all(|(name, pattern_args)| {
+                    (tags.iter().find(|(tag, _)| tag == name))
+                        .map_or(false, |(_, args)| all_match(pattern_args, args, bindings))
+                })
+                && matches_alias_ext(pattern_ext, ext, params, bindings)
+        }
+        _ => pattern == typ,
+    }
+}
+
+fn matches_alias_ext(
+    pattern: &TypeExtension,
+    ext: &TypeExtension,
+    params: &[Variable],
+    bindings: &mut [Option<Type>],
+) -> bool {
+    match (pattern, ext) {
+        (TypeExtension::Closed, TypeExtension::Closed) => true,
+        (TypeExtension::Open(pattern), TypeExtension::Open(ext)) => {
+            matches_alias_body(pattern, ext, params, bindings)
+        }
+        _ => false,
+    }
+}
+
+fn write_type_with_names(
+    interns: &Interns,
+    introduced_variables: &IntroducedVariables,
+    typ: &Type,
+    buf: &mut String,
+    parens: Parens,
+) {
+    let write_applied = |buf: &mut String, symbol: Symbol, args: Vec<&Type>| {
+        let write_parens = parens == Parens::InTypeParam && !args.is_empty();
+
+        if write_parens {
+            buf.push('(');
+        }
+        buf.push_str(symbol.as_str(interns));
+
+        for arg in args {
+            buf.push(' ');
+            write_type_with_names(interns, introduced_variables, arg, buf, Parens::InTypeParam);
+        }
+
+        if write_parens {
+            buf.push(')');
+        }
+    };
+
+    match typ {
+        Type::EmptyRec => buf.push_str("{}"),
+        Type::EmptyTagUnion => buf.push_str("[]"),
+        Type::Variable(var) => {
+            let var = *var;
+            let name = (introduced_variables.iter_named())
+                .find(|v| v.variable() == var)
+                .map(|v| v.name().as_str());
+
+            if let Some(name) = name {
+                buf.push_str(name);
+            } else if introduced_variables.inferred.iter().any(|v| v.value == var) {
+                buf.push('_');
+            } else {
+                buf.push('*');
+            }
+        }
+        Type::Apply(symbol, args, _) => write_applied(buf, *symbol, args.iter().collect()),
+        Type::DelayedAlias(AliasCommon {
+            symbol,
+            type_arguments,
+            ..
+        }) => write_applied(buf, *symbol, type_arguments.iter().collect()),
+        Type::Alias {
+            symbol,
+            type_arguments,
+            ..
+        } => write_applied(
+            buf,
+            *symbol,
+            type_arguments.iter().map(|arg| &arg.typ).collect(),
+        ),
+        Type::HostExposedAlias {
+            name,
+            type_arguments,
+            ..
+        } => write_applied(buf, *name, type_arguments.iter().collect()),
+        Type::Function(args, _closure, ret) => {
+            let write_parens = parens != Parens::Unnecessary;
+
+            if write_parens {
+                buf.push('(');
+            }
+
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    buf.push_str(", ");
+                }
+                write_type_with_names(interns, introduced_variables, arg, buf, Parens::InFn);
+            }
+
+            buf.push_str(" -> ");
+            write_type_with_names(interns, introduced_variables, ret, buf, Parens::InFn);
+
+            if write_parens {
+                buf.push(')');
+            }
+        }
+        Type::Record(fields, ext) => {
+            buf.push('{');
+
+            for (i, (label, field)) in fields.iter().enumerate() {
+                buf.push_str(if i > 0 { ", " } else { " " });
+                buf.push_str(label.as_str());
+
+                let field_type = match field {
+                    RecordField::Optional(typ) | RecordField::RigidOptional(typ) => {
+                        buf.push_str(" ? ");
+                        typ
+                    }
+                    RecordField::Required(typ) | RecordField::Demanded(typ) => {
+                        buf.push_str(" : ");
+                        typ
+                    }
+                };
+                write_type_with_names(
+                    interns,
+                    introduced_variables,
+                    field_type,
+                    buf,
+                    Parens::Unnecessary,
+                );
+            }
+
+            if !fields.is_empty() {
+                buf.push(' ');
+            }
+            buf.push('}');
+
+            if let TypeExtension::Open(ext) = ext {
+                write_type_with_names(interns, introduced_variables, ext, buf, Parens::InTypeParam);
+            }
+        }
+        Type::TagUnion(tags, ext) | Type::RecursiveTagUnion(_, tags, ext) => {
+            buf.push('[');
+
+            for (i, (tag_name, args)) in tags.iter().enumerate() {
+                if i > 0 {
+                    buf.push_str(", ");
+                }
+                buf.push_str(tag_name.0.as_str());
+
+                for arg in args {
+                    buf.push(' ');
+                    write_type_with_names(
+                        interns,
+                        introduced_variables,
+                        arg,
+                        buf,
+                        Parens::InTypeParam,
+                    );
+                }
+            }
+
+            buf.push(']');
+
+            if let TypeExtension::Open(ext) = ext {
+                write_type_with_names(interns, introduced_variables, ext, buf, Parens::InTypeParam);
+            }
+        }
+        Type::FunctionOrTagUnion(tag_name, _, ext) => {
+            buf.push('[');
+            buf.push_str(tag_name.0.as_str());
+            buf.push(']');
+
+            if let TypeExtension::Open(ext) = ext {
+                write_type_with_names(interns, introduced_variables, ext, buf, Parens::InTypeParam);
+            }
+        }
+        Type::ClosureTag { .. }
+        | Type::UnspecializedLambdaSet { .. }
+        | Type::RangedNumber(_)
+        | Type::Erroneous(_) => buf.push('?'),
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -56,8 +629,8 @@ impl<'a> NamedOrAbleVariable<'a> {
         }
     }
 
-    pub fn name(&self) -> &Lowercase {
-        match self {
+    pub fn name(&self) -> &'a Lowercase {
+        match *self {
             NamedOrAbleVariable::Named(nv) => &nv.name,
             NamedOrAbleVariable::Able(av) => &av.name,
         }
@@ -69,6 +642,13 @@ impl<'a> NamedOrAbleVariable<'a> {
             NamedOrAbleVariable::Able(av) => av.variable,
         }
     }
+
+    pub fn rigidity(&self) -> Rigidity {
+        match self {
+            NamedOrAbleVariable::Named(nv) => nv.rigidity,
+            NamedOrAbleVariable::Able(_) => Rigidity::Rigid,
+        }
+    }
 }
 
 pub enum OwnedNamedOrAble {
@@ -113,6 +693,14 @@ impl OwnedNamedOrAble {
     }
 }
 
+/// Whether a named type variable should be introduced as rigid, or left flexible so that it can
+/// be unified with other types.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Rigidity {
+    Rigid,
+    Flex,
+}
+
 /// A named type variable, not bound to an ability.
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct NamedVariable {
@@ -120,6 +708,7 @@ pub struct NamedVariable {
     pub name: Lowercase,
     // NB: there may be multiple occurrences of a variable
     pub first_seen: Region,
+    pub rigidity: Rigidity,
 }
 
 /// A type variable bound to an ability, like "a has Hash".
@@ -140,6 +729,12 @@ pub struct IntroducedVariables {
     pub named: VecSet<NamedVariable>,
     pub able: VecSet<AbleVariable>,
     pub host_exposed_aliases: VecMap<Symbol, Variable>,
+    /// Set once more `_` and `*` variables were written than the environment allows.
+    pub inferred_limit_reached: bool,
+    /// Type names that were not in scope, and the variables standing in for them. Only
+    /// populated when the environment defers unrecognized types. All occurrences of such a name
+    /// share one variable, which is also recorded in `inferred`.
+    pub deferred_names: VecMap<Ident, Variable>,
 }
 
 impl IntroducedVariables {
@@ -154,18 +749,71 @@ impl IntroducedVariables {
             .all(|&v| v != var));
     }
 
+    /// Panics if a name or a variable was introduced more than once, which means these
+    /// variables were put together incorrectly.
+    #[cfg(debug_assertions)]
+    pub fn assert_consistent(&self) {
+        let mut names = MutSet::default();
+        for name in self.iter_named().map(|v| v.name()) {
+            assert!(
+                names.insert(name),
+                "The name {:?} is introduced more than once in {:?}",
+                name,
+                self
+            );
+        }
+
+        let mut variables = MutSet::default();
+        let all_variables = (self.wildcards.iter().map(|v| v.value))
+            .chain(self.lambda_sets.iter().copied())
+            .chain(self.inferred.iter().map(|v| v.value))
+            .chain(self.named.iter().map(|nv| nv.variable))
+            .chain(self.able.iter().map(|av| av.variable))
+            .chain(self.host_exposed_aliases.values().copied());
+        for var in all_variables {
+            assert!(
+                variables.insert(var),
+                "The variable {:?} is introduced more than once in {:?}",
+                var,
+                self
+            );
+        }
+    }
+
     pub fn insert_named(&mut self, name: Lowercase, var: Loc<Variable>) {
+        self.insert_named_with_rigidity(name, var, Rigidity::Rigid)
+    }
+
+    pub fn insert_named_with_rigidity(
+        &mut self,
+        name: Lowercase,
+        var: Loc<Variable>,
+        rigidity: Rigidity,
+    ) {
         self.debug_assert_not_already_present(var.value);
 
         let named_variable = NamedVariable {
             name,
             variable: var.value,
             first_seen: var.region,
+            rigidity,
         };
 
         self.named.insert(named_variable);
     }
 
+    /// Changes how the named variable `name` will be introduced. Returns `false` if there is no
+    /// such variable.
+    pub fn set_rigidity(&mut self, name: &Lowercase, rigidity: Rigidity) -> bool {
+        match self.named.iter_mut().find(|nv| &nv.name == name) {
+            Some(nv) => {
+                nv.rigidity = rigidity;
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn insert_able(&mut self, name: Lowercase, var: Loc<Variable>, ability: Symbol) {
         self.debug_assert_not_already_present(var.value);
 
@@ -189,6 +837,11 @@ impl IntroducedVariables {
         self.inferred.push(var);
     }
 
+    pub fn insert_deferred(&mut self, name: Ident, var: Loc<Variable>) {
+        self.insert_inferred(var);
+        self.deferred_names.insert(name, var.value);
+    }
+
     pub fn insert_lambda_set(&mut self, var: Variable) {
         self.debug_assert_not_already_present(var);
         self.lambda_sets.push(var);
@@ -208,6 +861,9 @@ impl IntroducedVariables {
 
         self.named.extend(other.named.iter().cloned());
         self.able.extend(other.able.iter().cloned());
+        self.inferred_limit_reached |= other.inferred_limit_reached;
+        self.deferred_names
+            .extend((other.deferred_names.iter()).map(|(name, var)| (name.clone(), *var)));
     }
 
     pub fn union_owned(&mut self, other: Self) {
@@ -218,6 +874,8 @@ impl IntroducedVariables {
 
         self.named.extend(other.named);
         self.able.extend(other.able.iter().cloned());
+        self.inferred_limit_reached |= other.inferred_limit_reached;
+        self.deferred_names.extend(other.deferred_names);
     }
 
     pub fn var_by_name(&self, name: &Lowercase) -> Option<Variable> {
@@ -236,12 +894,35 @@ impl IntroducedVariables {
         self.iter_named().find(|v| v.name() == name)
     }
 
+    /// The names of the variables in scope at `cursor`, like an editor would suggest when
+    /// completing a type variable there: those written before it, and those bound to abilities,
+    /// since a `has` clause binds its variable throughout the annotation. Sorted, without
+    /// duplicates.
+    pub fn named_in_scope_at(&self, cursor: Region) -> Vec<&Lowercase> {
+        let mut names: Vec<_> = (self.named.iter())
+            .filter(|nv| nv.first_seen.end() <= cursor.start())
+            .map(|nv| &nv.name)
+            .chain(self.able.iter().map(|av| &av.name))
+            .collect();
+        names.sort();
+        names.dedup();
+
+        names
+    }
+
+    /// Each variable bound by a `has` clause, and the abilities it must implement.
+    pub fn ability_constraints(&self) -> impl Iterator<Item = (&Lowercase, &[Symbol])> {
+        (self.able.iter()).map(|av| (&av.name, std::slice::from_ref(&av.ability)))
+    }
+
     pub fn collect_able(&self) -> Vec<Variable> {
         self.able.iter().map(|av| av.variable).collect()
     }
 
     pub fn collect_rigid(&self) -> Vec<Variable> {
-        (self.named.iter().map(|nv| nv.variable))
+        (self.named.iter())
+            .filter(|nv| nv.rigidity == Rigidity::Rigid)
+            .map(|nv| nv.variable)
             .chain(self.wildcards.iter().map(|wc| wc.value))
             // For our purposes, lambda set vars are treated like rigids
             .chain(self.lambda_sets.iter().copied())
@@ -249,7 +930,49 @@ impl IntroducedVariables {
     }
 
     pub fn collect_flex(&self) -> Vec<Variable> {
-        self.inferred.iter().map(|iv| iv.value).collect()
+        (self.inferred.iter().map(|iv| iv.value))
+            .chain(
+                (self.named.iter())
+                    .filter(|nv| nv.rigidity == Rigidity::Flex)
+                    .map(|nv| nv.variable),
+            )
+            .collect()
+    }
+
+    /// Returns a copy with every variable renamed consistently with `renaming`, which is usually
+    /// filled in by [`Type::rename_all_variables`] on the annotation's type first. Variables that
+    /// were not renamed yet get new ones from `var_store`.
+    pub fn rename_all_variables(
+        &self,
+        renaming: &mut MutMap<Variable, Variable>,
+        var_store: &mut VarStore,
+    ) -> Self {
+        let mut rename = |var: Variable| rename_variable(var, renaming, var_store);
+        let mut renamed = self.clone();
+
+        for wildcard in renamed.wildcards.iter_mut() {
+            wildcard.value = rename(wildcard.value);
+        }
+        for lambda_set in renamed.lambda_sets.iter_mut() {
+            *lambda_set = rename(*lambda_set);
+        }
+        for inferred in renamed.inferred.iter_mut() {
+            inferred.value = rename(inferred.value);
+        }
+        for nv in renamed.named.iter_mut() {
+            nv.variable = rename(nv.variable);
+        }
+        for av in renamed.able.iter_mut() {
+            av.variable = rename(av.variable);
+        }
+        for (_, var) in renamed.host_exposed_aliases.iter_mut() {
+            *var = rename(*var);
+        }
+        for (_, var) in renamed.deferred_names.iter_mut() {
+            *var = rename(*var);
+        }
+
+        renamed
     }
 }
 
@@ -257,7 +980,11 @@ fn malformed(env: &mut Env, region: Region, name: &str) {
     use roc_problem::can::RuntimeError::*;
 
     let problem = MalformedTypeName((*name).into(), region);
-    env.problem(roc_problem::can::Problem::RuntimeError(problem));
+    annotation_problem(
+        env,
+        region,
+        roc_problem::can::Problem::RuntimeError(problem),
+    );
 }
 
 /// Canonicalizes a top-level type annotation.
@@ -268,6 +995,187 @@ pub fn canonicalize_annotation(
     region: Region,
     var_store: &mut VarStore,
     pending_abilities_in_scope: &PendingAbilitiesInScope,
+) -> Annotation {
+    let annotation = canonicalize_type_def_body(
+        env,
+        scope,
+        annotation,
+        region,
+        var_store,
+        pending_abilities_in_scope,
+    );
+
+    report_uninhabited(env, scope, &annotation.typ, region);
+
+    annotation
+}
+
+/// Canonicalizes the body of an alias or opaque definition. Unlike [`canonicalize_annotation`],
+/// this doesn't report an uninhabited type, since definitions like `Never : []` and
+/// `Marker := []` are how the empty type and phantom markers are written.
+pub fn canonicalize_type_def_body(
+    env: &mut Env,
+    scope: &mut Scope,
+    annotation: &TypeAnnotation,
+    region: Region,
+    var_store: &mut VarStore,
+    pending_abilities_in_scope: &PendingAbilitiesInScope,
+) -> Annotation {
+    if introduces_aliases(annotation) {
+        if env.local_as_aliases {
+            // Everything the annotation introduces goes out of scope again once we're done.
+            scope.inner_scope(|inner_scope| {
+                canonicalize_annotation_help(
+                    env,
+                    inner_scope,
+                    annotation,
+                    region,
+                    var_store,
+                    pending_abilities_in_scope,
+                )
+            })
+        } else {
+            canonicalize_annotation_help(
+                env,
+                scope,
+                annotation,
+                region,
+                var_store,
+                pending_abilities_in_scope,
+            )
+        }
+    } else {
+        canonicalize_annotation_help(
+            env,
+            &mut ReadOnlyScope(scope),
+            annotation,
+            region,
+            var_store,
+            pending_abilities_in_scope,
+        )
+    }
+}
+
+/// Like [`canonicalize_annotation`], but fails if any part of the annotation is erroneous,
+/// rather than leaving [`Type::Erroneous`] somewhere within the type. The error holds the problems
+/// canonicalizing the annotation reported, which are taken out of `env`. It may be empty, since
+/// some problems, like applying an alias to the wrong number of arguments, are only reported
+/// when the type is solved.
+pub fn canonicalize_annotation_checked(
+    env: &mut Env,
+    scope: &mut Scope,
+    annotation: &TypeAnnotation,
+    region: Region,
+    var_store: &mut VarStore,
+    pending_abilities_in_scope: &PendingAbilitiesInScope,
+) -> Result<Annotation, Vec<roc_problem::can::Problem>> {
+    let problems_before = env.problems.len();
+
+    let annotation = canonicalize_annotation(
+        env,
+        scope,
+        annotation,
+        region,
+        var_store,
+        pending_abilities_in_scope,
+    );
+
+    if annotation.typ.contains_erroneous() {
+        Err(env.problems.split_off(problems_before))
+    } else {
+        Ok(annotation)
+    }
+}
+
+/// Canonicalizes a top-level type annotation that introduces no aliases (see
+/// [`introduces_aliases`]), which only needs to read the scope. This lets many such annotations
+/// share one scope.
+pub fn canonicalize_annotation_read_only(
+    env: &mut Env,
+    scope: &Scope,
+    annotation: &TypeAnnotation,
+    region: Region,
+    var_store: &mut VarStore,
+    pending_abilities_in_scope: &PendingAbilitiesInScope,
+) -> Annotation {
+    debug_assert!(!introduces_aliases(annotation));
+
+    let annotation = canonicalize_annotation_help(
+        env,
+        &mut ReadOnlyScope(scope),
+        annotation,
+        region,
+        var_store,
+        pending_abilities_in_scope,
+    );
+
+    report_uninhabited(env, scope, &annotation.typ, region);
+
+    annotation
+}
+
+/// Whether canonicalizing this annotation introduces aliases into scope, which is the case
+/// exactly when it contains an `as`.
+pub fn introduces_aliases(annotation: &TypeAnnotation) -> bool {
+    struct FindAs(bool);
+
+    impl TypeAnnotationVisitor for FindAs {
+        fn visit_type_annotation(&mut self, annotation: &TypeAnnotation, region: Region) {
+            // No need to look any further once we've found one.
+            if !self.0 {
+                walk_type_annotation(self, annotation, region);
+            }
+        }
+
+        fn visit_as(&mut self, _header: &TypeHeader, _region: Region) {
+            self.0 = true;
+        }
+    }
+
+    let mut visitor = FindAs(false);
+    visitor.visit_type_annotation(annotation, Region::zero());
+
+    visitor.0
+}
+
+/// The parts of a [`Scope`] that canonicalizing annotations needs. Only `as` annotations modify
+/// the scope; everything else just reads it.
+trait AnnotationScope {
+    fn scope(&self) -> &Scope;
+
+    /// `None` if the scope is read-only.
+    fn scope_mut(&mut self) -> Option<&mut Scope>;
+}
+
+impl AnnotationScope for Scope {
+    fn scope(&self) -> &Scope {
+        self
+    }
+
+    fn scope_mut(&mut self) -> Option<&mut Scope> {
+        Some(self)
+    }
+}
+
+struct ReadOnlyScope<'a>(&'a Scope);
+
+impl AnnotationScope for ReadOnlyScope<'_> {
+    fn scope(&self) -> &Scope {
+        self.0
+    }
+
+    fn scope_mut(&mut self) -> Option<&mut Scope> {
+        None
+    }
+}
+
+fn canonicalize_annotation_help(
+    env: &mut Env,
+    scope: &mut impl AnnotationScope,
+    annotation: &TypeAnnotation,
+    region: Region,
+    var_store: &mut VarStore,
+    pending_abilities_in_scope: &PendingAbilitiesInScope,
 ) -> Annotation {
     let mut introduced_variables = IntroducedVariables::default();
     let mut references = VecSet::default();
@@ -280,7 +1188,7 @@ pub fn canonicalize_annotation(
             for clause in clauses.iter() {
                 let opt_err = canonicalize_has_clause(
                     env,
-                    scope,
+                    scope.scope(),
                     var_store,
                     &mut introduced_variables,
                     clause,
@@ -293,6 +1201,7 @@ pub fn canonicalize_annotation(
                         introduced_variables,
                         references,
                         aliases,
+                        phantom_variables: VecMap::default(),
                     };
                 }
             }
@@ -312,152 +1221,539 @@ pub fn canonicalize_annotation(
         &mut references,
     );
 
+    if !matches!(typ, Type::Erroneous(_)) {
+        let used = typ.variables();
+        for able in introduced_variables.able.iter() {
+            // Ability member signatures report this as `AbilityMemberMissingSelf` instead.
+            if !pending_abilities_in_scope.contains_key(&able.ability)
+                && !used.contains(&able.variable)
+            {
+                annotation_problem(
+                    env,
+                    able.first_seen,
+                    roc_problem::can::Problem::UnusedHasClauseVariable {
+                        name: able.name.clone(),
+                        region: able.first_seen,
+                    },
+                );
+            }
+        }
+    }
+
+    let phantom_variables = phantom_variables(scope.scope(), &references);
+
+    #[cfg(debug_assertions)]
+    introduced_variables.assert_consistent();
+
     Annotation {
         typ,
         introduced_variables,
         references,
         aliases,
+        phantom_variables,
+    }
+}
+
+/// Reports `typ` if no value has it, since nothing could then be annotated with it.
+fn report_uninhabited(env: &mut Env, scope: &Scope, typ: &Type, region: Region) {
+    if is_uninhabited_with_scope(scope, typ) {
+        annotation_problem(
+            env,
+            region,
+            roc_problem::can::Problem::UninhabitedType { region },
+        );
+    }
+}
+
+/// Like [`Type::is_uninhabited`], but also expands the alias the type is written as, if any.
+fn is_uninhabited_with_scope(scope: &Scope, typ: &Type) -> bool {
+    match typ {
+        Type::DelayedAlias(AliasCommon { symbol, .. }) => {
+            scope.lookup_alias(*symbol).map_or(false, |alias| {
+                alias.kind != AliasKind::Opaque && is_uninhabited_with_scope(scope, &alias.typ)
+            })
+        }
+        _ => typ.is_uninhabited(),
+    }
+}
+
+/// Finds the phantom type variables of the aliases and opaques in `references`.
+fn phantom_variables(scope: &Scope, references: &VecSet<Symbol>) -> VecMap<Symbol, Vec<Lowercase>> {
+    let mut phantom_variables = VecMap::default();
+
+    for symbol in references.iter() {
+        if let Some(alias) = scope.lookup_alias(*symbol) {
+            let phantoms: Vec<_> = (alias.phantom_variables())
+                .map(|loc_var| loc_var.value.name.clone())
+                .collect();
+
+            if !phantoms.is_empty() {
+                phantom_variables.insert(*symbol, phantoms);
+            }
+        }
+    }
+
+    phantom_variables
+}
+
+/// Like [`canonicalize_annotation`], but inferred variables in the same position as a named
+/// variable of `expected`, such as the signature a definition is checked against, are given that
+/// name for use in error messages. So with `expected` being `a -> List a`, the `_` in `_ -> List
+/// Str` is called `a`. Returns the annotation along with the names given out; other variables
+/// are left unnamed.
+pub fn canonicalize_annotation_with_expected(
+    env: &mut Env,
+    scope: &mut Scope,
+    annotation: &TypeAnnotation,
+    region: Region,
+    var_store: &mut VarStore,
+    pending_abilities_in_scope: &PendingAbilitiesInScope,
+    expected: Option<&Annotation>,
+) -> (Annotation, VecMap<Variable, Lowercase>) {
+    let annotation = canonicalize_annotation(
+        env,
+        scope,
+        annotation,
+        region,
+        var_store,
+        pending_abilities_in_scope,
+    );
+
+    let mut names = VecMap::default();
+
+    if let Some(expected) = expected {
+        let expected_names: MutMap<Variable, Lowercase> =
+            (expected.introduced_variables.iter_named())
+                .map(|v| (v.variable(), v.name().clone()))
+                .collect();
+        let introduced_variables = &annotation.introduced_variables;
+        let mut taken: MutSet<Lowercase> = (introduced_variables.iter_named())
+            .map(|v| v.name().clone())
+            .collect();
+
+        inherit_expected_names(
+            &annotation.typ,
+            &expected.typ,
+            &expected_names,
+            &mut taken,
+            &introduced_variables.inferred,
+            &mut names,
+        );
+    }
+
+    (annotation, names)
+}
+
+/// Walks `typ` and `expected` together, naming the inferred variables of `typ` after the named
+/// variables of `expected` in the same position, and adding them to `names`. Names in `taken`
+/// are not given out again.
+fn inherit_expected_names(
+    typ: &Type,
+    expected: &Type,
+    expected_names: &MutMap<Variable, Lowercase>,
+    taken: &mut MutSet<Lowercase>,
+    inferred: &[Loc<Variable>],
+    names: &mut VecMap<Variable, Lowercase>,
+) {
+    let mut go = |typ, expected| {
+        inherit_expected_names(typ, expected, expected_names, taken, inferred, names)
+    };
+
+    match (typ, expected) {
+        (Type::Variable(var), Type::Variable(expected_var)) => {
+            let is_inferred = inferred.iter().any(|v| v.value == *var);
+            if let Some(name) = expected_names.get(expected_var) {
+                if is_inferred && taken.insert(name.clone()) {
+                    names.insert(*var, name.clone());
+                }
+            }
+        }
+        (Type::Function(args, _, ret), Type::Function(expected_args, _, expected_ret))
+            if args.len() == expected_args.len() =>
+        {
+            for (arg, expected_arg) in args.iter().zip(expected_args) {
+                go(arg, expected_arg);
+            }
+            go(ret, expected_ret);
+        }
+        (Type::Apply(symbol, args, _), Type::Apply(expected_symbol, expected_args, _))
+            if symbol == expected_symbol && args.len() == expected_args.len() =>
+        {
+            for (arg, expected_arg) in args.iter().zip(expected_args) {
+                go(arg, expected_arg);
+            }
+        }
+        (Type::DelayedAlias(common), Type::DelayedAlias(expected_common))
+            if common.symbol == expected_common.symbol =>
+        {
+            let args = common.type_arguments.iter();
+            for (arg, expected_arg) in args.zip(&expected_common.type_arguments) {
+                go(arg, expected_arg);
+            }
+        }
+        (
+            Type::Alias {
+                symbol,
+                type_arguments,
+                actual,
+                ..
+            },
+            Type::Alias {
+                symbol: expected_symbol,
+                type_arguments: expected_type_arguments,
+                actual: expected_actual,
+                ..
+            },
+        ) if symbol == expected_symbol => {
+            for (arg, expected_arg) in type_arguments.iter().zip(expected_type_arguments) {
+                go(&arg.typ, &expected_arg.typ);
+            }
+            go(actual, expected_actual);
+        }
+        (Type::Record(fields, ext), Type::Record(expected_fields, expected_ext)) => {
+            for (label, field) in fields.iter() {
+                if let Some(expected_field) = expected_fields.get(label) {
+                    go(field.as_inner(), expected_field.as_inner());
+                }
+            }
+            if let (TypeExtension::Open(ext), TypeExtension::Open(expected_ext)) =
+                (ext, expected_ext)
+            {
+                go(ext, expected_ext);
+            }
+        }
+        (Type::TagUnion(tags, ext), Type::TagUnion(expected_tags, expected_ext)) => {
+            for (tag_name, payload) in tags.iter() {
+                let expected_payload = (expected_tags.iter())
+                    .find(|(expected_name, _)| expected_name == tag_name)
+                    .map(|(_, expected_payload)| expected_payload);
+                if let Some(expected_payload) = expected_payload {
+                    for (arg, expected_arg) in payload.iter().zip(expected_payload) {
+                        go(arg, expected_arg);
+                    }
+                }
+            }
+            if let (TypeExtension::Open(ext), TypeExtension::Open(expected_ext)) =
+                (ext, expected_ext)
+            {
+                go(ext, expected_ext);
+            }
+        }
+        _ => {}
     }
 }
 
+/// The type of the field `field` of the record type `record`, like `Str` for `{ name : Str }`
+/// and `name`, as a `.name` projection would need. Reports
+/// [`roc_problem::can::Problem::FieldNotInRecord`] at `region` if the record is not known to have
+/// that field.
+pub fn project_record_field(
+    env: &mut Env,
+    record: &Type,
+    field: &Lowercase,
+    region: Region,
+) -> Type {
+    match record {
+        Type::Record(fields, ext) => match fields.get(field) {
+            Some(field_type) => field_type.as_inner().clone(),
+            // Extensions can be records themselves, like `{ a : Str }{ b : Str }`.
+            None => match ext {
+                TypeExtension::Open(ext) => project_record_field(env, ext, field, region),
+                TypeExtension::Closed => field_not_in_record(env, field, region),
+            },
+        },
+        Type::Alias { actual, .. } => project_record_field(env, actual, field, region),
+        // Whatever was wrong with the record was reported already.
+        Type::Erroneous(problem) => Type::Erroneous(problem.clone()),
+        _ => field_not_in_record(env, field, region),
+    }
+}
+
+fn field_not_in_record(env: &mut Env, field: &Lowercase, region: Region) -> Type {
+    annotation_problem(
+        env,
+        region,
+        roc_problem::can::Problem::FieldNotInRecord {
+            field: field.clone(),
+            region,
+        },
+    );
+
+    Type::Erroneous(Problem::CanonicalizationProblem)
+}
+
 pub(crate) fn make_apply_symbol(
     env: &mut Env,
     region: Region,
-    scope: &mut Scope,
+    scope: &Scope,
     module_name: &str,
     ident: &str,
 ) -> Result<Symbol, Type> {
-    if module_name.is_empty() {
-        // Since module_name was empty, this is an unqualified type.
-        // Look it up in scope!
+    let is_home_module = !module_name.is_empty()
+        && env.module_ids.get_id(&ModuleName::from(module_name)) == Some(env.home);
+
+    let symbol = if module_name.is_empty() || is_home_module {
+        // This is an unqualified type, or one qualified with the name of this very module, like
+        // `MyModule.Foo` within `MyModule`. Either way, look it up in scope!
 
         match scope.lookup_str(ident, region) {
-            Ok(symbol) => Ok(symbol),
+            Ok(symbol) => symbol,
             Err(problem) => {
-                env.problem(roc_problem::can::Problem::RuntimeError(problem));
+                annotation_problem(
+                    env,
+                    region,
+                    roc_problem::can::Problem::RuntimeError(problem),
+                );
 
                 let ident: Ident = (*ident).into();
-                Err(Type::Erroneous(Problem::UnrecognizedIdent(ident)))
+                return Err(Type::Erroneous(Problem::UnrecognizedIdent(Loc::at(
+                    region, ident,
+                ))));
             }
         }
     } else {
         match env.qualified_lookup(scope, module_name, ident, region) {
-            Ok(symbol) => Ok(symbol),
+            Ok(symbol) => symbol,
             Err(problem) => {
                 // Either the module wasn't imported, or
                 // it was imported but it doesn't expose this ident.
-                env.problem(roc_problem::can::Problem::RuntimeError(problem));
+                annotation_problem(
+                    env,
+                    region,
+                    roc_problem::can::Problem::RuntimeError(problem),
+                );
 
                 // A failed import should have already been reported through
                 // roc_can::env::Env::qualified_lookup's checks
-                Err(Type::Erroneous(Problem::SolvedTypeError))
+                return Err(Type::Erroneous(Problem::SolvedTypeError));
             }
         }
+    };
+
+    if let Some(allowed) = env.allowed_builtin_types {
+        if symbol.is_builtin() && !allowed.contains(&symbol) {
+            annotation_problem(
+                env,
+                region,
+                roc_problem::can::Problem::TypeNotAllowedInContext { symbol, region },
+            );
+
+            return Err(Type::Erroneous(Problem::CanonicalizationProblem));
+        }
+    }
+
+    match env.symbol_policy {
+        Some(allows) if !allows(symbol) => {
+            annotation_problem(
+                env,
+                region,
+                roc_problem::can::Problem::DisallowedTypeReference { symbol, region },
+            );
+
+            Err(Type::Erroneous(Problem::CanonicalizationProblem))
+        }
+        _ => Ok(symbol),
+    }
+}
+
+/// Looks up the ability named in a `has` clause.
+///
+/// Abilities defined in this module are found in scope, even when they are defined after the
+/// clause, and whether or not their name is qualified with this module's name. Abilities from
+/// other modules are looked up through their module.
+fn make_ability_symbol(
+    env: &mut Env,
+    region: Region,
+    scope: &Scope,
+    pending_abilities_in_scope: &PendingAbilitiesInScope,
+    module_name: &str,
+    ident: &str,
+) -> Result<Symbol, Type> {
+    let symbol = make_apply_symbol(env, region, scope, module_name, ident)?;
+
+    // Ability defined locally, whose members we are constructing right now...
+    if !pending_abilities_in_scope.contains_key(&symbol)
+        // or an ability that was imported from elsewhere
+        && !scope.abilities_store.is_ability(symbol)
+    {
+        annotation_problem(
+            env,
+            region,
+            roc_problem::can::Problem::HasClauseIsNotAbility { region },
+        );
+        return Err(Type::Erroneous(Problem::HasClauseIsNotAbility(region)));
+    }
+
+    Ok(symbol)
+}
+
+/// Reports a problem found while canonicalizing an annotation, at `region`. The same annotation
+/// may be canonicalized more than once, like an alias at each of its use sites, so a problem that
+/// was already reported at that region is not reported again.
+fn annotation_problem(env: &mut Env, region: Region, problem: roc_problem::can::Problem) {
+    let reported = env.reported_annotation_problems.entry(region).or_default();
+
+    if !reported.contains(&problem) {
+        reported.push(problem.clone());
+        env.problem(problem);
     }
 }
 
 /// Retrieves all symbols in an annotations that reference a type definition, that is either an
-/// alias or an opaque type.
+/// alias, an opaque type, or an ability named in a `has` clause.
 ///
 /// For example, in `[A Age U8, B Str {}]`, there are three type definition references - `Age`,
-/// `U8`, and `Str`.
+/// `U8`, and `Str`. In `a -> U64 | a has Hash`, they are `U64` and `Hash`.
 pub fn find_type_def_symbols(
     scope: &mut Scope,
     initial_annotation: &roc_parse::ast::TypeAnnotation,
 ) -> Vec<Symbol> {
-    use roc_parse::ast::TypeAnnotation::*;
+    struct FindTypeDefSymbols<'a> {
+        scope: &'a mut Scope,
+        result: Vec<Symbol>,
+    }
+
+    impl TypeAnnotationVisitor for FindTypeDefSymbols<'_> {
+        fn visit_apply(&mut self, _module_name: &str, ident: &str, _region: Region) {
+            let ident: Ident = ident.into();
+            let symbol = self.scope.scopeless_symbol(&ident, Region::zero());
+
+            self.result.push(symbol);
+        }
+    }
 
-    let mut result = Vec::new();
+    let mut visitor = FindTypeDefSymbols {
+        scope,
+        result: Vec::new(),
+    };
+    visitor.visit_type_annotation(initial_annotation, Region::zero());
 
-    let mut stack = vec![initial_annotation];
+    visitor.result
+}
 
-    while let Some(annotation) = stack.pop() {
-        match annotation {
-            Apply(_module_name, ident, arguments) => {
-                let ident: Ident = (*ident).into();
-                let symbol = scope.scopeless_symbol(&ident, Region::zero());
+/// A visitor over a parsed [`TypeAnnotation`], driven by [`walk_type_annotation`].
+///
+/// The hooks for nodes with children default to walking those children; override them and call
+/// the matching `walk_*` function to keep descending. The hooks for the remaining node kinds
+/// are called before the node's children, if any, are walked.
+pub trait TypeAnnotationVisitor: Sized {
+    fn visit_type_annotation(&mut self, annotation: &TypeAnnotation, region: Region) {
+        walk_type_annotation(self, annotation, region);
+    }
 
-                result.push(symbol);
+    fn visit_record_field(&mut self, field: &AssignedField<TypeAnnotation>, region: Region) {
+        walk_record_field(self, field, region);
+    }
 
-                for t in arguments.iter() {
-                    stack.push(&t.value);
-                }
+    fn visit_tag(&mut self, tag: &Tag, region: Region) {
+        walk_tag(self, tag, region);
+    }
+
+    fn visit_has_clause(&mut self, clause: &HasClause, _region: Region) {
+        let ability = &clause.ability;
+        self.visit_type_annotation(&ability.value, ability.region);
+    }
+
+    fn visit_apply(&mut self, _module_name: &str, _ident: &str, _region: Region) {}
+
+    fn visit_bound_variable(&mut self, _name: &str, _region: Region) {}
+
+    fn visit_as(&mut self, _header: &TypeHeader, _region: Region) {}
+
+    fn visit_inferred(&mut self, _region: Region) {}
+
+    fn visit_wildcard(&mut self, _region: Region) {}
+
+    fn visit_malformed(&mut self, _malformed: &str, _region: Region) {}
+}
+
+pub fn walk_type_annotation<V: TypeAnnotationVisitor>(
+    visitor: &mut V,
+    annotation: &TypeAnnotation,
+    region: Region,
+) {
+    use roc_parse::ast::TypeAnnotation::*;
+
+    let visit_all = |visitor: &mut V, annotations: &[Loc<TypeAnnotation>]| {
+        for annotation in annotations {
+            visitor.visit_type_annotation(&annotation.value, annotation.region);
+        }
+    };
+
+    match annotation {
+        Function(arguments, result) => {
+            visit_all(visitor, arguments);
+            visitor.visit_type_annotation(&result.value, result.region);
+        }
+        Apply(module_name, ident, arguments) => {
+            visitor.visit_apply(module_name, ident, region);
+            visit_all(visitor, arguments);
+        }
+        BoundVariable(name) => visitor.visit_bound_variable(name, region),
+        As(actual, _, header) => {
+            visitor.visit_as(header, region);
+            visitor.visit_type_annotation(&actual.value, actual.region);
+        }
+        Record { fields, ext } => {
+            for field in fields.iter() {
+                visitor.visit_record_field(&field.value, field.region);
             }
-            Function(arguments, result) => {
-                for t in arguments.iter() {
-                    stack.push(&t.value);
-                }
 
-                stack.push(&result.value);
+            if let Some(ext) = ext {
+                visitor.visit_type_annotation(&ext.value, ext.region);
             }
-            BoundVariable(_) => {}
-            As(actual, _, _) => {
-                stack.push(&actual.value);
+        }
+        TagUnion { ext, tags } => {
+            for tag in tags.iter() {
+                visitor.visit_tag(&tag.value, tag.region);
             }
-            Record { fields, ext } => {
-                let mut inner_stack = Vec::with_capacity(fields.items.len());
-
-                for field in fields.items.iter() {
-                    inner_stack.push(&field.value)
-                }
-
-                while let Some(assigned_field) = inner_stack.pop() {
-                    match assigned_field {
-                        AssignedField::RequiredValue(_, _, t)
-                        | AssignedField::OptionalValue(_, _, t) => {
-                            stack.push(&t.value);
-                        }
-                        AssignedField::LabelOnly(_) => {}
-                        AssignedField::SpaceBefore(inner, _)
-                        | AssignedField::SpaceAfter(inner, _) => inner_stack.push(inner),
-                        AssignedField::Malformed(_) => {}
-                    }
-                }
 
-                for t in ext.iter() {
-                    stack.push(&t.value);
-                }
+            if let Some(ext) = ext {
+                visitor.visit_type_annotation(&ext.value, ext.region);
             }
-            TagUnion { ext, tags } => {
-                let mut inner_stack = Vec::with_capacity(tags.items.len());
-
-                for tag in tags.items.iter() {
-                    inner_stack.push(&tag.value)
-                }
-
-                while let Some(tag) = inner_stack.pop() {
-                    match tag {
-                        Tag::Apply { args, .. } => {
-                            for t in args.iter() {
-                                stack.push(&t.value);
-                            }
-                        }
-                        Tag::SpaceBefore(inner, _) | Tag::SpaceAfter(inner, _) => {
-                            inner_stack.push(inner)
-                        }
-                        Tag::Malformed(_) => {}
-                    }
-                }
+        }
+        Inferred => visitor.visit_inferred(region),
+        Wildcard => visitor.visit_wildcard(region),
+        Where(annotation, clauses) => {
+            visitor.visit_type_annotation(&annotation.value, annotation.region);
 
-                for t in ext.iter() {
-                    stack.push(&t.value);
-                }
-            }
-            SpaceBefore(inner, _) | SpaceAfter(inner, _) => {
-                stack.push(inner);
+            for clause in clauses.iter() {
+                visitor.visit_has_clause(&clause.value, clause.region);
             }
-            Where(annotation, clauses) => {
-                stack.push(&annotation.value);
+        }
+        SpaceBefore(inner, _) | SpaceAfter(inner, _) => {
+            visitor.visit_type_annotation(inner, region);
+        }
+        Malformed(malformed) => visitor.visit_malformed(malformed, region),
+    }
+}
 
-                for has_clause in clauses.iter() {
-                    stack.push(&has_clause.value.ability.value);
-                }
-            }
-            Inferred | Wildcard | Malformed(_) => {}
+pub fn walk_record_field<V: TypeAnnotationVisitor>(
+    visitor: &mut V,
+    field: &AssignedField<TypeAnnotation>,
+    region: Region,
+) {
+    match field {
+        AssignedField::RequiredValue(_, _, t) | AssignedField::OptionalValue(_, _, t) => {
+            visitor.visit_type_annotation(&t.value, t.region);
         }
+        AssignedField::SpaceBefore(inner, _) | AssignedField::SpaceAfter(inner, _) => {
+            visitor.visit_record_field(inner, region);
+        }
+        AssignedField::LabelOnly(_) | AssignedField::Malformed(_) => {}
     }
+}
 
-    result
+pub fn walk_tag<V: TypeAnnotationVisitor>(visitor: &mut V, tag: &Tag, region: Region) {
+    match tag {
+        Tag::Apply { args, .. } => {
+            for arg in args.iter() {
+                visitor.visit_type_annotation(&arg.value, arg.region);
+            }
+        }
+        Tag::SpaceBefore(inner, _) | Tag::SpaceAfter(inner, _) => visitor.visit_tag(inner, region),
+        Tag::Malformed(_) => {}
+    }
 }
 
 fn find_fresh_var_name(introduced_variables: &IntroducedVariables) -> Lowercase {
@@ -472,7 +1768,7 @@ fn can_annotation_help(
     env: &mut Env,
     annotation: &roc_parse::ast::TypeAnnotation,
     region: Region,
-    scope: &mut Scope,
+    scope: &mut impl AnnotationScope,
     var_store: &mut VarStore,
     introduced_variables: &mut IntroducedVariables,
     local_aliases: &mut VecMap<Symbol, Alias>,
@@ -485,6 +1781,19 @@ fn can_annotation_help(
             let mut args = Vec::new();
 
             for arg in *argument_types {
+                if env.suggest_open_extensions {
+                    if let Some(kind) = closed_extension_kind(&arg.value) {
+                        annotation_problem(
+                            env,
+                            arg.region,
+                            roc_problem::can::Problem::ClosedArgumentExtension {
+                                region: arg.region,
+                                kind,
+                            },
+                        );
+                    }
+                }
+
                 let arg_ann = can_annotation_help(
                     env,
                     &arg.value,
@@ -517,7 +1826,40 @@ fn can_annotation_help(
             Type::Function(args, Box::new(closure), Box::new(ret))
         }
         Apply(module_name, ident, type_arguments) => {
-            let symbol = match make_apply_symbol(env, region, scope, module_name, ident) {
+            if env.defer_unrecognized_types
+                && module_name.is_empty()
+                && scope.scope().lookup_str(ident, region).is_err()
+            {
+                // Stand in for the type until it is written, whatever arguments it is given. The
+                // arguments are still canonicalized, so that the types they refer to are used,
+                // and unknown types among them deferred too.
+                for arg in *type_arguments {
+                    can_annotation_help(
+                        env,
+                        &arg.value,
+                        arg.region,
+                        scope,
+                        var_store,
+                        introduced_variables,
+                        local_aliases,
+                        references,
+                    );
+                }
+
+                let name = Ident::from(*ident);
+                let var = match introduced_variables.deferred_names.get(&name) {
+                    Some(var) => *var,
+                    None => {
+                        let var = var_store.fresh();
+                        introduced_variables.insert_deferred(name, Loc::at(region, var));
+                        var
+                    }
+                };
+
+                return Type::Variable(var);
+            }
+
+            let symbol = match make_apply_symbol(env, region, scope.scope(), module_name, ident) {
                 Err(problem) => return problem,
                 Ok(symbol) => symbol,
             };
@@ -526,14 +1868,21 @@ fn can_annotation_help(
 
             references.insert(symbol);
 
-            if scope.abilities_store.is_ability(symbol) {
+            // Builtin abilities may be referred to before their modules' abilities are registered.
+            if scope.scope().abilities_store.is_ability(symbol)
+                || (symbol.is_builtin() && symbol.is_derivable_ability())
+            {
                 let fresh_ty_var = find_fresh_var_name(introduced_variables);
 
-                env.problem(roc_problem::can::Problem::AbilityUsedAsType(
-                    fresh_ty_var.clone(),
-                    symbol,
+                annotation_problem(
+                    env,
                     region,
-                ));
+                    roc_problem::can::Problem::AbilityUsedAsType(
+                        fresh_ty_var.clone(),
+                        symbol,
+                        region,
+                    ),
+                );
 
                 // Generate an variable bound to the ability so we can keep compiling.
                 let var = var_store.fresh();
@@ -556,21 +1905,79 @@ fn can_annotation_help(
                 args.push(arg_ann);
             }
 
-            match scope.lookup_alias(symbol) {
+            match scope.scope().lookup_alias(symbol) {
                 Some(alias) => {
                     // use a known alias
 
-                    if alias.type_variables.len() != args.len() {
+                    if let Some(reexported) = env.reexported_opaques {
+                        if alias.kind == AliasKind::Opaque
+                            && symbol.module_id() != env.home
+                            && !symbol.is_builtin()
+                            && !reexported.contains(&symbol)
+                        {
+                            annotation_problem(
+                                env,
+                                region,
+                                roc_problem::can::Problem::OpaqueNotExported {
+                                    opaque: symbol,
+                                    region,
+                                },
+                            );
+
+                            return Type::Erroneous(Problem::CanonicalizationProblem);
+                        }
+                    }
+
+                    if alias.type_variables.len() != args.len()
+                        && !alias.fill_default_type_arguments(&mut args)
+                        && !(env.infer_missing_type_arguments
+                            && infer_missing_type_arguments(
+                                env,
+                                var_store,
+                                introduced_variables,
+                                alias.type_variables.len(),
+                                &mut args,
+                                region,
+                            ))
+                    {
                         let error = Type::Erroneous(Problem::BadTypeArguments {
                             symbol,
                             region,
-                            alias_needs: alias.type_variables.len() as u8,
-                            type_got: args.len() as u8,
+                            alias_needs: alias.type_variables.len(),
+                            type_got: args.len(),
                             alias_kind: alias.kind,
                         });
                         return error;
                     }
 
+                    if env.expand_aliases_eagerly {
+                        let (type_arguments, lambda_set_variables, actual) =
+                            instantiate_and_freshen_alias_type(
+                                var_store,
+                                introduced_variables,
+                                &alias.type_variables,
+                                args,
+                                &alias.lambda_set_variables,
+                                alias.typ.clone(),
+                            );
+
+                        let type_arguments = (alias.type_variables.iter())
+                            .zip(type_arguments)
+                            .map(|(loc_var, (_, typ))| OptAbleType {
+                                typ,
+                                opt_ability: loc_var.value.opt_bound_ability,
+                            })
+                            .collect();
+
+                        return Type::Alias {
+                            symbol,
+                            type_arguments,
+                            lambda_set_variables,
+                            actual: Box::new(actual),
+                            kind: alias.kind,
+                        };
+                    }
+
                     let mut type_var_to_arg = Vec::new();
 
                     for (_, arg_ann) in alias.type_variables.iter().zip(args) {
@@ -594,18 +2001,33 @@ fn can_annotation_help(
                         lambda_set_variables,
                     })
                 }
+                // A type without a body, like a builtin or an abstract type a platform exposes
+                // without its structure. It is only known by its name, so it unifies nominally:
+                // with applications of the same symbol, and nothing else.
                 None => Type::Apply(symbol, args, region),
             }
         }
         BoundVariable(v) => {
             let name = Lowercase::from(*v);
 
+            // A named inferred variable, like `_a`, is inferred like `_`, but all occurrences of
+            // the same name share one variable.
+            let rigidity = if v.starts_with('_') {
+                Rigidity::Flex
+            } else {
+                Rigidity::Rigid
+            };
+
             match introduced_variables.var_by_name(&name) {
                 Some(var) => Type::Variable(var),
                 None => {
                     let var = var_store.fresh();
 
-                    introduced_variables.insert_named(name, Loc::at(region, var));
+                    introduced_variables.insert_named_with_rigidity(
+                        name,
+                        Loc::at(region, var),
+                        rigidity,
+                    );
 
                     Type::Variable(var)
                 }
@@ -619,17 +2041,40 @@ fn can_annotation_help(
                 vars: loc_vars,
             },
         ) => {
+            let scope = match scope.scope_mut() {
+                Some(scope) => scope,
+                None => internal_error!(
+                    "`as` annotations introduce aliases, and so need a mutable scope"
+                ),
+            };
+
             let symbol = match scope.introduce(name.value.into(), region) {
                 Ok(symbol) => symbol,
 
                 Err((shadowed_symbol, shadow, _new_symbol)) => {
+                    // Point at the name after `as`, rather than the whole annotation.
+                    let shadow = Loc::at(name.region, shadow.value);
                     let problem = Problem::Shadowed(shadowed_symbol.region, shadow.clone());
 
-                    env.problem(roc_problem::can::Problem::Shadowing {
-                        original_region: shadowed_symbol.region,
-                        shadow,
-                        kind: ShadowKind::Variable,
-                    });
+                    // Only types have uppercase names, so say which kind of type is shadowed.
+                    let kind = if scope.abilities_store.is_ability(shadowed_symbol.value) {
+                        ShadowKind::Ability
+                    } else {
+                        match scope.lookup_alias(shadowed_symbol.value) {
+                            Some(alias) if alias.kind == AliasKind::Opaque => ShadowKind::Opaque,
+                            _ => ShadowKind::Alias,
+                        }
+                    };
+
+                    annotation_problem(
+                        env,
+                        region,
+                        roc_problem::can::Problem::Shadowing {
+                            original_region: shadowed_symbol.region,
+                            shadow,
+                            kind,
+                        },
+                    );
 
                     return Type::Erroneous(problem);
                 }
@@ -645,6 +2090,32 @@ fn can_annotation_help(
                 local_aliases,
                 references,
             );
+
+            // The alias is defined by the whole `<body> as <header>`, so that is the region we
+            // remember it by, even if we were only handed the region of one side.
+            let alias_region =
+                Region::across_all([region, loc_inner.region, alias_header.region()].iter());
+
+            if loc_vars.is_empty() {
+                if let Type::Alias {
+                    symbol: existing, ..
+                }
+                | Type::DelayedAlias(AliasCommon {
+                    symbol: existing, ..
+                }) = inner_type
+                {
+                    annotation_problem(
+                        env,
+                        alias_region,
+                        roc_problem::can::Problem::RedundantAsAlias {
+                            alias: symbol,
+                            existing,
+                            region: alias_region,
+                        },
+                    );
+                }
+            }
+
             let mut vars = Vec::with_capacity(loc_vars.len());
             let mut lowercase_vars: Vec<Loc<AliasVar>> = Vec::with_capacity(loc_vars.len());
 
@@ -668,6 +2139,8 @@ fn can_annotation_help(
                             name: var_name,
                             var,
                             opt_bound_ability: None,
+                            opt_default: None,
+                            variance: Variance::Inferred,
                         },
                     ));
                 } else {
@@ -683,6 +2156,8 @@ fn can_annotation_help(
                             name: var_name,
                             var,
                             opt_bound_ability: None,
+                            opt_default: None,
+                            variance: Variance::Inferred,
                         },
                     ));
                 }
@@ -725,6 +2200,20 @@ fn can_annotation_help(
                 } else {
                     Type::RecursiveTagUnion(rec_var, new_tags, ext)
                 }
+            } else if inner_type.contains_symbol(symbol) {
+                // Only references in the payloads of a tag union are replaced by a recursion
+                // variable; any other reference makes the type infinite, which solving would
+                // only report much later, and far from here.
+                annotation_problem(
+                    env,
+                    alias_region,
+                    roc_problem::can::Problem::InfiniteType {
+                        alias: symbol,
+                        region: alias_region,
+                    },
+                );
+
+                Type::Erroneous(Problem::CyclicAlias(symbol, alias_region, vec![]))
             } else {
                 inner_type
             };
@@ -738,7 +2227,7 @@ fn can_annotation_help(
 
             scope.add_alias(
                 symbol,
-                region,
+                alias_region,
                 lowercase_vars,
                 alias_actual,
                 AliasKind::Structural, // aliases in "as" are never opaque
@@ -788,6 +2277,8 @@ fn can_annotation_help(
 
             if fields.is_empty() {
                 match ext {
+                    // An explicitly closed extension, like `{}{}`, is just `{}`.
+                    Some(_) if ext_type == Type::EmptyRec => Type::EmptyRec,
                     Some(_) => {
                         // just `a` does not mean the same as `{}a`, so even
                         // if there are no fields, still make this a `Record`,
@@ -809,7 +2300,13 @@ fn can_annotation_help(
                     references,
                 );
 
-                Type::Record(field_types, TypeExtension::from_type(ext_type))
+                if field_types.is_empty() {
+                    // Every field was malformed, which has been reported already. Don't let
+                    // this stand for a record without fields, which would only cause more errors.
+                    Type::Erroneous(Problem::CanonicalizationProblem)
+                } else {
+                    Type::Record(field_types, TypeExtension::from_type(ext_type))
+                }
             }
         }
         TagUnion { tags, ext, .. } => {
@@ -826,6 +2323,8 @@ fn can_annotation_help(
 
             if tags.is_empty() {
                 match ext {
+                    // An explicitly closed extension, like `[][]`, is just `[]`.
+                    Some(_) if ext_type == Type::EmptyTagUnion => Type::EmptyTagUnion,
                     Some(_) => {
                         // just `a` does not mean the same as `{}a`, so even
                         // if there are no fields, still make this a `Record`,
@@ -847,6 +2346,12 @@ fn can_annotation_help(
                     references,
                 );
 
+                if tag_types.is_empty() {
+                    // Every tag was malformed, which has been reported already. Don't let this
+                    // stand for a tag union without tags, which no value could have.
+                    return Type::Erroneous(Problem::CanonicalizationProblem);
+                }
+
                 // sort here; we later instantiate type aliases, so this type might get duplicated
                 // many times. Then, when inserting into the subs, the tags are sorted.
                 // in theory we save a lot of time by sorting once here
@@ -866,6 +2371,10 @@ fn can_annotation_help(
             references,
         ),
         Wildcard => {
+            if !check_inferred_limit(env, introduced_variables, region) {
+                return Type::Erroneous(Problem::CanonicalizationProblem);
+            }
+
             let var = var_store.fresh();
 
             introduced_variables.insert_wildcard(Loc::at(region, var));
@@ -873,6 +2382,10 @@ fn can_annotation_help(
             Type::Variable(var)
         }
         Inferred => {
+            if !check_inferred_limit(env, introduced_variables, region) {
+                return Type::Erroneous(Problem::CanonicalizationProblem);
+            }
+
             // Inference variables aren't bound to a rigid or a wildcard, so all we have to do is
             // make a fresh unconstrained variable, and let the type solver fill it in for us 🤠
             let var = var_store.fresh();
@@ -885,9 +2398,13 @@ fn can_annotation_help(
             debug_assert!(!clauses.is_empty());
 
             // Has clauses are allowed only on the top level of a signature, which we handle elsewhere.
-            env.problem(roc_problem::can::Problem::IllegalHasClause {
-                region: Region::across_all(clauses.iter().map(|clause| &clause.region)),
-            });
+            annotation_problem(
+                env,
+                region,
+                roc_problem::can::Problem::IllegalHasClause {
+                    region: Region::across_all(clauses.iter().map(|clause| &clause.region)),
+                },
+            );
 
             Type::Erroneous(Problem::CanonicalizationProblem)
         }
@@ -903,9 +2420,82 @@ fn can_annotation_help(
     }
 }
 
+/// If this is a non-empty record or tag union without an extension, like `{ name : Str }`,
+/// returns which of the two it is.
+fn closed_extension_kind(annotation: &TypeAnnotation) -> Option<ExtensionTypeKind> {
+    match annotation {
+        TypeAnnotation::Record { fields, ext: None } if !fields.is_empty() => {
+            Some(ExtensionTypeKind::Record)
+        }
+        TypeAnnotation::TagUnion { tags, ext: None } if !tags.is_empty() => {
+            Some(ExtensionTypeKind::TagUnion)
+        }
+        TypeAnnotation::SpaceBefore(nested, _) | TypeAnnotation::SpaceAfter(nested, _) => {
+            closed_extension_kind(nested)
+        }
+        _ => None,
+    }
+}
+
+/// Returns whether another `_` or `*` variable may be introduced, reporting
+/// [`roc_problem::can::Problem::TooManyInferredVariables`] the first time the limit is passed.
+fn check_inferred_limit(
+    env: &mut Env,
+    introduced_variables: &mut IntroducedVariables,
+    region: Region,
+) -> bool {
+    let limit = match env.max_inferred_variables {
+        Some(limit) => limit,
+        None => return true,
+    };
+
+    if introduced_variables.wildcards.len() + introduced_variables.inferred.len() < limit {
+        return true;
+    }
+
+    if !introduced_variables.inferred_limit_reached {
+        introduced_variables.inferred_limit_reached = true;
+        annotation_problem(
+            env,
+            region,
+            roc_problem::can::Problem::TooManyInferredVariables { limit, region },
+        );
+    }
+
+    false
+}
+
+/// Pads `args` with inferred variables up to `arity`, as if the missing ones were written `_`.
+/// Returns `false`, leaving `args` untouched, if there are too many arguments already.
+fn infer_missing_type_arguments(
+    env: &mut Env,
+    var_store: &mut VarStore,
+    introduced_variables: &mut IntroducedVariables,
+    arity: usize,
+    args: &mut Vec<Type>,
+    region: Region,
+) -> bool {
+    if args.len() > arity {
+        return false;
+    }
+
+    while args.len() < arity {
+        if !check_inferred_limit(env, introduced_variables, region) {
+            args.push(Type::Erroneous(Problem::CanonicalizationProblem));
+            continue;
+        }
+
+        let var = var_store.fresh();
+        introduced_variables.insert_inferred(Loc::at(region, var));
+        args.push(Type::Variable(var));
+    }
+
+    true
+}
+
 fn canonicalize_has_clause(
     env: &mut Env,
-    scope: &mut Scope,
+    scope: &Scope,
     var_store: &mut VarStore,
     introduced_variables: &mut IntroducedVariables,
     clause: &Loc<roc_parse::ast::HasClause<'_>>,
@@ -925,38 +2515,71 @@ fn canonicalize_has_clause(
     );
     let var_name = Lowercase::from(var_name);
 
-    let ability = match ability.value {
-        TypeAnnotation::Apply(module_name, ident, _type_arguments) => {
-            let symbol = make_apply_symbol(env, ability.region, scope, module_name, ident)?;
-
-            // Ability defined locally, whose members we are constructing right now...
-            if !pending_abilities_in_scope.contains_key(&symbol)
-                // or an ability that was imported from elsewhere
-                && !scope.abilities_store.is_ability(symbol)
-            {
-                let region = ability.region;
-                env.problem(roc_problem::can::Problem::HasClauseIsNotAbility { region });
-                return Err(Type::Erroneous(Problem::HasClauseIsNotAbility(region)));
+    // `a has Phantom` marks `a` as intentionally phantom, unless there is an actual `Phantom`
+    // ability in scope. It only introduces `a` as a named rigid, not bound to any ability; an
+    // alias definition then finds its parameter `a` among the named variables, and so does not
+    // report it as a phantom type argument.
+    if let TypeAnnotation::Apply("", "Phantom", []) = ability.value {
+        match scope.lookup_str("Phantom", ability.region) {
+            // The members of a `Phantom` ability must be bound to it, so only elsewhere is the
+            // clause ambiguous.
+            Ok(symbol) if !pending_abilities_in_scope.contains_key(&symbol) => {
+                // The clause changes meaning with what is in scope, so say which one it means.
+                annotation_problem(
+                    env,
+                    ability.region,
+                    roc_problem::can::Problem::PhantomMarkerShadowed {
+                        symbol,
+                        region: ability.region,
+                    },
+                );
+            }
+            Ok(_) => {}
+            Err(_) => {
+                if introduced_variables.var_by_name(&var_name).is_none() {
+                    introduced_variables.insert_named(var_name, Loc::at(region, var_store.fresh()));
+                }
+                return Ok(());
             }
-            symbol
         }
+    }
+
+    let ability = match ability.value {
+        TypeAnnotation::Apply(module_name, ident, _type_arguments) => make_ability_symbol(
+            env,
+            ability.region,
+            scope,
+            pending_abilities_in_scope,
+            module_name,
+            ident,
+        )?,
         _ => {
             let region = ability.region;
-            env.problem(roc_problem::can::Problem::HasClauseIsNotAbility { region });
+            annotation_problem(
+                env,
+                region,
+                roc_problem::can::Problem::HasClauseIsNotAbility { region },
+            );
             return Err(Type::Erroneous(Problem::HasClauseIsNotAbility(region)));
         }
     };
 
     references.insert(ability);
 
+    // Types carry one ability per variable, so `a has Eq, a has Hash` reports the second clause
+    // as shadowing `a`.
     if let Some(shadowing) = introduced_variables.named_var_by_name(&var_name) {
         let var_name_ident = var_name.to_string().into();
         let shadow = Loc::at(region, var_name_ident);
-        env.problem(roc_problem::can::Problem::Shadowing {
-            original_region: shadowing.first_seen(),
-            shadow: shadow.clone(),
-            kind: ShadowKind::Variable,
-        });
+        annotation_problem(
+            env,
+            region,
+            roc_problem::can::Problem::Shadowing {
+                original_region: shadowing.first_seen(),
+                shadow: shadow.clone(),
+                kind: ShadowKind::Variable,
+            },
+        );
         return Err(Type::Erroneous(Problem::Shadowed(
             shadowing.first_seen(),
             shadow,
@@ -973,7 +2596,7 @@ fn canonicalize_has_clause(
 #[allow(clippy::too_many_arguments)]
 fn can_extension_type<'a>(
     env: &mut Env,
-    scope: &mut Scope,
+    scope: &mut impl AnnotationScope,
     var_store: &mut VarStore,
     introduced_variables: &mut IntroducedVariables,
     local_aliases: &mut VecMap<Symbol, Alias>,
@@ -995,8 +2618,6 @@ fn can_extension_type<'a>(
         )
     }
 
-    use roc_problem::can::ExtensionTypeKind;
-
     let (empty_ext_type, valid_extension_type): (_, fn(&Type) -> bool) = match ext_problem_kind {
         ExtensionTypeKind::Record => (Type::EmptyRec, valid_record_ext_type),
         ExtensionTypeKind::TagUnion => (Type::EmptyTagUnion, valid_tag_ext_type),
@@ -1014,7 +2635,13 @@ fn can_extension_type<'a>(
                 local_aliases,
                 references,
             );
-            if valid_extension_type(shallow_dealias_with_scope(scope, &ext_type)) {
+            let dealiased = shallow_dealias_with_scope(scope.scope(), &ext_type);
+
+            if *dealiased == empty_ext_type {
+                // An alias of `{}` or `[]` closes the record or tag union, just like writing
+                // `{}` or `[]` directly would.
+                empty_ext_type
+            } else if valid_extension_type(dealiased) {
                 ext_type
             } else {
                 // Report an error but mark the extension variable to be inferred
@@ -1022,10 +2649,14 @@ fn can_extension_type<'a>(
                 //
                 // THEORY: invalid extension types can appear in this position. Otherwise
                 // they would be caught as errors during unification.
-                env.problem(roc_problem::can::Problem::InvalidExtensionType {
-                    region: loc_ann.region,
-                    kind: ext_problem_kind,
-                });
+                annotation_problem(
+                    env,
+                    loc_ann.region,
+                    roc_problem::can::Problem::InvalidExtensionType {
+                        region: loc_ann.region,
+                        kind: ext_problem_kind,
+                    },
+                );
 
                 let var = var_store.fresh();
 
@@ -1039,16 +2670,19 @@ fn can_extension_type<'a>(
 }
 
 /// a shallow dealias, continue until the first constructor is not an alias.
-fn shallow_dealias_with_scope<'a>(scope: &'a mut Scope, typ: &'a Type) -> &'a Type {
+///
+/// Opaque types are not unwrapped: their structure is hidden, so they cannot stand in for it.
+fn shallow_dealias_with_scope<'a>(scope: &'a Scope, typ: &'a Type) -> &'a Type {
     let mut result = typ;
     loop {
         match result {
-            Type::Alias { actual, .. } => {
+            Type::Alias { kind, actual, .. } if *kind != AliasKind::Opaque => {
                 // another loop
                 result = actual;
             }
             Type::DelayedAlias(AliasCommon { symbol, .. }) => match scope.lookup_alias(*symbol) {
                 None => unreachable!(),
+                Some(alias) if alias.kind == AliasKind::Opaque => break,
                 Some(alias) => {
                     result = &alias.typ;
                 }
@@ -1069,6 +2703,68 @@ pub fn instantiate_and_freshen_alias_type(
     lambda_set_variables: &[LambdaSet],
     mut actual_type: Type,
 ) -> (Vec<(Lowercase, Type)>, Vec<LambdaSet>, Type) {
+    let (type_var_to_arg, new_lambda_set_variables, substitutions, recursion_var) =
+        alias_substitutions(
+            var_store,
+            introduced_variables,
+            type_variables,
+            type_arguments,
+            lambda_set_variables,
+            &actual_type,
+        );
+
+    // instantiate variables
+    if let (Type::RecursiveTagUnion(rvar, _, _), Some(new)) = (&mut actual_type, recursion_var) {
+        *rvar = new;
+    }
+    actual_type.substitute(&substitutions);
+
+    (type_var_to_arg, new_lambda_set_variables, actual_type)
+}
+
+/// Like [`instantiate_and_freshen_alias_type`], but shares `body` with the other instantiations
+/// of the alias rather than copying it.
+pub fn instantiate_shared_alias_type(
+    var_store: &mut VarStore,
+    introduced_variables: &mut IntroducedVariables,
+    type_variables: &[Loc<AliasVar>],
+    type_arguments: Vec<Type>,
+    lambda_set_variables: &[LambdaSet],
+    body: &SharedAliasBody,
+) -> (Vec<(Lowercase, Type)>, Vec<LambdaSet>, InstantiatedAlias) {
+    let (type_var_to_arg, new_lambda_set_variables, substitutions, recursion_var) =
+        alias_substitutions(
+            var_store,
+            introduced_variables,
+            type_variables,
+            type_arguments,
+            lambda_set_variables,
+            body.as_type(),
+        );
+
+    (
+        type_var_to_arg,
+        new_lambda_set_variables,
+        body.instantiate(substitutions, recursion_var),
+    )
+}
+
+/// What each variable of an alias's body stands for in one instantiation of the alias, along
+/// with the fresh variable for the body if it is a recursive tag union.
+#[allow(clippy::type_complexity)]
+fn alias_substitutions(
+    var_store: &mut VarStore,
+    introduced_variables: &mut IntroducedVariables,
+    type_variables: &[Loc<AliasVar>],
+    type_arguments: Vec<Type>,
+    lambda_set_variables: &[LambdaSet],
+    actual_type: &Type,
+) -> (
+    Vec<(Lowercase, Type)>,
+    Vec<LambdaSet>,
+    ImMap<Variable, Type>,
+    Option<Variable>,
+) {
     let mut substitutions = ImMap::default();
     let mut type_var_to_arg = Vec::new();
 
@@ -1081,10 +2777,11 @@ pub fn instantiate_and_freshen_alias_type(
     }
 
     // make sure the recursion variable is freshly instantiated
-    if let Type::RecursiveTagUnion(rvar, _, _) = &mut actual_type {
+    let mut recursion_var = None;
+    if let Type::RecursiveTagUnion(rvar, _, _) = actual_type {
         let new = var_store.fresh();
         substitutions.insert(*rvar, Type::Variable(new));
-        *rvar = new;
+        recursion_var = Some(new);
     }
 
     // make sure hidden variables are freshly instantiated
@@ -1100,10 +2797,12 @@ pub fn instantiate_and_freshen_alias_type(
         }
     }
 
-    // instantiate variables
-    actual_type.substitute(&substitutions);
-
-    (type_var_to_arg, new_lambda_set_variables, actual_type)
+    (
+        type_var_to_arg,
+        new_lambda_set_variables,
+        substitutions,
+        recursion_var,
+    )
 }
 
 pub fn freshen_opaque_def(
@@ -1166,7 +2865,7 @@ fn can_assigned_fields<'a>(
     env: &mut Env,
     fields: &&[Loc<AssignedField<'a, TypeAnnotation<'a>>>],
     region: Region,
-    scope: &mut Scope,
+    scope: &mut impl AnnotationScope,
     var_store: &mut VarStore,
     introduced_variables: &mut IntroducedVariables,
     local_aliases: &mut VecMap<Symbol, Alias>,
@@ -1261,12 +2960,16 @@ fn can_assigned_fields<'a>(
         // ensure that the new name is not already in this record:
         // note that the right-most tag wins when there are two with the same name
         if let Some(replaced_region) = seen.insert(new_name.clone(), loc_field.region) {
-            env.problem(roc_problem::can::Problem::DuplicateRecordFieldType {
-                field_name: new_name,
-                record_region: region,
-                field_region: loc_field.region,
-                replaced_region,
-            });
+            annotation_problem(
+                env,
+                loc_field.region,
+                roc_problem::can::Problem::DuplicateRecordFieldType {
+                    field_name: new_name,
+                    record_region: region,
+                    field_region: loc_field.region,
+                    replaced_region,
+                },
+            );
         }
     }
 
@@ -1279,7 +2982,7 @@ fn can_tags<'a>(
     env: &mut Env,
     tags: &'a [Loc<Tag<'a>>],
     region: Region,
-    scope: &mut Scope,
+    scope: &mut impl AnnotationScope,
     var_store: &mut VarStore,
     introduced_variables: &mut IntroducedVariables,
     local_aliases: &mut VecMap<Symbol, Alias>,
@@ -1340,14 +3043,300 @@ fn can_tags<'a>(
         // ensure that the new name is not already in this tag union:
         // note that the right-most tag wins when there are two with the same name
         if let Some(replaced_region) = seen.insert(new_name.clone(), loc_tag.region) {
-            env.problem(roc_problem::can::Problem::DuplicateTag {
-                tag_name: new_name,
-                tag_region: loc_tag.region,
-                tag_union_region: region,
-                replaced_region,
-            });
+            annotation_problem(
+                env,
+                loc_tag.region,
+                roc_problem::can::Problem::DuplicateTag {
+                    tag_name: new_name,
+                    tag_region: loc_tag.region,
+                    tag_union_region: region,
+                    replaced_region,
+                },
+            );
         }
     }
 
     tag_types
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn read_only_path_matches_mutable_path() {
+        use bumpalo::Bump;
+        use roc_module::symbol::{IdentIds, ModuleId, ModuleIds};
+
+        let arena = Bump::new();
+        let module_ids = ModuleIds::default();
+        let dep_idents = IdentIds::exposed_builtins(0);
+
+        // a, *, _, Bogus -> a
+        let annotation = TypeAnnotation::Function(
+            arena.alloc([
+                Loc::at_zero(TypeAnnotation::BoundVariable("a")),
+                Loc::at_zero(TypeAnnotation::Wildcard),
+                Loc::at_zero(TypeAnnotation::Inferred),
+                Loc::at_zero(TypeAnnotation::Apply("", "Bogus", &[])),
+            ]),
+            arena.alloc(Loc::at_zero(TypeAnnotation::BoundVariable("a"))),
+        );
+        assert!(!introduces_aliases(&annotation));
+
+        let canonicalize = |read_only: bool| {
+            let mut env = Env::new(&arena, ModuleId::ATTR, &dep_idents, &module_ids);
+            let mut scope = Scope::new(ModuleId::ATTR, IdentIds::default(), Default::default());
+            let mut var_store = VarStore::default();
+
+            let annotation = if read_only {
+                canonicalize_annotation_read_only(
+                    &mut env,
+                    &scope,
+                    &annotation,
+                    Region::zero(),
+                    &mut var_store,
+                    &VecMap::default(),
+                )
+            } else {
+                canonicalize_annotation_help(
+                    &mut env,
+                    &mut scope,
+                    &annotation,
+                    Region::zero(),
+                    &mut var_store,
+                    &VecMap::default(),
+                )
+            };
+
+            (annotation, env.problems)
+        };
+
+        let (mutable, mutable_problems) = canonicalize(false);
+        let (read_only, read_only_problems) = canonicalize(true);
+
+        assert_eq!(mutable.typ, read_only.typ);
+        assert_eq!(
+            format!("{:?}", mutable.introduced_variables),
+            format!("{:?}", read_only.introduced_variables)
+        );
+        assert_eq!(mutable.references, read_only.references);
+        assert_eq!(mutable_problems, read_only_problems);
+    }
+
+    #[test]
+    fn visitor_visits_every_node() {
+        use roc_parse::ast::{Collection, CommentOrNewline, Pattern, Spaced};
+
+        #[derive(Default)]
+        struct Record(Vec<String>);
+
+        impl TypeAnnotationVisitor for Record {
+            fn visit_type_annotation(&mut self, annotation: &TypeAnnotation, region: Region) {
+                if !matches!(
+                    annotation,
+                    TypeAnnotation::SpaceBefore(..) | TypeAnnotation::SpaceAfter(..)
+                ) {
+                    self.0.push("annotation".to_string());
+                }
+                walk_type_annotation(self, annotation, region);
+            }
+
+            fn visit_record_field(
+                &mut self,
+                field: &AssignedField<TypeAnnotation>,
+                region: Region,
+            ) {
+                if let AssignedField::RequiredValue(name, _, _)
+                | AssignedField::OptionalValue(name, _, _) = field
+                {
+                    self.0.push(format!("field {}", name.value));
+                }
+                walk_record_field(self, field, region);
+            }
+
+            fn visit_tag(&mut self, tag: &Tag, region: Region) {
+                if let Tag::Apply { name, .. } = tag {
+                    self.0.push(format!("tag {}", name.value));
+                }
+                walk_tag(self, tag, region);
+            }
+
+            fn visit_has_clause(&mut self, clause: &HasClause, _region: Region) {
+                self.0
+                    .push(format!("has {}", clause.var.value.extract_spaces().item));
+
+                let ability = &clause.ability;
+                self.visit_type_annotation(&ability.value, ability.region);
+            }
+
+            fn visit_apply(&mut self, _module_name: &str, ident: &str, _region: Region) {
+                self.0.push(format!("apply {}", ident));
+            }
+
+            fn visit_bound_variable(&mut self, name: &str, _region: Region) {
+                self.0.push(format!("var {}", name));
+            }
+
+            fn visit_as(&mut self, header: &TypeHeader, _region: Region) {
+                self.0.push(format!("as {}", header.name.value));
+            }
+
+            fn visit_inferred(&mut self, _region: Region) {
+                self.0.push("_".to_string());
+            }
+
+            fn visit_wildcard(&mut self, _region: Region) {
+                self.0.push("*".to_string());
+            }
+
+            fn visit_malformed(&mut self, malformed: &str, _region: Region) {
+                self.0.push(format!("malformed {}", malformed));
+            }
+        }
+
+        // { a : List b, c ? [A x, B]* }r, (_ -> ([C (L a)] as L a)), ?? -> Str | b has Hash
+        let newline: &[CommentOrNewline] = &[CommentOrNewline::Newline];
+        let b = [Loc::at_zero(TypeAnnotation::BoundVariable("b"))];
+        let list_b = Loc::at_zero(TypeAnnotation::Apply("", "List", &b));
+        let a_x = [Loc::at_zero(TypeAnnotation::BoundVariable("x"))];
+        let tag_b = Tag::Apply {
+            name: Loc::at_zero("B"),
+            args: &[],
+        };
+        let tags = [
+            Loc::at_zero(Tag::Apply {
+                name: Loc::at_zero("A"),
+                args: &a_x,
+            }),
+            Loc::at_zero(Tag::SpaceBefore(&tag_b, newline)),
+        ];
+        let tag_union_ext = Loc::at_zero(TypeAnnotation::Wildcard);
+        let tag_union = Loc::at_zero(TypeAnnotation::TagUnion {
+            ext: Some(&tag_union_ext),
+            tags: Collection::with_items(&tags),
+        });
+        let field_c = AssignedField::OptionalValue(Loc::at_zero("c"), &[], &tag_union);
+        let fields = [
+            Loc::at_zero(AssignedField::RequiredValue(
+                Loc::at_zero("a"),
+                &[],
+                &list_b,
+            )),
+            Loc::at_zero(AssignedField::SpaceAfter(&field_c, newline)),
+        ];
+        let record_ext = Loc::at_zero(TypeAnnotation::BoundVariable("r"));
+        let record = TypeAnnotation::Record {
+            fields: Collection::with_items(&fields),
+            ext: Some(&record_ext),
+        };
+
+        let l_a = [Loc::at_zero(TypeAnnotation::BoundVariable("a"))];
+        let c_args = [Loc::at_zero(TypeAnnotation::Apply("", "L", &l_a))];
+        let c_tags = [Loc::at_zero(Tag::Apply {
+            name: Loc::at_zero("C"),
+            args: &c_args,
+        })];
+        let recursive = Loc::at_zero(TypeAnnotation::TagUnion {
+            ext: None,
+            tags: Collection::with_items(&c_tags),
+        });
+        let l_vars = [Loc::at_zero(Pattern::Identifier("a"))];
+        let alias = Loc::at_zero(TypeAnnotation::As(
+            &recursive,
+            &[],
+            TypeHeader {
+                name: Loc::at_zero("L"),
+                vars: &l_vars,
+            },
+        ));
+        let inferred = [Loc::at_zero(TypeAnnotation::Inferred)];
+        let function = TypeAnnotation::Function(&inferred, &alias);
+
+        let args = [
+            Loc::at_zero(TypeAnnotation::SpaceBefore(&record, newline)),
+            Loc::at_zero(function),
+            Loc::at_zero(TypeAnnotation::Malformed("??")),
+        ];
+        let ret = Loc::at_zero(TypeAnnotation::Apply("", "Str", &[]));
+        let function = Loc::at_zero(TypeAnnotation::Function(&args, &ret));
+        let clauses = [Loc::at_zero(HasClause {
+            var: Loc::at_zero(Spaced::Item("b")),
+            ability: Loc::at_zero(TypeAnnotation::Apply("", "Hash", &[])),
+        })];
+        let annotation = TypeAnnotation::Where(&function, &clauses);
+
+        let mut visitor = Record::default();
+        visitor.visit_type_annotation(&annotation, Region::zero());
+
+        assert_eq!(
+            visitor.0,
+            [
+                "annotation",
+                "annotation",
+                // { a : List b, c ? [A x, B]* }r
+                "annotation",
+                "field a",
+                "annotation",
+                "apply List",
+                "annotation",
+                "var b",
+                "field c",
+                "annotation",
+                "tag A",
+                "annotation",
+                "var x",
+                "tag B",
+                "annotation",
+                "*",
+                "annotation",
+                "var r",
+                // _ -> ([C (L a)] as L a)
+                "annotation",
+                "annotation",
+                "_",
+                "annotation",
+                "as L",
+                "annotation",
+                "tag C",
+                "annotation",
+                "apply L",
+                "annotation",
+                "var a",
+                // ??
+                "annotation",
+                "malformed ??",
+                // Str
+                "annotation",
+                "apply Str",
+                // b has Hash
+                "has b",
+                "annotation",
+                "apply Hash",
+            ]
+        );
+    }
+
+    #[test]
+    fn as_introduces_aliases() {
+        use roc_parse::ast::Pattern;
+
+        // List (* as Any a)
+        let wildcard = Loc::at_zero(TypeAnnotation::Wildcard);
+        let vars = [Loc::at_zero(Pattern::Identifier("a"))];
+        let any = TypeAnnotation::As(
+            &wildcard,
+            &[],
+            TypeHeader {
+                name: Loc::at_zero("Any"),
+                vars: &vars,
+            },
+        );
+        let args = [Loc::at_zero(any)];
+        let annotation = TypeAnnotation::Apply("", "List", &args);
+
+        assert!(introduces_aliases(&annotation));
+    }
+}
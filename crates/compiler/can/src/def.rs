@@ -2,11 +2,11 @@ use crate::abilities::AbilityMemberData;
 use crate::abilities::ImplKey;
 use crate::abilities::MemberVariables;
 use crate::abilities::PendingMemberType;
-use crate::annotation::canonicalize_annotation;
 use crate::annotation::find_type_def_symbols;
 use crate::annotation::make_apply_symbol;
 use crate::annotation::IntroducedVariables;
 use crate::annotation::OwnedNamedOrAble;
+use crate::annotation::{canonicalize_annotation, canonicalize_type_def_body};
 use crate::env::Env;
 use crate::expr::AccessorData;
 use crate::expr::AnnotatedMark;
@@ -45,6 +45,7 @@ use roc_types::types::AliasVar;
 use roc_types::types::LambdaSet;
 use roc_types::types::MemberImpl;
 use roc_types::types::OptAbleType;
+use roc_types::types::Variance;
 use roc_types::types::{Alias, Type};
 use std::fmt::Debug;
 
@@ -321,7 +322,7 @@ fn canonicalize_alias<'a>(
     kind: AliasKind,
 ) -> Result<Alias, ()> {
     let symbol = name.value;
-    let can_ann = canonicalize_annotation(
+    let can_ann = canonicalize_type_def_body(
         env,
         scope,
         &ann.value,
@@ -366,6 +367,8 @@ fn canonicalize_alias<'a>(
                         name,
                         var,
                         opt_bound_ability,
+                        opt_default: None,
+                        variance: Variance::Inferred,
                     },
                     region: loc_lowercase.region,
                 });
@@ -388,6 +391,8 @@ fn canonicalize_alias<'a>(
                             name: loc_lowercase.value.clone(),
                             var: var_store.fresh(),
                             opt_bound_ability: None,
+                            opt_default: None,
+                            variance: Variance::Inferred,
                         },
                         region: loc_lowercase.region,
                     });
@@ -1237,6 +1242,23 @@ fn resolve_abilities<'a>(
                 .partition(|av| av.ability == ability);
 
             let var_bound_to_ability = match variables_bound_to_ability.as_slice() {
+                [one]
+                    if !matches!(member_annot.typ, Type::Erroneous(_))
+                        && !member_annot.typ.variables().contains(&one.variable) =>
+                {
+                    // The variable is bound to the ability, but the signature never mentions it,
+                    // like in
+                    //   Hash has hash : U64 -> U64 | a has Hash
+                    // so no implementation of the member could ever be chosen.
+                    env.problem(Problem::AbilityMemberMissingSelf {
+                        member: member_sym,
+                        ability,
+                        var_name: one.name.clone(),
+                        region: member_name_region,
+                    });
+                    // Pretend the member isn't a part of the ability
+                    continue;
+                }
                 [one] => one.variable,
                 [] => {
                     // There are no variables bound to the parent ability - then this member doesn't
@@ -7,6 +7,10 @@ use roc_module::symbol::{IdentIdsByModule, ModuleId, ModuleIds, Symbol};
 use roc_problem::can::{Problem, RuntimeError};
 use roc_region::all::{Loc, Region};
 
+/// Decides whether annotations may refer to a type, after its name has been resolved. Returns
+/// `false` to forbid the reference.
+pub type SymbolPolicy<'a> = &'a dyn Fn(Symbol) -> bool;
+
 /// The canonicalization environment for a particular module.
 pub struct Env<'a> {
     /// The module's path. Opaques and unqualified references to identifiers
@@ -20,6 +24,10 @@ pub struct Env<'a> {
     /// Problems we've encountered along the way, which will be reported to the user at the end.
     pub problems: Vec<Problem>,
 
+    /// The problems reported while canonicalizing annotations, by the region they were reported
+    /// at, so that canonicalizing an annotation again does not report them twice.
+    pub reported_annotation_problems: MutMap<Region, Vec<Problem>>,
+
     /// Closures
     pub closures: MutMap<Symbol, References>,
 
@@ -34,6 +42,47 @@ pub struct Env<'a> {
 
     pub top_level_symbols: VecSet<Symbol>,
 
+    /// The most `_` and `*` variables a single annotation may introduce. Past this limit,
+    /// [`Problem::TooManyInferredVariables`] is reported and no further variables are allocated
+    /// for that annotation. `None` means there is no limit.
+    pub max_inferred_variables: Option<usize>,
+
+    /// Whether to suggest opening records and tag unions that are closed in function argument
+    /// annotations, by reporting [`Problem::ClosedArgumentExtension`].
+    pub suggest_open_extensions: bool,
+
+    /// Whether unqualified type names that are not in scope stand for a type yet to be written,
+    /// rather than being errors. Useful when checking code that is still being edited.
+    pub defer_unrecognized_types: bool,
+
+    /// Whether an alias applied to too few arguments has the missing trailing ones inferred, as if
+    /// they were written `_`, rather than reporting bad type arguments. Defaults still come first.
+    pub infer_missing_type_arguments: bool,
+
+    /// Whether aliases introduced with `as` are only in scope within the annotation that
+    /// introduces them. They are still recorded in that annotation's aliases.
+    pub local_as_aliases: bool,
+
+    /// Whether aliases in annotations are expanded right away, rather than delayed until they are
+    /// needed. Only the representation changes; useful for seeing the full types when debugging
+    /// the solver.
+    pub expand_aliases_eagerly: bool,
+
+    /// Restricts which types annotations may refer to, by reporting
+    /// [`Problem::DisallowedTypeReference`] for those it forbids. `None` allows every type.
+    pub symbol_policy: Option<SymbolPolicy<'a>>,
+
+    /// Restricts annotations to the opaque types of other modules that are re-exported, that is,
+    /// those in the set; any other is reported as [`Problem::OpaqueNotExported`]. Builtin opaques
+    /// are always allowed. `None` allows every opaque type.
+    pub reexported_opaques: Option<&'a VecSet<Symbol>>,
+
+    /// Restricts annotations to the builtin types in the set, like `Str` and `Bool` for a small
+    /// embedded language; any other builtin, including builtin abilities, is reported as
+    /// [`Problem::TypeNotAllowedInContext`]. Types defined outside the builtins are unaffected.
+    /// `None` allows every builtin type.
+    pub allowed_builtin_types: Option<&'a VecSet<Symbol>>,
+
     pub arena: &'a Bump,
 }
 
@@ -50,11 +99,21 @@ impl<'a> Env<'a> {
             dep_idents,
             module_ids,
             problems: Vec::new(),
+            reported_annotation_problems: MutMap::default(),
             closures: MutMap::default(),
             qualified_value_lookups: VecSet::default(),
             qualified_type_lookups: VecSet::default(),
             tailcallable_symbol: None,
             top_level_symbols: VecSet::default(),
+            max_inferred_variables: None,
+            suggest_open_extensions: false,
+            defer_unrecognized_types: false,
+            infer_missing_type_arguments: false,
+            local_as_aliases: false,
+            expand_aliases_eagerly: false,
+            symbol_policy: None,
+            reexported_opaques: None,
+            allowed_builtin_types: None,
         }
     }
 
@@ -1,5 +1,5 @@
 use crate::abilities::{ImplKey, PendingAbilitiesStore, ResolvedImpl};
-use crate::annotation::canonicalize_annotation;
+use crate::annotation::{canonicalize_annotation, Rigidity};
 use crate::def::{canonicalize_defs, Def};
 use crate::effect_module::HostedGeneratedFunctions;
 use crate::env::Env;
@@ -390,7 +390,9 @@ pub fn canonicalize_module_defs<'a>(
     }
 
     for named in output.introduced_variables.named {
-        rigid_variables.named.insert(named.variable, named.name);
+        if named.rigidity == Rigidity::Rigid {
+            rigid_variables.named.insert(named.variable, named.name);
+        }
     }
 
     for able in output.introduced_variables.able {
@@ -534,6 +536,7 @@ pub fn canonicalize_module_defs<'a>(
                                 introduced_variables: def_annotation.introduced_variables,
                                 references: Default::default(),
                                 aliases: Default::default(),
+                                phantom_variables: Default::default(),
                             };
 
                             let hosted_def = crate::effect_module::build_host_exposed_def(
@@ -592,6 +595,7 @@ pub fn canonicalize_module_defs<'a>(
                                 introduced_variables: def_annotation.introduced_variables,
                                 references: Default::default(),
                                 aliases: Default::default(),
+                                phantom_variables: Default::default(),
                             };
 
                             let hosted_def = crate::effect_module::build_host_exposed_def(
@@ -1,13 +1,16 @@
 extern crate bumpalo;
 
 use self::bumpalo::Bump;
+use roc_can::annotation::{canonicalize_annotation, Annotation};
 use roc_can::env::Env;
 use roc_can::expr::Output;
 use roc_can::expr::{canonicalize_expr, Expr};
 use roc_can::operator;
 use roc_can::scope::Scope;
 use roc_collections::all::MutMap;
+use roc_collections::{VecMap, VecSet};
 use roc_module::symbol::{IdentIds, Interns, ModuleId, ModuleIds, Symbol};
+use roc_parse::ast::TypeAnnotation;
 use roc_problem::can::Problem;
 use roc_region::all::{Loc, Region};
 use roc_types::subs::{VarStore, Variable};
@@ -96,6 +99,97 @@ pub fn can_expr_with(arena: &Bump, home: ModuleId, expr_str: &str) -> CanExprOut
     }
 }
 
+/// An environment, scope and var store for canonicalizing type annotations of the `Test` module
+/// on their own, outside of any expression.
+pub struct AnnotationFixture<'a> {
+    pub arena: &'a Bump,
+    pub env: Env<'a>,
+    pub scope: Scope,
+    pub var_store: VarStore,
+}
+
+#[allow(dead_code)]
+impl<'a> AnnotationFixture<'a> {
+    pub fn new(arena: &'a Bump) -> Self {
+        Self::with_module_ids(arena, test_home(), ModuleIds::default())
+    }
+
+    /// Like [`AnnotationFixture::new`], but `home` is one of `module_ids`, which may name other
+    /// modules as well.
+    pub fn with_module_ids(arena: &'a Bump, home: ModuleId, module_ids: ModuleIds) -> Self {
+        let module_ids = arena.alloc(module_ids);
+        let dep_idents = arena.alloc(IdentIds::exposed_builtins(0));
+
+        AnnotationFixture {
+            arena,
+            env: Env::new(arena, home, dep_idents, module_ids),
+            scope: Scope::new(home, IdentIds::default(), Default::default()),
+            var_store: VarStore::default(),
+        }
+    }
+
+    pub fn home(&self) -> ModuleId {
+        self.env.home
+    }
+
+    pub fn parse(&self, src: &'a str) -> Loc<TypeAnnotation<'a>> {
+        roc_parse::test_helpers::parse_annotation_with(self.arena, src).unwrap_or_else(|e| {
+            panic!(
+                "AnnotationFixture::parse() got a parse error when attempting to parse:\n\n{:?} {:?}",
+                src, e
+            )
+        })
+    }
+
+    /// Takes the problems reported so far. Those annotations are forgotten too, so that an
+    /// annotation canonicalized afterwards reports its problems even if it has the same regions.
+    pub fn take_problems(&mut self) -> Vec<Problem> {
+        self.env.reported_annotation_problems.clear();
+
+        std::mem::take(&mut self.env.problems)
+    }
+
+    /// Canonicalizes `annotation`, which is not in any abilities' definitions.
+    pub fn canonicalize(&mut self, annotation: &TypeAnnotation) -> Annotation {
+        self.canonicalize_at(annotation, Region::zero())
+    }
+
+    pub fn canonicalize_at(&mut self, annotation: &TypeAnnotation, region: Region) -> Annotation {
+        canonicalize_annotation(
+            &mut self.env,
+            &mut self.scope,
+            annotation,
+            region,
+            &mut self.var_store,
+            &VecMap::default(),
+        )
+    }
+
+    /// Parses and canonicalizes the annotation `src`.
+    pub fn canonicalize_str(&mut self, src: &'a str) -> Annotation {
+        self.canonicalize_str_with_abilities(src, &VecMap::default())
+    }
+
+    /// Like [`AnnotationFixture::canonicalize_str`], for an annotation in the definitions of the
+    /// abilities in `pending_abilities`, mapped to their members.
+    pub fn canonicalize_str_with_abilities(
+        &mut self,
+        src: &'a str,
+        pending_abilities: &VecMap<Symbol, VecSet<Symbol>>,
+    ) -> Annotation {
+        let loc_annotation = self.parse(src);
+
+        canonicalize_annotation(
+            &mut self.env,
+            &mut self.scope,
+            &loc_annotation.value,
+            loc_annotation.region,
+            &mut self.var_store,
+            pending_abilities,
+        )
+    }
+}
+
 #[allow(dead_code)]
 pub fn mut_map_from_pairs<K, V, I>(pairs: I) -> MutMap<K, V>
 where
@@ -12,7 +12,7 @@ mod helpers;
 
 #[cfg(test)]
 mod test_can {
-    use crate::helpers::{can_expr_with, test_home, CanExprOut};
+    use crate::helpers::{can_expr_with, test_home, AnnotationFixture, CanExprOut};
     use bumpalo::Bump;
     use roc_can::expr::Expr::{self, *};
     use roc_can::expr::{ClosureData, IntValue, Recursive};
@@ -291,6 +291,24 @@ mod test_can {
         assert_eq!(problems, Vec::new());
     }
 
+    #[test]
+    fn phantom_alias_parameters_can_be_marked() {
+        let src = indoc!(
+            r#"
+                Tagged tag value : value | tag has Phantom
+
+                x : Tagged Str Num.U8
+                x = 1
+
+                x
+            "#
+        );
+        let arena = Bump::new();
+        let CanExprOut { problems, .. } = can_expr_with(&arena, test_home(), src);
+
+        assert_eq!(problems, Vec::new());
+    }
+
     #[test]
     fn correct_annotated_body_with_comments() {
         let src = indoc!(
@@ -570,6 +588,2410 @@ mod test_can {
             .iter()
             .all(|problem| matches!(problem, Problem::UnusedDef(_, _))));
     }
+
+    #[test]
+    fn identical_annotation_problems_reported_once() {
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        // `Bogus` is not in scope; canonicalizing the same annotation twice, as happens when it
+        // is instantiated at several use sites, should only report that once.
+        for _ in 0..2 {
+            fixture.canonicalize_str("Bogus");
+        }
+
+        assert_eq!(fixture.env.problems.len(), 1);
+        assert!(matches!(
+            fixture.env.problems[0],
+            Problem::RuntimeError(RuntimeError::LookupNotInScope(..))
+        ));
+    }
+
+    #[test]
+    fn bad_type_arguments_counts_are_not_truncated() {
+        use roc_region::all::Loc;
+        use roc_types::types::{AliasKind, AliasVar, Problem, Type};
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        // Big a0 a1 .. a299 : {}
+        let big = fixture
+            .scope
+            .introduce("Big".into(), Region::zero())
+            .unwrap();
+        let type_variables = (0..300)
+            .map(|i| {
+                Loc::at_zero(AliasVar::unbound(
+                    format!("a{}", i).into(),
+                    fixture.var_store.fresh(),
+                ))
+            })
+            .collect();
+        fixture.scope.add_alias(
+            big,
+            Region::zero(),
+            type_variables,
+            Type::EmptyRec,
+            AliasKind::Structural,
+        );
+
+        let annotation = fixture.canonicalize_str("Big {}");
+
+        assert_eq!(
+            annotation.typ,
+            Type::Erroneous(Problem::BadTypeArguments {
+                symbol: big,
+                region: Region::new(Position::new(0), Position::new(6)),
+                type_got: 1,
+                alias_needs: 300,
+                alias_kind: AliasKind::Structural,
+            })
+        );
+    }
+
+    #[test]
+    fn too_many_inferred_variables() {
+        use roc_problem::can::Problem;
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+        fixture.env.max_inferred_variables = Some(3);
+
+        let annotation = fixture.canonicalize_str("_, _, _, _, _, _, _, _, _ -> _");
+
+        assert_eq!(annotation.introduced_variables.inferred.len(), 3);
+        assert!(annotation.introduced_variables.inferred_limit_reached);
+        assert_eq!(
+            fixture.env.problems,
+            vec![Problem::TooManyInferredVariables {
+                limit: 3,
+                // the fourth `_`
+                region: Region::new(Position::new(9), Position::new(10)),
+            }]
+        );
+    }
+
+    #[test]
+    fn closed_argument_record_suggests_opening() {
+        use roc_problem::can::{ExtensionTypeKind, Problem};
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        let mut problems_with_suggestions = |suggest_open_extensions| {
+            fixture.env.suggest_open_extensions = suggest_open_extensions;
+            fixture.canonicalize_str("{ name : Str }, {} -> {}");
+            fixture.take_problems()
+        };
+
+        assert_eq!(problems_with_suggestions(false), vec![]);
+        // `{}` is closed too, but there is nothing to open it up to.
+        assert_eq!(
+            problems_with_suggestions(true),
+            vec![Problem::ClosedArgumentExtension {
+                // `{ name : Str }`
+                region: Region::new(Position::new(0), Position::new(14)),
+                kind: ExtensionTypeKind::Record,
+            }]
+        );
+    }
+
+    #[test]
+    fn alias_default_type_arguments() {
+        use roc_module::symbol::Symbol;
+        use roc_region::all::Loc;
+        use roc_types::types::{
+            AliasCommon, AliasKind, AliasVar, RecordField, Type, TypeExtension,
+        };
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        // Config a : { field : a }, where `a` defaults to `Str`
+        let config = fixture
+            .scope
+            .introduce("Config".into(), Region::zero())
+            .unwrap();
+        let a = fixture.var_store.fresh();
+        let mut fields = roc_collections::SendMap::default();
+        fields.insert("field".into(), RecordField::Required(Type::Variable(a)));
+        fixture.scope.add_alias(
+            config,
+            Region::zero(),
+            vec![Loc::at_zero(AliasVar {
+                opt_default: Some(Type::Apply(Symbol::STR_STR, vec![], Region::zero())),
+                ..AliasVar::unbound("a".into(), a)
+            })],
+            Type::Record(fields, TypeExtension::Closed),
+            AliasKind::Structural,
+        );
+
+        let mut canonicalize = |src| {
+            let annotation = fixture.canonicalize_str(src);
+
+            match annotation.typ {
+                Type::DelayedAlias(AliasCommon {
+                    symbol,
+                    type_arguments,
+                    ..
+                }) if symbol == config => type_arguments,
+                other => panic!("expected a Config alias, got {:?}", other),
+            }
+        };
+
+        let defaulted = canonicalize("Config");
+        assert_eq!(
+            defaulted,
+            vec![Type::Apply(Symbol::STR_STR, vec![], Region::zero())]
+        );
+
+        let explicit = canonicalize("Config {}");
+        assert_eq!(explicit, vec![Type::EmptyRec]);
+
+        assert_eq!(fixture.env.problems, Vec::new());
+    }
+
+    #[test]
+    fn redundant_as_alias() {
+        use roc_types::types::{AliasKind, Type};
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        // Id : {}
+        let id = fixture
+            .scope
+            .introduce("Id".into(), Region::zero())
+            .unwrap();
+        fixture.scope.add_alias(
+            id,
+            Region::zero(),
+            vec![],
+            Type::EmptyRec,
+            AliasKind::Structural,
+        );
+
+        let annotation = fixture.canonicalize_str("Id as Name");
+
+        let name = match annotation.typ {
+            Type::HostExposedAlias { name, .. } => name,
+            other => panic!("expected the `Name` alias, got {:?}", other),
+        };
+        assert_eq!(
+            fixture.env.problems,
+            vec![Problem::RedundantAsAlias {
+                alias: name,
+                existing: id,
+                region: Region::new(Position::new(0), Position::new(10)),
+            }]
+        );
+    }
+
+    #[test]
+    fn as_aliases_are_arity_checked_where_applied() {
+        use roc_types::types::{AliasKind, Problem as TypeProblem, Type};
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        let annotation = fixture.canonicalize_str("(List a as Pair a b), Pair Str -> Str");
+
+        let args = match annotation.typ {
+            Type::Function(args, _, _) => args,
+            other => panic!("expected a function, got {:?}", other),
+        };
+        let pair = match &args[0] {
+            Type::Alias { symbol, .. } => *symbol,
+            other => panic!("expected the `Pair` alias, got {:?}", other),
+        };
+
+        // The alias the `as` introduced is in scope for the rest of the annotation, so applying
+        // it to too few arguments is caught like it is for any other alias.
+        assert_eq!(
+            args[1],
+            Type::Erroneous(TypeProblem::BadTypeArguments {
+                symbol: pair,
+                // `Pair Str`
+                region: Region::new(Position::new(22), Position::new(30)),
+                alias_needs: 2,
+                type_got: 1,
+                alias_kind: AliasKind::Structural,
+            })
+        );
+    }
+
+    #[test]
+    fn checked_annotations_fail_when_erroneous() {
+        use roc_can::annotation::canonicalize_annotation_checked;
+        use roc_collections::VecMap;
+        use roc_types::types::{AliasKind, Type};
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        // Id : {}
+        let id = fixture
+            .scope
+            .introduce("Id".into(), Region::zero())
+            .unwrap();
+        fixture.scope.add_alias(
+            id,
+            Region::zero(),
+            vec![],
+            Type::EmptyRec,
+            AliasKind::Structural,
+        );
+
+        let mut check = |src| {
+            let annotation = fixture.parse(src);
+            canonicalize_annotation_checked(
+                &mut fixture.env,
+                &mut fixture.scope,
+                &annotation.value,
+                annotation.region,
+                &mut fixture.var_store,
+                &VecMap::default(),
+            )
+        };
+
+        assert!(
+            matches!(check("List Str"), Ok(annotation) if matches!(annotation.typ, Type::Apply(..)))
+        );
+
+        assert!(matches!(
+            check("List Missing").err().as_deref(),
+            Some([Problem::RuntimeError(RuntimeError::LookupNotInScope(..))])
+        ));
+
+        // A wrong number of type arguments is only reported when solving.
+        assert!(matches!(check("Id Str").err().as_deref(), Some([])));
+
+        // The problems were handed back rather than left in the environment.
+        assert_eq!(fixture.env.problems, Vec::new());
+    }
+
+    #[test]
+    fn structural_types_fold_into_the_most_specific_alias() {
+        use roc_collections::{SendMap, VecMap};
+        use roc_module::symbol::Symbol;
+        use roc_parse::ast::{AssignedField, Collection, TypeAnnotation};
+        use roc_region::all::Loc;
+        use roc_types::types::{
+            Alias, AliasKind, AliasVar, OptAbleType, RecordField, Type, TypeExtension,
+        };
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        let str_type = || Type::Apply(Symbol::STR_STR, vec![], Region::zero());
+        let list_of = |typ| Type::Apply(Symbol::LIST_LIST, vec![typ], Region::zero());
+        let x_and_y = |typ: Type| {
+            let mut fields = SendMap::default();
+            fields.insert("x".into(), RecordField::Required(typ.clone()));
+            fields.insert("y".into(), RecordField::Required(typ));
+            Type::Record(fields, TypeExtension::Closed)
+        };
+        let alias = |type_variables, typ| Alias {
+            region: Region::zero(),
+            type_variables,
+            lambda_set_variables: vec![],
+            recursion_variables: Default::default(),
+            typ,
+            kind: AliasKind::Structural,
+        };
+
+        // Point : { x : Str, y : Str }
+        // Pair a : { x : a, y : a }
+        let point = fixture
+            .scope
+            .introduce("Point".into(), Region::zero())
+            .unwrap();
+        let pair = fixture
+            .scope
+            .introduce("Pair".into(), Region::zero())
+            .unwrap();
+        let a = fixture.var_store.fresh();
+        let mut aliases = VecMap::default();
+        aliases.insert(point, alias(vec![], x_and_y(str_type())));
+        aliases.insert(
+            pair,
+            alias(
+                vec![Loc::at_zero(AliasVar::unbound("a".into(), a))],
+                x_and_y(Type::Variable(a)),
+            ),
+        );
+
+        // { x : <field>, y : <field> }
+        let x_and_y_ann = |field: TypeAnnotation<'static>| {
+            let field = Loc::at_zero(field);
+            let x = Loc::at_zero(AssignedField::RequiredValue(
+                Loc::at_zero("x"),
+                &[],
+                arena.alloc(field),
+            ));
+            let y = Loc::at_zero(AssignedField::RequiredValue(
+                Loc::at_zero("y"),
+                &[],
+                arena.alloc(field),
+            ));
+            TypeAnnotation::Record {
+                fields: Collection::with_items(arena.alloc([x, y])),
+                ext: None,
+            }
+        };
+        let mut fold = |annotation: TypeAnnotation| {
+            let mut annotation = fixture.canonicalize(&annotation);
+            annotation.fold_into_aliases(&aliases);
+            annotation.typ
+        };
+        let folded_into = |typ: Type| match typ {
+            Type::Alias {
+                symbol,
+                type_arguments,
+                actual,
+                ..
+            } => (
+                symbol,
+                type_arguments
+                    .into_iter()
+                    .map(|OptAbleType { typ, .. }| typ)
+                    .collect::<Vec<_>>(),
+                *actual,
+            ),
+            other => panic!("expected an alias, got {:?}", other),
+        };
+
+        const STR: TypeAnnotation<'static> = TypeAnnotation::Apply("", "Str", &[]);
+        const LIST_STR: TypeAnnotation<'static> =
+            TypeAnnotation::Apply("", "List", &[Loc::at_zero(STR)]);
+
+        // Both aliases match { x : Str, y : Str }, but `Point` is the more specific one.
+        let (symbol, arguments, actual) = folded_into(fold(x_and_y_ann(STR)));
+        assert_eq!((symbol, arguments), (point, vec![]));
+        assert_eq!(actual, x_and_y(str_type()));
+
+        // Only `Pair` matches { x : List Str, y : List Str }.
+        let (symbol, arguments, _) = folded_into(fold(x_and_y_ann(LIST_STR)));
+        assert_eq!((symbol, arguments), (pair, vec![list_of(str_type())]));
+
+        // Types within other types are folded too.
+        let record = arena.alloc(Loc::at_zero(x_and_y_ann(STR)));
+        let list_of_record = TypeAnnotation::Apply("", "List", std::slice::from_ref(record));
+        match fold(list_of_record) {
+            Type::Apply(Symbol::LIST_LIST, args, _) => {
+                assert_eq!(folded_into(args[0].clone()).0, point);
+            }
+            other => panic!("expected a list, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn as_recursion_reaches_into_records_in_payloads() {
+        use roc_module::ident::TagName;
+        use roc_module::symbol::Symbol;
+        use roc_types::types::{RecordField, Type, TypeExtension};
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        let annotation =
+            fixture.canonicalize_str("[Leaf Num.I64, Node { children : List Tree }] as Tree");
+
+        assert_eq!(fixture.env.problems, Vec::new());
+
+        let tree = match annotation.typ {
+            Type::HostExposedAlias { name, .. } => name,
+            other => panic!("expected the `Tree` alias, got {:?}", other),
+        };
+        let (rec_var, tags) = match &fixture.scope.lookup_alias(tree).unwrap().typ {
+            Type::RecursiveTagUnion(rec_var, tags, TypeExtension::Closed) => (*rec_var, tags),
+            other => panic!("expected a recursive tag union, got {:?}", other),
+        };
+        let node = tags
+            .iter()
+            .find(|(name, _)| name == &TagName("Node".into()))
+            .unwrap();
+        let children = match node.1.as_slice() {
+            [Type::Record(fields, TypeExtension::Closed)] => fields.get(&"children".into()),
+            other => panic!("expected a record payload, got {:?}", other),
+        };
+        match children {
+            Some(RecordField::Required(Type::Apply(Symbol::LIST_LIST, args, _))) => {
+                assert_eq!(args, &[Type::Variable(rec_var)]);
+            }
+            other => panic!("expected `children : List Tree`, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn as_alias_outside_of_tag_payload_is_infinite() {
+        use roc_parse::ast::{AssignedField, Collection, TypeAnnotation, TypeHeader};
+        use roc_region::all::Loc;
+        use roc_types::types::{self, Type};
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        // { next : Node } as Node
+        let node = Loc::new(9, 13, TypeAnnotation::Apply("", "Node", &[]));
+        let next = Loc::new(
+            2,
+            13,
+            AssignedField::RequiredValue(Loc::new(2, 6, "next"), &[], &node),
+        );
+        let inner = Loc::new(
+            0,
+            15,
+            TypeAnnotation::Record {
+                fields: Collection::with_items(arena.alloc([next])),
+                ext: None,
+            },
+        );
+        let annotation = TypeAnnotation::As(
+            &inner,
+            &[],
+            TypeHeader {
+                name: Loc::new(19, 23, "Node"),
+                vars: &[],
+            },
+        );
+        let annotation = fixture.canonicalize_at(&annotation, inner.region);
+
+        let node = match annotation.typ {
+            Type::HostExposedAlias { name, .. } => name,
+            other => panic!("expected the `Node` alias, got {:?}", other),
+        };
+        assert_eq!(
+            fixture.env.problems,
+            vec![Problem::InfiniteType {
+                alias: node,
+                region: Region::new(Position::new(0), Position::new(23)),
+            }]
+        );
+        assert!(matches!(
+            fixture.scope.lookup_alias(node).unwrap().typ,
+            Type::Erroneous(types::Problem::CyclicAlias(..))
+        ));
+    }
+
+    #[test]
+    fn as_alias_region_covers_body_and_header() {
+        use roc_parse::ast::{TypeAnnotation, TypeHeader};
+        use roc_region::all::{Loc, Position};
+        use roc_types::types::Type;
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        // {} as Unit
+        let inner = Loc::new(
+            0,
+            2,
+            TypeAnnotation::Record {
+                fields: Default::default(),
+                ext: None,
+            },
+        );
+        let annotation = TypeAnnotation::As(
+            &inner,
+            &[],
+            TypeHeader {
+                name: Loc::new(6, 10, "Unit"),
+                vars: &[],
+            },
+        );
+        // Only hand over the region of the body.
+        let annotation = fixture.canonicalize_at(&annotation, inner.region);
+
+        let unit = match annotation.typ {
+            Type::HostExposedAlias { name, .. } => name,
+            other => panic!("expected the `Unit` alias, got {:?}", other),
+        };
+        assert_eq!(
+            fixture.scope.lookup_alias(unit).map(|alias| alias.region),
+            Some(Region::new(Position::new(0), Position::new(10)))
+        );
+    }
+
+    #[test]
+    fn annotation_records_phantom_variables() {
+        use roc_region::all::Loc;
+        use roc_types::types::{AliasKind, AliasVar, Type};
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        // Id a := {}
+        let id = fixture
+            .scope
+            .introduce("Id".into(), Region::zero())
+            .unwrap();
+        fixture.scope.add_alias(
+            id,
+            Region::zero(),
+            vec![Loc::at_zero(AliasVar::unbound(
+                "a".into(),
+                fixture.var_store.fresh(),
+            ))],
+            Type::EmptyRec,
+            AliasKind::Opaque,
+        );
+
+        let annotation = fixture.canonicalize_str("Id b");
+
+        assert_eq!(fixture.env.problems, Vec::new());
+        assert_eq!(
+            annotation.phantom_variables.get(&id),
+            Some(&vec!["a".into()])
+        );
+    }
+
+    #[test]
+    fn annotation_ability_constraints() {
+        use roc_collections::{VecMap, VecSet};
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        let hash = fixture
+            .scope
+            .introduce("Hash".into(), Region::zero())
+            .unwrap();
+        let eq = fixture
+            .scope
+            .introduce("Eq".into(), Region::zero())
+            .unwrap();
+        let mut pending_abilities = VecMap::default();
+        pending_abilities.insert(hash, VecSet::default());
+        pending_abilities.insert(eq, VecSet::default());
+
+        let annotation = fixture
+            .canonicalize_str_with_abilities("a -> b | a has Hash, b has Eq", &pending_abilities);
+
+        assert_eq!(fixture.env.problems, Vec::new());
+
+        let mut constraints: Vec<_> = annotation
+            .introduced_variables
+            .ability_constraints()
+            .map(|(name, abilities)| (name.clone(), abilities.to_vec()))
+            .collect();
+        constraints.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        assert_eq!(
+            constraints,
+            vec![("a".into(), vec![hash]), ("b".into(), vec![eq])]
+        );
+    }
+
+    #[test]
+    fn has_clause_variables_must_be_used() {
+        use roc_collections::{VecMap, VecSet};
+        use roc_region::all::Position;
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        // An imported ability, and one whose members are being defined right now.
+        let hash = fixture
+            .scope
+            .introduce("Hash".into(), Region::zero())
+            .unwrap();
+        fixture.scope.abilities_store.register_ability(hash, []);
+        let eq = fixture
+            .scope
+            .introduce("Eq".into(), Region::zero())
+            .unwrap();
+        let mut pending_abilities = VecMap::default();
+        pending_abilities.insert(eq, VecSet::default());
+
+        let mut check = |src| {
+            let annotation = fixture.canonicalize_str_with_abilities(src, &pending_abilities);
+
+            (
+                annotation.introduced_variables.able.len(),
+                fixture.take_problems(),
+            )
+        };
+
+        assert_eq!(check("a -> Str | a has Hash"), (1, Vec::new()));
+
+        assert_eq!(
+            check("Str -> Str | a has Hash"),
+            (
+                1,
+                vec![Problem::UnusedHasClauseVariable {
+                    name: "a".into(),
+                    // `a has Hash`
+                    region: Region::new(Position::new(13), Position::new(23)),
+                }]
+            )
+        );
+
+        // In the signature of a member of Eq, this is left for the ability member checks to
+        // report.
+        assert_eq!(check("Str -> Str | a has Eq"), (1, Vec::new()));
+    }
+
+    #[test]
+    fn type_def_symbols_include_abilities() {
+        use roc_can::annotation::find_type_def_symbols;
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        let annotation = fixture.parse("(a -> Age | a has Hash), b -> b | b has Eq");
+
+        let symbols = find_type_def_symbols(&mut fixture.scope, &annotation.value);
+        let mut names: Vec<_> = (symbols.iter())
+            .map(|symbol| {
+                let ident_ids = &fixture.scope.locals.ident_ids;
+                ident_ids.get_name(symbol.ident_id()).unwrap()
+            })
+            .collect();
+        names.sort_unstable();
+
+        assert_eq!(names, ["Age", "Eq", "Hash"]);
+    }
+
+    #[test]
+    fn phantom_variables_are_introduced_as_rigids() {
+        use roc_can::annotation::Rigidity;
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        let annotation = fixture.canonicalize_str("Str | tag has Phantom");
+
+        assert_eq!(fixture.env.problems, Vec::new());
+
+        let introduced = &annotation.introduced_variables;
+        let tag = "tag".into();
+        assert!(introduced.var_by_name(&tag).is_some());
+        assert!(introduced
+            .named
+            .iter()
+            .all(|nv| nv.rigidity == Rigidity::Rigid));
+        assert_eq!(introduced.ability_constraints().count(), 0);
+    }
+
+    #[test]
+    fn phantom_markers_can_be_shadowed() {
+        use roc_region::all::Position;
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        // An ability that happens to be named like the marker.
+        let phantom = fixture
+            .scope
+            .introduce("Phantom".into(), Region::zero())
+            .unwrap();
+        fixture.scope.abilities_store.register_ability(phantom, []);
+
+        let annotation = fixture.canonicalize_str("tag -> Str | tag has Phantom");
+
+        assert_eq!(
+            fixture.env.problems,
+            vec![Problem::PhantomMarkerShadowed {
+                symbol: phantom,
+                // `Phantom`
+                region: Region::new(Position::new(21), Position::new(28)),
+            }]
+        );
+        let able: Vec<_> = annotation.introduced_variables.able.iter().collect();
+        assert_eq!(able.len(), 1);
+        assert_eq!(able[0].ability, phantom);
+    }
+
+    #[test]
+    fn variables_are_bound_to_one_ability() {
+        use roc_collections::{VecMap, VecSet};
+        use roc_problem::can::ShadowKind;
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        let hash = fixture
+            .scope
+            .introduce("Hash".into(), Region::zero())
+            .unwrap();
+        let eq = fixture
+            .scope
+            .introduce("Eq".into(), Region::zero())
+            .unwrap();
+        let mut pending_abilities = VecMap::default();
+        pending_abilities.insert(hash, VecSet::default());
+        pending_abilities.insert(eq, VecSet::default());
+
+        let mut constraints_of = |src| {
+            let annotation = fixture.canonicalize_str_with_abilities(src, &pending_abilities);
+
+            let constraints: Vec<_> = annotation
+                .introduced_variables
+                .ability_constraints()
+                .map(|(name, abilities)| (name.clone(), abilities.to_vec()))
+                .collect();
+            (constraints, fixture.take_problems())
+        };
+
+        let (constraints, problems) = constraints_of("a -> a | a has Eq, a has Hash");
+        assert_eq!(constraints, vec![("a".into(), vec![eq])]);
+        assert!(matches!(
+            problems.as_slice(),
+            [Problem::Shadowing {
+                kind: ShadowKind::Variable,
+                ..
+            }]
+        ));
+
+        let (constraints, problems) = constraints_of("a -> a | a has Eq, a has Eq");
+        assert_eq!(constraints, vec![("a".into(), vec![eq])]);
+        assert!(matches!(
+            problems.as_slice(),
+            [Problem::Shadowing {
+                kind: ShadowKind::Variable,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn annotation_to_source_string_keeps_variable_names() {
+        use roc_module::symbol::{IdentIds, Interns, ModuleIds};
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        let annotation = fixture.canonicalize_str("a -> List a");
+
+        assert_eq!(fixture.env.problems, Vec::new());
+
+        let interns = Interns {
+            module_ids: ModuleIds::default(),
+            all_ident_ids: IdentIds::exposed_builtins(0),
+        };
+        assert_eq!(annotation.to_source_string(&interns), "a -> List a");
+    }
+
+    #[test]
+    fn annotation_diff_of_changed_reference() {
+        use roc_can::annotation::AnnotationDiff;
+        use roc_module::symbol::Symbol;
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        let email = fixture
+            .scope
+            .introduce("Email".into(), Region::zero())
+            .unwrap();
+
+        let prev = fixture.canonicalize_str("List Str");
+        let next = fixture.canonicalize_str("List Email");
+
+        assert_eq!(
+            next.diff(&prev),
+            AnnotationDiff {
+                added_references: vec![email],
+                removed_references: vec![Symbol::STR_STR],
+                changed_aliases: vec![],
+            }
+        );
+        assert!(next.diff(&next).is_empty());
+        assert_eq!(fixture.env.problems, Vec::new());
+    }
+
+    #[test]
+    fn structural_hashes_ignore_variable_numbering() {
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        let mut hash = |src| fixture.canonicalize_str(src).structural_hash();
+
+        // Each canonicalization numbers its variables differently...
+        let a_to_list_a = hash("a -> List a");
+        assert_eq!(a_to_list_a, hash("a -> List a"));
+
+        // ...but the hash still tells apart different types, references, and variable names.
+        let a_to_list_b = hash("a -> List b");
+        assert_ne!(a_to_list_a, a_to_list_b);
+        assert_ne!(a_to_list_a, hash("a -> List Str"));
+        assert_ne!(a_to_list_a, hash("b -> List b"));
+        assert_ne!(a_to_list_b, hash("b -> List a"));
+        assert_eq!(fixture.env.problems, Vec::new());
+    }
+
+    #[test]
+    fn inferred_variables_inherit_names_from_the_expected_type() {
+        use roc_can::annotation::canonicalize_annotation_with_expected;
+        use roc_collections::VecMap;
+        use roc_types::types::Type;
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        let expected = fixture.canonicalize_str("a, b -> List a");
+        let actual = fixture.parse("_, _ -> List Str");
+        let (actual, inherited_names) = canonicalize_annotation_with_expected(
+            &mut fixture.env,
+            &mut fixture.scope,
+            &actual.value,
+            actual.region,
+            &mut fixture.var_store,
+            &VecMap::default(),
+            Some(&expected),
+        );
+
+        let args = match &actual.typ {
+            Type::Function(args, _, _) => args,
+            other => panic!("expected a function, got {:?}", other),
+        };
+        let names: Vec<_> = (args.iter())
+            .map(|arg| match arg {
+                Type::Variable(var) => inherited_names.get(var).cloned(),
+                other => panic!("expected an inferred variable, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(names, vec![Some("a".into()), Some("b".into())]);
+        assert_eq!(fixture.env.problems, Vec::new());
+    }
+
+    #[test]
+    fn unrecognized_types_can_be_deferred() {
+        use roc_types::types::Type;
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+        fixture.env.defer_unrecognized_types = true;
+
+        let annotation = fixture.canonicalize_str("Widget -> Widget");
+
+        assert_eq!(fixture.env.problems, Vec::new());
+
+        let introduced = &annotation.introduced_variables;
+        let widget_var = *introduced.deferred_names.get(&"Widget".into()).unwrap();
+        assert_eq!(introduced.collect_flex(), vec![widget_var]);
+        assert!(matches!(
+            annotation.typ,
+            Type::Function(args, _, ret)
+                if args == vec![Type::Variable(widget_var)] && *ret == Type::Variable(widget_var)
+        ));
+    }
+
+    #[test]
+    fn arguments_of_deferred_types_are_canonicalized() {
+        use roc_types::types::{AliasKind, Type};
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+        fixture.env.defer_unrecognized_types = true;
+
+        // MyAlias : {}
+        let my_alias = fixture
+            .scope
+            .introduce("MyAlias".into(), Region::zero())
+            .unwrap();
+        fixture.scope.add_alias(
+            my_alias,
+            Region::zero(),
+            vec![],
+            Type::EmptyRec,
+            AliasKind::Structural,
+        );
+
+        let annotation = fixture.canonicalize_str("Foo MyAlias (Bar Str)");
+
+        assert_eq!(fixture.env.problems, Vec::new());
+        assert!(annotation.references.contains(&my_alias));
+
+        let deferred = &annotation.introduced_variables.deferred_names;
+        assert!(deferred.get(&"Foo".into()).is_some());
+        assert!(deferred.get(&"Bar".into()).is_some());
+    }
+
+    #[test]
+    fn as_aliases_can_be_kept_local() {
+        let arena = Bump::new();
+
+        let can_in_fresh_scope = |local_as_aliases| {
+            let mut fixture = AnnotationFixture::new(&arena);
+            fixture.env.local_as_aliases = local_as_aliases;
+
+            let annotation = fixture.canonicalize_str("(List a as Items a) -> Items a");
+
+            assert_eq!(fixture.env.problems, Vec::new());
+            assert_eq!(annotation.aliases.len(), 1);
+
+            let (symbol, _) = annotation.aliases.iter().next().unwrap();
+            (
+                fixture.scope.lookup_str("Items", Region::zero()).is_ok(),
+                fixture.scope.lookup_alias(*symbol).is_some(),
+            )
+        };
+
+        // By default, the alias stays in scope for the rest of the module...
+        assert_eq!(can_in_fresh_scope(false), (true, true));
+        // ...but it can be confined to the annotation that introduces it.
+        assert_eq!(can_in_fresh_scope(true), (false, false));
+    }
+
+    #[test]
+    fn aliases_can_be_expanded_eagerly() {
+        use roc_module::symbol::Symbol;
+        use roc_parse::ast::TypeAnnotation;
+        use roc_region::all::Loc;
+        use roc_types::types::{AliasCommon, AliasKind, AliasVar, Type, Variance};
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        // Wrap a : List a
+        let wrap = fixture
+            .scope
+            .introduce("Wrap".into(), Region::zero())
+            .unwrap();
+        let a = fixture.var_store.fresh();
+        fixture.scope.add_alias(
+            wrap,
+            Region::zero(),
+            vec![Loc::at_zero(AliasVar {
+                name: "a".into(),
+                var: a,
+                opt_bound_ability: None,
+                opt_default: None,
+                variance: Variance::Inferred,
+            })],
+            Type::Apply(Symbol::LIST_LIST, vec![Type::Variable(a)], Region::zero()),
+            AliasKind::Structural,
+        );
+
+        // Wrap Str
+        let str_ann = Loc::at_zero(TypeAnnotation::Apply("", "Str", &[]));
+        let wrap_str = TypeAnnotation::Apply("", "Wrap", arena.alloc([str_ann]));
+
+        let str_type = fixture.canonicalize(&str_ann.value).typ;
+
+        match fixture.canonicalize(&wrap_str).typ {
+            Type::DelayedAlias(AliasCommon {
+                symbol,
+                type_arguments,
+                ..
+            }) => {
+                assert_eq!(symbol, wrap);
+                assert_eq!(type_arguments, vec![str_type.clone()]);
+            }
+            other => panic!("expected a delayed alias, got {:?}", other),
+        }
+
+        fixture.env.expand_aliases_eagerly = true;
+
+        match fixture.canonicalize(&wrap_str).typ {
+            Type::Alias {
+                symbol,
+                type_arguments,
+                actual,
+                ..
+            } => {
+                assert_eq!(symbol, wrap);
+                assert_eq!(type_arguments.len(), 1);
+                assert_eq!(type_arguments[0].typ, str_type);
+                assert_eq!(
+                    *actual,
+                    Type::Apply(Symbol::LIST_LIST, vec![str_type], Region::zero())
+                );
+            }
+            other => panic!("expected an expanded alias, got {:?}", other),
+        }
+
+        assert_eq!(fixture.env.problems, Vec::new());
+    }
+
+    #[test]
+    fn declared_variances_are_kept_with_the_alias() {
+        use roc_collections::SendMap;
+        use roc_parse::ast::TypeAnnotation;
+        use roc_region::all::Loc;
+        use roc_types::types::{
+            AliasCommon, AliasKind, AliasVar, RecordField, Type, TypeExtension, Variance,
+        };
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        // Convert -a +b : { input : a, output : b }
+        let convert = fixture
+            .scope
+            .introduce("Convert".into(), Region::zero())
+            .unwrap();
+        let a = fixture.var_store.fresh();
+        let b = fixture.var_store.fresh();
+        let mut fields = SendMap::default();
+        fields.insert("input".into(), RecordField::Required(Type::Variable(a)));
+        fields.insert("output".into(), RecordField::Required(Type::Variable(b)));
+        fixture.scope.add_alias(
+            convert,
+            Region::zero(),
+            vec![
+                Loc::at_zero(AliasVar {
+                    variance: Variance::Contravariant,
+                    ..AliasVar::unbound("a".into(), a)
+                }),
+                Loc::at_zero(AliasVar {
+                    variance: Variance::Covariant,
+                    ..AliasVar::unbound("b".into(), b)
+                }),
+            ],
+            Type::Record(fields, TypeExtension::Closed),
+            AliasKind::Structural,
+        );
+
+        // Convert Str Str
+        let str_ann = Loc::at_zero(TypeAnnotation::Apply("", "Str", &[]));
+        let convert_str = TypeAnnotation::Apply("", "Convert", arena.alloc([str_ann, str_ann]));
+
+        let delayed = match fixture.canonicalize(&convert_str).typ {
+            Type::DelayedAlias(AliasCommon { symbol, .. }) => symbol,
+            other => panic!("expected a delayed alias, got {:?}", other),
+        };
+        fixture.env.expand_aliases_eagerly = true;
+        let expanded = match fixture.canonicalize(&convert_str).typ {
+            Type::Alias { symbol, .. } => symbol,
+            other => panic!("expected an expanded alias, got {:?}", other),
+        };
+
+        // Both forms of the instantiated alias lead back to the declared variances.
+        for symbol in [delayed, expanded] {
+            assert_eq!(
+                fixture.scope.lookup_alias(symbol).unwrap().variances(),
+                vec![Variance::Contravariant, Variance::Covariant]
+            );
+        }
+        assert_eq!(fixture.env.problems, Vec::new());
+    }
+
+    #[test]
+    fn shared_alias_bodies_instantiate_like_copied_ones() {
+        use roc_can::annotation::{
+            instantiate_and_freshen_alias_type, instantiate_shared_alias_type, IntroducedVariables,
+        };
+        use roc_module::ident::TagName;
+        use roc_module::symbol::Symbol;
+        use roc_region::all::Loc;
+        use roc_types::subs::VarStore;
+        use roc_types::types::{AliasVar, SharedAliasBody, Type, TypeExtension, Variance};
+
+        let mut var_store = VarStore::default();
+        let mut introduced_variables = IntroducedVariables::default();
+
+        // ConsList a : [Cons a ConsList a, Nil]
+        let a = var_store.fresh();
+        let rec_var = var_store.fresh();
+        let type_variables = vec![Loc::at_zero(AliasVar {
+            name: "a".into(),
+            var: a,
+            opt_bound_ability: None,
+            opt_default: None,
+            variance: Variance::Inferred,
+        })];
+        let body = Type::RecursiveTagUnion(
+            rec_var,
+            vec![
+                (
+                    TagName("Cons".into()),
+                    vec![Type::Variable(a), Type::Variable(rec_var)],
+                ),
+                (TagName("Nil".into()), vec![]),
+            ],
+            TypeExtension::Closed,
+        );
+        let shared_body = SharedAliasBody::new(body.clone());
+        let str_type = || vec![Type::Apply(Symbol::STR_STR, vec![], Region::zero())];
+
+        let checkpoint = var_store.checkpoint();
+        let (_, _, copied) = instantiate_and_freshen_alias_type(
+            &mut var_store,
+            &mut introduced_variables,
+            &type_variables,
+            str_type(),
+            &[],
+            body,
+        );
+
+        var_store.rollback(checkpoint);
+        let (_, _, first) = instantiate_shared_alias_type(
+            &mut var_store,
+            &mut introduced_variables,
+            &type_variables,
+            str_type(),
+            &[],
+            &shared_body,
+        );
+        let (_, _, second) = instantiate_shared_alias_type(
+            &mut var_store,
+            &mut introduced_variables,
+            &type_variables,
+            str_type(),
+            &[],
+            &shared_body,
+        );
+
+        assert_eq!(first.to_type(), copied);
+        // Each instantiation gets its own recursion variable, but they share one body.
+        assert_ne!(second.to_type(), copied);
+        assert!(std::ptr::eq(
+            first.body().as_type(),
+            second.body().as_type()
+        ));
+    }
+
+    #[test]
+    fn missing_type_arguments_can_be_inferred() {
+        use roc_module::symbol::Symbol;
+        use roc_parse::ast::TypeAnnotation;
+        use roc_region::all::Loc;
+        use roc_types::types::{
+            AliasCommon, AliasKind, AliasVar, Problem as TypeProblem, Type, Variance,
+        };
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        // Pair a b : List a
+        let pair = fixture
+            .scope
+            .introduce("Pair".into(), Region::zero())
+            .unwrap();
+        let alias_var = |name: &str, var| {
+            Loc::at_zero(AliasVar {
+                name: name.into(),
+                var,
+                opt_bound_ability: None,
+                opt_default: None,
+                variance: Variance::Inferred,
+            })
+        };
+        let a = fixture.var_store.fresh();
+        let b = fixture.var_store.fresh();
+        fixture.scope.add_alias(
+            pair,
+            Region::zero(),
+            vec![alias_var("a", a), alias_var("b", b)],
+            Type::Apply(Symbol::LIST_LIST, vec![Type::Variable(a)], Region::zero()),
+            AliasKind::Structural,
+        );
+
+        // Pair Str
+        let str_ann = Loc::at_zero(TypeAnnotation::Apply("", "Str", &[]));
+        let pair_str = TypeAnnotation::Apply("", "Pair", arena.alloc([str_ann]));
+
+        // By default, leaving out an argument is an error...
+        assert!(matches!(
+            fixture.canonicalize(&pair_str).typ,
+            Type::Erroneous(TypeProblem::BadTypeArguments {
+                alias_needs: 2,
+                type_got: 1,
+                ..
+            })
+        ));
+
+        // ...but it can be left for inference instead.
+        fixture.env.infer_missing_type_arguments = true;
+
+        let annotation = fixture.canonicalize(&pair_str);
+        match annotation.typ {
+            Type::DelayedAlias(AliasCommon {
+                symbol,
+                type_arguments,
+                ..
+            }) => {
+                assert_eq!(symbol, pair);
+                assert_eq!(
+                    type_arguments[0],
+                    Type::Apply(Symbol::STR_STR, vec![], Region::zero())
+                );
+                let inferred: Vec<_> = (annotation.introduced_variables.inferred.iter())
+                    .map(|var| Type::Variable(var.value))
+                    .collect();
+                assert_eq!(type_arguments[1..], inferred);
+            }
+            other => panic!("expected a delayed alias, got {:?}", other),
+        }
+
+        assert_eq!(fixture.env.problems, Vec::new());
+    }
+
+    #[test]
+    fn builtin_abilities_cannot_be_used_as_types() {
+        use roc_module::symbol::Symbol;
+        use roc_parse::ast::TypeAnnotation;
+        use roc_types::types::Type;
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        // Encode.Encoding
+        let annotation = fixture.canonicalize(&TypeAnnotation::Apply("Encode", "Encoding", &[]));
+
+        // The ability stands for a variable bound to it, rather than a type applied to nothing.
+        assert!(matches!(annotation.typ, Type::Variable(_)));
+        assert_eq!(
+            fixture.env.problems,
+            vec![Problem::AbilityUsedAsType(
+                "a".into(),
+                Symbol::ENCODE_ENCODING,
+                Region::zero()
+            )]
+        );
+        let able: Vec<_> = annotation.introduced_variables.able.iter().collect();
+        assert_eq!(able.len(), 1);
+        assert_eq!(able[0].ability, Symbol::ENCODE_ENCODING);
+    }
+
+    #[test]
+    fn uninhabited_annotations_are_reported() {
+        use roc_parse::ast::{Collection, Tag, TypeAnnotation};
+        use roc_region::all::Loc;
+        use roc_types::types::{AliasKind, Type};
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        // Never : []
+        let never = fixture
+            .scope
+            .introduce("Never".into(), Region::zero())
+            .unwrap();
+        fixture.scope.add_alias(
+            never,
+            Region::zero(),
+            vec![],
+            Type::EmptyTagUnion,
+            AliasKind::Structural,
+        );
+        // Marker := []
+        let marker = fixture
+            .scope
+            .introduce("Marker".into(), Region::zero())
+            .unwrap();
+        fixture.scope.add_alias(
+            marker,
+            Region::zero(),
+            vec![],
+            Type::EmptyTagUnion,
+            AliasKind::Opaque,
+        );
+
+        let empty = || TypeAnnotation::TagUnion {
+            tags: Collection::empty(),
+            ext: None,
+        };
+        let a_empty = |ext| TypeAnnotation::TagUnion {
+            tags: Collection::with_items(arena.alloc([Loc::at_zero(Tag::Apply {
+                name: Loc::at_zero("A"),
+                args: arena.alloc([Loc::at_zero(empty())]),
+            })])),
+            ext,
+        };
+        let empty_to_str = TypeAnnotation::Function(
+            arena.alloc([Loc::at_zero(empty())]),
+            arena.alloc(Loc::at_zero(TypeAnnotation::Apply("", "Str", &[]))),
+        );
+        let wildcard = arena.alloc(Loc::at_zero(TypeAnnotation::Wildcard));
+
+        let region = Region::new(Position::new(4), Position::new(6));
+        let cases = [
+            // []
+            (empty(), true),
+            // Never
+            (TypeAnnotation::Apply("", "Never", &[]), true),
+            // Marker, which is only ever used as a phantom type
+            (TypeAnnotation::Apply("", "Marker", &[]), false),
+            // [A []]
+            (a_empty(None), true),
+            // [A []]*
+            (a_empty(Some(wildcard)), false),
+            // [] -> Str
+            (empty_to_str, false),
+        ];
+
+        for (annotation, uninhabited) in cases {
+            fixture.canonicalize_at(&annotation, region);
+
+            let expected = if uninhabited {
+                vec![Problem::UninhabitedType { region }]
+            } else {
+                vec![]
+            };
+            assert_eq!(fixture.take_problems(), expected, "{:?}", annotation);
+        }
+    }
+
+    #[test]
+    fn empty_type_definitions_are_not_reported_as_uninhabited() {
+        let src = indoc!(
+            r#"
+                Marker := []
+                Never : []
+
+                markers : List Marker
+                markers = []
+
+                nevers : List Never
+                nevers = []
+
+                { markers, nevers }
+            "#
+        );
+        let arena = Bump::new();
+        let CanExprOut { problems, .. } = can_expr_with(&arena, test_home(), src);
+
+        assert_eq!(problems, Vec::new());
+    }
+
+    #[test]
+    fn builtin_types_can_be_restricted_to_an_allowlist() {
+        use roc_collections::VecSet;
+        use roc_module::symbol::Symbol;
+        use roc_types::types::{Problem as TypeProblem, Type};
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+        let allowed = VecSet::from_iter([Symbol::STR_STR, Symbol::BOX_BOX_TYPE]);
+        fixture.env.allowed_builtin_types = Some(&allowed);
+
+        let annotation = fixture.canonicalize_str("{ name : Str, avatar : Box Str }");
+
+        assert!(matches!(annotation.typ, Type::Record(..)));
+        assert_eq!(fixture.env.problems, Vec::new());
+
+        let annotation = fixture.canonicalize_str("List Str");
+        let list_region = Region::new(Position::new(0), Position::new(8));
+
+        assert_eq!(
+            annotation.typ,
+            Type::Erroneous(TypeProblem::CanonicalizationProblem)
+        );
+        assert_eq!(
+            fixture.env.problems,
+            vec![Problem::TypeNotAllowedInContext {
+                symbol: Symbol::LIST_LIST,
+                region: list_region,
+            }]
+        );
+    }
+
+    #[test]
+    fn symbol_policy_can_forbid_types() {
+        use roc_module::symbol::Symbol;
+        use roc_types::types::{Problem as TypeProblem, Type};
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+        let no_lists = |symbol| symbol != Symbol::LIST_LIST;
+        fixture.env.symbol_policy = Some(&no_lists);
+
+        let annotation = fixture.canonicalize_str("List Str");
+        let list_region = Region::new(Position::new(0), Position::new(8));
+
+        assert_eq!(
+            annotation.typ,
+            Type::Erroneous(TypeProblem::CanonicalizationProblem)
+        );
+        assert_eq!(
+            fixture.env.problems,
+            vec![Problem::DisallowedTypeReference {
+                symbol: Symbol::LIST_LIST,
+                region: list_region,
+            }]
+        );
+
+        // Str on its own is fine.
+        fixture.env.problems.clear();
+        let annotation = fixture.canonicalize_str("Str");
+
+        assert!(!matches!(annotation.typ, Type::Erroneous(_)));
+        assert_eq!(fixture.env.problems, Vec::new());
+    }
+
+    #[test]
+    fn foreign_opaques_must_be_reexported_when_asked() {
+        use roc_collections::VecSet;
+        use roc_module::symbol::{IdentIds, ModuleIds, Symbol};
+        use roc_types::types::{AliasKind, Problem as TypeProblem, Type};
+
+        let arena = Bump::new();
+        let mut module_ids = ModuleIds::default();
+        let home = module_ids.get_or_insert(&"Test".into());
+        let email_module = module_ids.get_or_insert(&"Email".into());
+        let mut fixture = AnnotationFixture::with_module_ids(&arena, home, module_ids);
+
+        // imports [Email.{ Email }], where Email := Str
+        let email = Symbol::new(email_module, IdentIds::default().add_str("Email"));
+        fixture
+            .scope
+            .import("Email".into(), email, Region::zero())
+            .unwrap();
+        let str = Type::Apply(Symbol::STR_STR, vec![], Region::zero());
+        (fixture.scope).add_alias(email, Region::zero(), vec![], str, AliasKind::Opaque);
+
+        let mut canonicalize = |reexported| {
+            fixture.env.reexported_opaques = reexported;
+            let annotation = fixture.canonicalize_str("Email");
+            (annotation.typ, fixture.take_problems())
+        };
+        let region = Region::new(Position::new(0), Position::new(5));
+
+        let (typ, problems) = canonicalize(None);
+        assert!(!matches!(typ, Type::Erroneous(_)));
+        assert_eq!(problems, Vec::new());
+
+        let none = VecSet::default();
+        let (typ, problems) = canonicalize(Some(&none));
+        assert_eq!(typ, Type::Erroneous(TypeProblem::CanonicalizationProblem));
+        assert_eq!(
+            problems,
+            vec![Problem::OpaqueNotExported {
+                opaque: email,
+                region,
+            }]
+        );
+
+        let only_email = VecSet::from_iter([email]);
+        let (typ, problems) = canonicalize(Some(&only_email));
+        assert!(!matches!(typ, Type::Erroneous(_)));
+        assert_eq!(problems, Vec::new());
+    }
+
+    #[test]
+    fn abstract_platform_types_are_applied_by_name() {
+        use roc_module::symbol::{IdentIds, ModuleIds, Symbol};
+        use roc_types::types::Type;
+
+        let arena = Bump::new();
+        let mut module_ids = ModuleIds::default();
+        let home = module_ids.get_or_insert(&"Test".into());
+        let platform_module = module_ids.get_or_insert(&"Platform".into());
+        let mut fixture = AnnotationFixture::with_module_ids(&arena, home, module_ids);
+
+        // imports [Platform.{ Handle }], where the platform doesn't say what a `Handle` is
+        let handle = Symbol::new(platform_module, IdentIds::default().add_str("Handle"));
+        fixture
+            .scope
+            .import("Handle".into(), handle, Region::zero())
+            .unwrap();
+
+        let annotation = fixture.canonicalize_str("Handle Str");
+
+        let str_region = Region::new(Position::new(7), Position::new(10));
+        assert_eq!(
+            annotation.typ,
+            Type::Apply(
+                handle,
+                vec![Type::Apply(Symbol::STR_STR, vec![], str_region)],
+                Region::new(Position::new(0), Position::new(10))
+            )
+        );
+        assert_eq!(fixture.env.problems, Vec::new());
+        assert!(annotation.references.contains(&handle));
+    }
+
+    #[test]
+    fn named_variables_in_scope_at_a_cursor() {
+        use roc_module::ident::Lowercase;
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        // The cursor is at the `_`.
+        let annotation = fixture.canonicalize_str("a, _ -> b");
+        let cursor = Region::new(Position::new(3), Position::new(4));
+        let introduced_variables = &annotation.introduced_variables;
+
+        let a: Lowercase = "a".into();
+        let b: Lowercase = "b".into();
+        assert_eq!(introduced_variables.named_in_scope_at(cursor), vec![&a]);
+        assert_eq!(
+            introduced_variables.named_in_scope_at(Region::from_pos(Position::new(9))),
+            vec![&a, &b]
+        );
+    }
+
+    #[test]
+    fn var_store_can_be_rolled_back_between_annotations() {
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        let checkpoint = fixture.var_store.checkpoint();
+        let first = fixture.canonicalize_str("a -> b").typ;
+        let after_first = fixture.var_store.peek();
+        fixture.var_store.rollback(checkpoint);
+        let second = fixture.canonicalize_str("a -> b").typ;
+
+        // The second annotation reused the variables of the first, which was thrown away.
+        assert_eq!(first, second);
+        assert_eq!(fixture.var_store.peek(), after_first);
+
+        // Without a rollback, the next annotation gets new variables.
+        let third = fixture.canonicalize_str("a -> b").typ;
+        assert_ne!(second, third);
+    }
+
+    #[test]
+    fn renamed_annotations_do_not_depend_on_the_var_store() {
+        use roc_collections::MutMap;
+        use roc_parse::ast::{AssignedField, Collection, TypeAnnotation};
+        use roc_region::all::Loc;
+        use roc_types::subs::VarStore;
+
+        let arena = Bump::new();
+
+        // { x : a, y : b }* -> List _
+        let field = |name, var| {
+            Loc::at_zero(AssignedField::RequiredValue(
+                Loc::at_zero(name),
+                &[],
+                arena.alloc(Loc::at_zero(TypeAnnotation::BoundVariable(var))),
+            ))
+        };
+        let record = Loc::at_zero(TypeAnnotation::Record {
+            fields: Collection::with_items(arena.alloc([field("x", "a"), field("y", "b")])),
+            ext: Some(arena.alloc(Loc::at_zero(TypeAnnotation::Wildcard))),
+        });
+        let list = Loc::at_zero(TypeAnnotation::Apply(
+            "",
+            "List",
+            arena.alloc([Loc::at_zero(TypeAnnotation::Inferred)]),
+        ));
+        let annotation = TypeAnnotation::Function(arena.alloc([record]), arena.alloc(list));
+
+        let can_renamed = |offset| {
+            let mut fixture = AnnotationFixture::new(&arena);
+            for _ in 0..offset {
+                fixture.var_store.fresh();
+            }
+
+            let annotation = fixture.canonicalize(&annotation);
+
+            let mut renaming = MutMap::default();
+            let mut var_store = VarStore::default();
+            let typ = annotation
+                .typ
+                .rename_all_variables(&mut renaming, &mut var_store);
+            let introduced_variables = annotation
+                .introduced_variables
+                .rename_all_variables(&mut renaming, &mut var_store);
+
+            (typ, introduced_variables)
+        };
+
+        let (typ1, introduced1) = can_renamed(0);
+        let (typ2, introduced2) = can_renamed(100);
+
+        assert_eq!(typ1, typ2);
+        assert_eq!(introduced1.collect_rigid(), introduced2.collect_rigid());
+        assert_eq!(introduced1.collect_flex(), introduced2.collect_flex());
+        assert_eq!(
+            introduced1.named.iter().collect::<Vec<_>>(),
+            introduced2.named.iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn wildcards_report_what_they_were_solved_to() {
+        use roc_module::symbol::Symbol;
+        use roc_types::subs::{Content, FlatType, Subs, VarStore, Variable};
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+        let mut subs = Subs::new();
+        fixture.var_store = VarStore::new_from_subs(&subs);
+
+        let annotation = fixture.canonicalize_str("* -> *");
+
+        while subs.len() < fixture.var_store.peek() as usize {
+            subs.fresh_unnamed_flex_var();
+        }
+
+        // Solving a call like `f "hello"` makes the argument a `Str`, while nothing is learned
+        // about the return type.
+        let arg_var = annotation.introduced_variables.wildcards[0].value;
+        let str_desc = subs.get_without_compacting(Variable::STR);
+        subs.union(arg_var, Variable::STR, str_desc);
+
+        let solved = annotation.solved_wildcards(&subs);
+
+        assert_eq!(solved.len(), 2);
+        assert_eq!(solved[0].0, Region::new(Position::new(0), Position::new(1)));
+        assert!(matches!(
+            solved[0].1,
+            Content::Structure(FlatType::Apply(Symbol::STR_STR, _))
+        ));
+        assert_eq!(solved[1].0, Region::new(Position::new(5), Position::new(6)));
+        assert!(matches!(solved[1].1, Content::FlexVar(_)));
+    }
+
+    #[test]
+    fn types_qualified_with_the_home_module_resolve() {
+        use roc_module::symbol::{ModuleIds, Symbol};
+        use roc_parse::ast::TypeAnnotation;
+        use roc_types::types::{AliasKind, Type};
+
+        let arena = Bump::new();
+        let mut module_ids = ModuleIds::default();
+        let home = module_ids.get_or_insert(&"MyModule".into());
+        let mut fixture = AnnotationFixture::with_module_ids(&arena, home, module_ids);
+
+        // Foo : Str
+        let foo = fixture
+            .scope
+            .introduce("Foo".into(), Region::zero())
+            .unwrap();
+        fixture.scope.add_alias(
+            foo,
+            Region::zero(),
+            vec![],
+            Type::Apply(Symbol::STR_STR, vec![], Region::zero()),
+            AliasKind::Structural,
+        );
+
+        let unqualified = fixture.canonicalize(&TypeAnnotation::Apply("", "Foo", &[]));
+        let qualified = fixture.canonicalize(&TypeAnnotation::Apply("MyModule", "Foo", &[]));
+
+        assert_eq!(unqualified.references, qualified.references);
+        assert!(qualified.references.contains(&foo));
+        assert_eq!(unqualified.typ, qualified.typ);
+        assert_eq!(fixture.env.problems, Vec::new());
+    }
+
+    #[test]
+    fn record_fields_can_be_projected() {
+        use roc_can::annotation::project_record_field;
+        use roc_module::symbol::Symbol;
+        use roc_parse::ast::{AssignedField, Collection, TypeAnnotation};
+        use roc_region::all::Loc;
+        use roc_types::types::{Problem as TypeProblem, Type};
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        // { name : Str }
+        let str = Loc::at_zero(TypeAnnotation::Apply("", "Str", &[]));
+        let name = Loc::at_zero(AssignedField::RequiredValue(
+            Loc::at_zero("name"),
+            &[],
+            &str,
+        ));
+        let record = TypeAnnotation::Record {
+            fields: Collection::with_items(arena.alloc([name])),
+            ext: None,
+        };
+        let record = fixture.canonicalize(&record).typ;
+
+        // .name
+        assert_eq!(
+            project_record_field(&mut fixture.env, &record, &"name".into(), Region::zero()),
+            Type::Apply(Symbol::STR_STR, vec![], Region::zero())
+        );
+        assert_eq!(fixture.env.problems, Vec::new());
+
+        // .age
+        let region = Region::new(Position::new(15), Position::new(19));
+        assert_eq!(
+            project_record_field(&mut fixture.env, &record, &"age".into(), region),
+            Type::Erroneous(TypeProblem::CanonicalizationProblem)
+        );
+        assert_eq!(
+            fixture.env.problems,
+            vec![Problem::FieldNotInRecord {
+                field: "age".into(),
+                region,
+            }]
+        );
+    }
+
+    #[test]
+    fn annotation_from_parts() {
+        use roc_can::annotation::{Annotation, IntroducedVariables};
+        use roc_collections::{VecMap, VecSet};
+        use roc_region::all::Loc;
+        use roc_types::subs::VarStore;
+        use roc_types::types::Type;
+
+        let mut var_store = VarStore::default();
+        let mut introduced_variables = IntroducedVariables::default();
+        let a = var_store.fresh();
+        introduced_variables.insert_named("a".into(), Loc::at_zero(a));
+
+        let annotation = Annotation::new(
+            Type::Variable(a),
+            introduced_variables,
+            VecSet::default(),
+            VecMap::default(),
+        );
+
+        assert_eq!(annotation.typ, Type::Variable(a));
+        assert_eq!(annotation.free_rigids_by_name(), vec![("a".into(), a)]);
+        assert!(annotation.phantom_variables.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Two named variables share a name")]
+    #[cfg(debug_assertions)]
+    fn annotation_from_parts_rejects_duplicate_names() {
+        use roc_can::annotation::{Annotation, IntroducedVariables};
+        use roc_collections::{VecMap, VecSet};
+        use roc_region::all::Loc;
+        use roc_types::subs::VarStore;
+        use roc_types::types::Type;
+
+        let mut var_store = VarStore::default();
+        let mut introduced_variables = IntroducedVariables::default();
+        introduced_variables.insert_named("a".into(), Loc::at_zero(var_store.fresh()));
+        introduced_variables.insert_named("a".into(), Loc::at_zero(var_store.fresh()));
+
+        Annotation::new(
+            Type::EmptyRec,
+            introduced_variables,
+            VecSet::default(),
+            VecMap::default(),
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn consistent_introduced_variables() {
+        use roc_can::annotation::IntroducedVariables;
+        use roc_region::all::Loc;
+        use roc_types::subs::VarStore;
+
+        let mut var_store = VarStore::default();
+        let mut introduced_variables = IntroducedVariables::default();
+        introduced_variables.insert_named("a".into(), Loc::at_zero(var_store.fresh()));
+        introduced_variables.insert_wildcard(Loc::at_zero(var_store.fresh()));
+        introduced_variables.insert_lambda_set(var_store.fresh());
+
+        introduced_variables.assert_consistent();
+    }
+
+    #[test]
+    #[should_panic(expected = "is introduced more than once")]
+    #[cfg(debug_assertions)]
+    fn corrupt_introduced_variables_are_caught() {
+        use roc_can::annotation::IntroducedVariables;
+        use roc_region::all::Loc;
+        use roc_types::subs::VarStore;
+
+        let mut var_store = VarStore::default();
+        let mut introduced_variables = IntroducedVariables::default();
+        let var = var_store.fresh();
+        introduced_variables.insert_wildcard(Loc::at_zero(var));
+        // Sneak past the checks done on insertion.
+        introduced_variables.inferred.push(Loc::at_zero(var));
+
+        introduced_variables.assert_consistent();
+    }
+
+    #[test]
+    fn explicitly_closed_extensions_are_closed() {
+        use roc_parse::ast::{AssignedField, Collection, Tag, TypeAnnotation};
+        use roc_region::all::Loc;
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        let empty_record = |ext| TypeAnnotation::Record {
+            fields: Collection::empty(),
+            ext,
+        };
+        let empty_tag_union = |ext| TypeAnnotation::TagUnion {
+            tags: Collection::empty(),
+            ext,
+        };
+        let x_str = |ext| TypeAnnotation::Record {
+            fields: Collection::with_items(arena.alloc([Loc::at_zero(
+                AssignedField::RequiredValue(
+                    Loc::at_zero("x"),
+                    &[],
+                    arena.alloc(Loc::at_zero(TypeAnnotation::Apply("", "Str", &[]))),
+                ),
+            )])),
+            ext,
+        };
+        let a_tag = |ext| TypeAnnotation::TagUnion {
+            tags: Collection::with_items(arena.alloc([Loc::at_zero(Tag::Apply {
+                name: Loc::at_zero("A"),
+                args: &[],
+            })])),
+            ext,
+        };
+
+        let closed_record = arena.alloc(Loc::at_zero(empty_record(None)));
+        let closed_record_twice = arena.alloc(Loc::at_zero(empty_record(Some(closed_record))));
+        let closed_tag_union = arena.alloc(Loc::at_zero(empty_tag_union(None)));
+        let closed_tag_union_twice =
+            arena.alloc(Loc::at_zero(empty_tag_union(Some(closed_tag_union))));
+
+        let mut can = |annotation: &TypeAnnotation| fixture.canonicalize(annotation).typ;
+
+        // { x : Str }{} and { x : Str }({}{}) are { x : Str }
+        let x_str_closed = can(&x_str(None));
+        assert_eq!(can(&x_str(Some(closed_record))), x_str_closed);
+        assert_eq!(can(&x_str(Some(closed_record_twice))), x_str_closed);
+
+        // [A][] and [A]([][]) are [A]
+        let a_tag_closed = can(&a_tag(None));
+        assert_eq!(can(&a_tag(Some(closed_tag_union))), a_tag_closed);
+        assert_eq!(can(&a_tag(Some(closed_tag_union_twice))), a_tag_closed);
+
+        assert_eq!(fixture.env.problems, Vec::new());
+    }
+
+    #[test]
+    fn inferred_record_extension_is_anonymous_and_open() {
+        use roc_parse::ast::{AssignedField, Collection, TypeAnnotation};
+        use roc_region::all::Loc;
+        use roc_types::types::{Type, TypeExtension};
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        // { x : Str }_
+        let annotation = TypeAnnotation::Record {
+            fields: Collection::with_items(arena.alloc([Loc::new(
+                2,
+                9,
+                AssignedField::RequiredValue(
+                    Loc::new(2, 3, "x"),
+                    &[],
+                    arena.alloc(Loc::new(6, 9, TypeAnnotation::Apply("", "Str", &[]))),
+                ),
+            )])),
+            ext: Some(arena.alloc(Loc::new(11, 12, TypeAnnotation::Inferred))),
+        };
+
+        let annotation = fixture.canonicalize_at(
+            &annotation,
+            Region::new(Position::new(0), Position::new(12)),
+        );
+
+        assert_eq!(fixture.env.problems, Vec::new());
+        assert!(annotation.introduced_variables.named.is_empty());
+
+        let inferred = &annotation.introduced_variables.inferred;
+        assert_eq!(inferred.len(), 1);
+        assert_eq!(
+            inferred[0].region,
+            Region::new(Position::new(11), Position::new(12))
+        );
+
+        match annotation.typ {
+            Type::Record(fields, TypeExtension::Open(ext)) => {
+                assert_eq!(fields.len(), 1);
+                assert_eq!(*ext, Type::Variable(inferred[0].value));
+            }
+            other => panic!("expected an open record, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn extension_types_are_validated() {
+        use roc_collections::SendMap;
+        use roc_module::ident::TagName;
+        use roc_module::symbol::Symbol;
+        use roc_parse::ast::{AssignedField, Collection, Tag, TypeAnnotation};
+        use roc_problem::can::ExtensionTypeKind;
+        use roc_region::all::Loc;
+        use roc_types::types::{AliasKind, RecordField, Type, TypeExtension};
+
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        enum Ext {
+            Open,
+            Closed,
+            Invalid,
+        }
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        let mut add_alias = |name: &str, typ: Type, kind: AliasKind| {
+            let symbol = fixture
+                .scope
+                .introduce(name.into(), Region::zero())
+                .unwrap();
+            fixture
+                .scope
+                .add_alias(symbol, Region::zero(), vec![], typ, kind);
+            symbol
+        };
+        let alias_of = |symbol: Symbol| {
+            Type::DelayedAlias(roc_types::types::AliasCommon {
+                symbol,
+                type_arguments: vec![],
+                lambda_set_variables: vec![],
+            })
+        };
+        let y_str = || {
+            let mut fields = SendMap::default();
+            fields.insert(
+                "y".into(),
+                RecordField::Required(Type::Apply(Symbol::STR_STR, vec![], Region::zero())),
+            );
+            Type::Record(fields, TypeExtension::Closed)
+        };
+        let b_tag = || Type::TagUnion(vec![(TagName("B".into()), vec![])], TypeExtension::Closed);
+
+        // Rec : { y : Str }
+        // Chain : Rec
+        // Empty : {}
+        // NotRec : Str
+        // BadChain : NotRec
+        // Tags : [B]
+        // TagsChain : Tags
+        // EmptyTags : []
+        // OpRec := { y : Str }
+        // OpTags := [B]
+        let rec = add_alias("Rec", y_str(), AliasKind::Structural);
+        add_alias("Chain", alias_of(rec), AliasKind::Structural);
+        add_alias("Empty", Type::EmptyRec, AliasKind::Structural);
+        let not_rec = add_alias(
+            "NotRec",
+            Type::Apply(Symbol::STR_STR, vec![], Region::zero()),
+            AliasKind::Structural,
+        );
+        add_alias("BadChain", alias_of(not_rec), AliasKind::Structural);
+        let tags = add_alias("Tags", b_tag(), AliasKind::Structural);
+        add_alias("TagsChain", alias_of(tags), AliasKind::Structural);
+        add_alias("EmptyTags", Type::EmptyTagUnion, AliasKind::Structural);
+        add_alias("OpRec", y_str(), AliasKind::Opaque);
+        add_alias("OpTags", b_tag(), AliasKind::Opaque);
+
+        let str_ann = || TypeAnnotation::Apply("", "Str", &[]);
+        let named = |name| TypeAnnotation::Apply("", name, &[]);
+        let str_to_str = TypeAnnotation::Function(
+            arena.alloc([Loc::at_zero(str_ann())]),
+            arena.alloc(Loc::at_zero(str_ann())),
+        );
+
+        // (extension, as a record extension, as a tag union extension)
+        let cases = [
+            (TypeAnnotation::BoundVariable("a"), Ext::Open, Ext::Open),
+            (TypeAnnotation::Wildcard, Ext::Open, Ext::Open),
+            (named("Rec"), Ext::Open, Ext::Invalid),
+            (named("Chain"), Ext::Open, Ext::Invalid),
+            (named("Empty"), Ext::Closed, Ext::Invalid),
+            (named("Tags"), Ext::Invalid, Ext::Open),
+            (named("TagsChain"), Ext::Invalid, Ext::Open),
+            (named("EmptyTags"), Ext::Invalid, Ext::Closed),
+            (str_ann(), Ext::Invalid, Ext::Invalid),
+            (named("NotRec"), Ext::Invalid, Ext::Invalid),
+            (named("BadChain"), Ext::Invalid, Ext::Invalid),
+            (str_to_str, Ext::Invalid, Ext::Invalid),
+            (named("OpRec"), Ext::Invalid, Ext::Invalid),
+            (named("OpTags"), Ext::Invalid, Ext::Invalid),
+        ];
+
+        // `{ x : Str }` and `[A]` followed by the extension
+        let ext_region = Region::new(Position::new(11), Position::new(14));
+        let record = |ext| TypeAnnotation::Record {
+            fields: Collection::with_items(arena.alloc([Loc::at_zero(
+                AssignedField::RequiredValue(
+                    Loc::at_zero("x"),
+                    &[],
+                    arena.alloc(Loc::at_zero(str_ann())),
+                ),
+            )])),
+            ext: Some(arena.alloc(Loc::at(ext_region, ext))),
+        };
+        let tag_union = |ext| TypeAnnotation::TagUnion {
+            tags: Collection::with_items(arena.alloc([Loc::at_zero(Tag::Apply {
+                name: Loc::at_zero("A"),
+                args: &[],
+            })])),
+            ext: Some(arena.alloc(Loc::at(ext_region, ext))),
+        };
+
+        for (ext, as_record, as_tag_union) in cases {
+            for (annotation, expected, kind) in [
+                (record(ext), as_record, ExtensionTypeKind::Record),
+                (tag_union(ext), as_tag_union, ExtensionTypeKind::TagUnion),
+            ] {
+                let annotation = fixture.canonicalize(&annotation);
+                let problems = fixture.take_problems();
+
+                let ext = match annotation.typ {
+                    Type::Record(_, ext) | Type::TagUnion(_, ext) => ext,
+                    other => panic!("expected a record or tag union, got {:?}", other),
+                };
+
+                match (expected, ext) {
+                    (Ext::Open, TypeExtension::Open(_)) | (Ext::Closed, TypeExtension::Closed) => {
+                        assert_eq!(
+                            problems,
+                            Vec::new(),
+                            "{:?} extension as a {:?}",
+                            expected,
+                            kind
+                        );
+                    }
+                    (Ext::Invalid, TypeExtension::Open(ext_type)) => {
+                        // The extension is reported, then inferred as permissively as possible.
+                        assert_eq!(
+                            problems,
+                            vec![Problem::InvalidExtensionType {
+                                region: ext_region,
+                                kind: kind.clone(),
+                            }],
+                        );
+                        assert!(matches!(
+                            *ext_type,
+                            Type::Variable(var)
+                                if annotation.introduced_variables.inferred.iter().any(|v| v.value == var)
+                        ));
+                    }
+                    (expected, ext) => panic!(
+                        "expected {:?} extension as a {:?}, got {:?}",
+                        expected,
+                        kind,
+                        Type::Record(Default::default(), ext)
+                    ),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn degenerate_records_and_tag_unions() {
+        use roc_module::ident::TagName;
+        use roc_module::symbol::Symbol;
+        use roc_parse::ast::{AssignedField, Collection, CommentOrNewline, Tag, TypeAnnotation};
+        use roc_problem::can::RuntimeError;
+        use roc_region::all::Loc;
+        use roc_types::types::{Problem as TypeProblem, RecordField, Type, TypeExtension};
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        let region = Region::new(Position::new(0), Position::new(12));
+        let mut can = |annotation: &TypeAnnotation| {
+            let typ = fixture.canonicalize_at(annotation, region).typ;
+
+            (typ, fixture.take_problems())
+        };
+        let malformed = |name: &str| {
+            Problem::RuntimeError(RuntimeError::MalformedTypeName(name.into(), region))
+        };
+        let newline: &[_] = arena.alloc([CommentOrNewline::Newline]);
+
+        // { a : Str
+        // }
+        let a_str = AssignedField::RequiredValue(
+            Loc::at_zero("a"),
+            &[],
+            arena.alloc(Loc::at_zero(TypeAnnotation::Apply("", "Str", &[]))),
+        );
+        let trailing_space = TypeAnnotation::Record {
+            fields: Collection::with_items(arena.alloc([Loc::at_zero(AssignedField::SpaceAfter(
+                arena.alloc(a_str),
+                newline,
+            ))])),
+            ext: None,
+        };
+        let (typ, problems) = can(&trailing_space);
+        match typ {
+            Type::Record(fields, TypeExtension::Closed) => {
+                assert_eq!(fields.len(), 1);
+                assert_eq!(
+                    fields.get(&"a".into()),
+                    Some(&RecordField::Required(Type::Apply(
+                        Symbol::STR_STR,
+                        vec![],
+                        Region::zero()
+                    )))
+                );
+            }
+            other => panic!("expected a closed record, got {:?}", other),
+        }
+        assert_eq!(problems, Vec::new());
+
+        // A record where every field is malformed is erroneous, with one problem per field.
+        let all_malformed_fields = TypeAnnotation::Record {
+            fields: Collection::with_items(arena.alloc([
+                Loc::at_zero(AssignedField::Malformed("A")),
+                Loc::at_zero(AssignedField::SpaceBefore(
+                    arena.alloc(AssignedField::Malformed("b c")),
+                    newline,
+                )),
+            ])),
+            ext: None,
+        };
+        assert_eq!(
+            can(&all_malformed_fields),
+            (
+                Type::Erroneous(TypeProblem::CanonicalizationProblem),
+                vec![malformed("A"), malformed("b c")]
+            )
+        );
+
+        // A tag union whose only tag is malformed is erroneous too, rather than having no tags.
+        let only_malformed_tag = TypeAnnotation::TagUnion {
+            tags: Collection::with_items(arena.alloc([Loc::at_zero(Tag::Malformed("a"))])),
+            ext: None,
+        };
+        assert_eq!(
+            can(&only_malformed_tag),
+            (
+                Type::Erroneous(TypeProblem::CanonicalizationProblem),
+                vec![malformed("a")]
+            )
+        );
+
+        // The well-formed tags of a tag union are kept.
+        let one_malformed_tag = TypeAnnotation::TagUnion {
+            tags: Collection::with_items(arena.alloc([
+                Loc::at_zero(Tag::SpaceAfter(
+                    arena.alloc(Tag::Apply {
+                        name: Loc::at_zero("A"),
+                        args: &[],
+                    }),
+                    newline,
+                )),
+                Loc::at_zero(Tag::Malformed("b")),
+            ])),
+            ext: None,
+        };
+        assert_eq!(
+            can(&one_malformed_tag),
+            (
+                Type::TagUnion(vec![(TagName("A".into()), vec![])], TypeExtension::Closed),
+                vec![malformed("b")]
+            )
+        );
+    }
+
+    #[test]
+    fn unrecognized_nested_apply_keeps_its_region() {
+        use roc_module::symbol::Symbol;
+        use roc_parse::ast::TypeAnnotation;
+        use roc_region::all::Loc;
+        use roc_types::types::{Problem, Type};
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        // List (Foo Str)
+        let foo_str = Loc::new(
+            6,
+            13,
+            TypeAnnotation::Apply(
+                "",
+                "Foo",
+                arena.alloc([Loc::new(10, 13, TypeAnnotation::Apply("", "Str", &[]))]),
+            ),
+        );
+        let annotation = TypeAnnotation::Apply("", "List", arena.alloc([foo_str]));
+
+        let annotation = fixture.canonicalize_at(
+            &annotation,
+            Region::new(Position::new(0), Position::new(14)),
+        );
+
+        match annotation.typ {
+            Type::Apply(Symbol::LIST_LIST, args, _) => assert_eq!(
+                args,
+                vec![Type::Erroneous(Problem::UnrecognizedIdent(Loc::new(
+                    6,
+                    13,
+                    "Foo".into()
+                )))]
+            ),
+            other => panic!("expected a list, got {:?}", other),
+        }
+    }
+
+    fn can_annotation_a_to_b(arena: &Bump) -> roc_can::annotation::Annotation {
+        let mut fixture = AnnotationFixture::new(arena);
+        let annotation = fixture.canonicalize_str("a -> b");
+
+        assert_eq!(fixture.env.problems, Vec::new());
+
+        annotation
+    }
+
+    #[test]
+    fn rename_rigid_to_concrete_type() {
+        use roc_types::types::Type;
+
+        let arena = Bump::new();
+        let mut annotation = can_annotation_a_to_b(&arena);
+        let introduced = &annotation.introduced_variables;
+        let b = introduced.var_by_name(&"b".into()).unwrap();
+        let closure = introduced.lambda_sets[0];
+
+        annotation.rename_rigid(&"a".into(), Type::EmptyRec);
+
+        assert_eq!(
+            annotation.typ,
+            Type::Function(
+                vec![Type::EmptyRec],
+                Box::new(Type::Variable(closure)),
+                Box::new(Type::Variable(b)),
+            )
+        );
+        assert_eq!(
+            annotation.introduced_variables.var_by_name(&"a".into()),
+            None
+        );
+        assert_eq!(
+            annotation.introduced_variables.var_by_name(&"b".into()),
+            Some(b)
+        );
+    }
+
+    #[test]
+    fn rename_rigid_to_other_variable() {
+        use roc_types::types::Type;
+
+        let arena = Bump::new();
+        let mut annotation = can_annotation_a_to_b(&arena);
+        let introduced = &annotation.introduced_variables;
+        let b = introduced.var_by_name(&"b".into()).unwrap();
+        let closure = introduced.lambda_sets[0];
+
+        annotation.rename_rigid(&"a".into(), Type::Variable(b));
+
+        assert_eq!(
+            annotation.typ,
+            Type::Function(
+                vec![Type::Variable(b)],
+                Box::new(Type::Variable(closure)),
+                Box::new(Type::Variable(b)),
+            )
+        );
+        assert_eq!(
+            annotation.introduced_variables.var_by_name(&"a".into()),
+            None
+        );
+    }
+
+    #[test]
+    fn free_rigids_by_name_skips_unused_rigids() {
+        use roc_types::types::Type;
+
+        let arena = Bump::new();
+        let mut annotation = can_annotation_a_to_b(&arena);
+        let introduced = &annotation.introduced_variables;
+        let a = introduced.var_by_name(&"a".into()).unwrap();
+        let b = introduced.var_by_name(&"b".into()).unwrap();
+
+        assert_eq!(
+            annotation.free_rigids_by_name(),
+            vec![("a".into(), a), ("b".into(), b)]
+        );
+
+        // `b` is still introduced, but no longer appears in the type.
+        let mut substitutions = roc_collections::ImMap::default();
+        substitutions.insert(b, Type::EmptyRec);
+        annotation.typ.substitute(&substitutions);
+
+        assert_eq!(annotation.free_rigids_by_name(), vec![("a".into(), a)]);
+    }
+
+    #[test]
+    fn scheme_of_identity() {
+        use roc_can::annotation::canonicalize_annotation;
+        use roc_collections::{VecMap, VecSet};
+        use roc_parse::ast::{HasClause, Spaced, TypeAnnotation};
+        use roc_region::all::Loc;
+        use roc_types::types::Type;
+
+        let arena = Bump::new();
+        let mut fixture = AnnotationFixture::new(&arena);
+
+        let hash = fixture
+            .scope
+            .introduce("Hash".into(), Region::zero())
+            .unwrap();
+        let mut pending_abilities = VecMap::default();
+        pending_abilities.insert(hash, VecSet::default());
+
+        let mut can_function_of = |var, clauses: &[_]| {
+            let var = Loc::at_zero(var);
+            let function = TypeAnnotation::Function(arena.alloc([var]), arena.alloc(var));
+            let annotation = if clauses.is_empty() {
+                function
+            } else {
+                TypeAnnotation::Where(
+                    arena.alloc(Loc::at_zero(function)),
+                    arena.alloc_slice_clone(clauses),
+                )
+            };
+            canonicalize_annotation(
+                &mut fixture.env,
+                &mut fixture.scope,
+                &annotation,
+                Region::zero(),
+                &mut fixture.var_store,
+                &pending_abilities,
+            )
+        };
+
+        // a -> a
+        let identity = can_function_of(TypeAnnotation::BoundVariable("a"), &[]);
+        let a = identity
+            .introduced_variables
+            .var_by_name(&"a".into())
+            .unwrap();
+        let (quantified, typ) = identity.into_scheme();
+        assert_eq!(quantified, vec![a]);
+        assert!(matches!(typ, Type::Function(..)));
+
+        // a -> a | a has Hash
+        let has_hash = Loc::at_zero(HasClause {
+            var: Loc::at_zero(Spaced::Item("a")),
+            ability: Loc::at_zero(TypeAnnotation::Apply("", "Hash", &[])),
+        });
+        let hash_identity = can_function_of(TypeAnnotation::BoundVariable("a"), &[has_hash]);
+        let a = hash_identity
+            .introduced_variables
+            .var_by_name(&"a".into())
+            .unwrap();
+        let (quantified, _) = hash_identity.into_scheme();
+        assert_eq!(quantified, vec![a]);
+
+        // * -> *
+        let (quantified, _) = can_function_of(TypeAnnotation::Wildcard, &[]).into_scheme();
+        assert_eq!(quantified, vec![]);
+
+        assert_eq!(fixture.env.problems, Vec::new());
+    }
+
+    #[test]
+    fn named_variables_can_be_made_flex() {
+        use roc_can::annotation::Rigidity;
+
+        let arena = Bump::new();
+        let mut annotation = can_annotation_a_to_b(&arena);
+        let introduced = &mut annotation.introduced_variables;
+        let a = introduced.var_by_name(&"a".into()).unwrap();
+        let b = introduced.var_by_name(&"b".into()).unwrap();
+        let closure = introduced.lambda_sets[0];
+
+        assert!(introduced.set_rigidity(&"b".into(), Rigidity::Flex));
+        assert!(!introduced.set_rigidity(&"c".into(), Rigidity::Flex));
+
+        let rigidity_of = |name: &str| {
+            (introduced.named.iter())
+                .find(|nv| nv.name.as_str() == name)
+                .map(|nv| nv.rigidity)
+        };
+        assert_eq!(rigidity_of("a"), Some(Rigidity::Rigid));
+        assert_eq!(rigidity_of("b"), Some(Rigidity::Flex));
+
+        assert_eq!(introduced.collect_rigid(), vec![a, closure]);
+        assert_eq!(introduced.collect_flex(), vec![b]);
+    }
+
     // LOCALS
 
     // TODO rewrite this test to check only for UnusedDef reports
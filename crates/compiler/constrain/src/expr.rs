@@ -4,7 +4,7 @@ use crate::builtins::{
     empty_list_type, float_literal, int_literal, list_type, num_literal, num_u32, str_type,
 };
 use crate::pattern::{constrain_pattern, PatternState};
-use roc_can::annotation::IntroducedVariables;
+use roc_can::annotation::{IntroducedVariables, Rigidity};
 use roc_can::constraint::{Constraint, Constraints, OpportunisticResolve};
 use roc_can::def::Def;
 use roc_can::exhaustive::{sketch_pattern_to_rows, sketch_when_branches, ExhaustiveContext};
@@ -2733,10 +2733,19 @@ fn instantiate_rigids(
     let mut annotation = annotation.clone();
     let mut new_rigid_variables: Vec<Variable> = Vec::new();
 
+    let mut new_infer_variables: Vec<Variable> =
+        introduced_vars.inferred.iter().map(|v| v.value).collect();
+
     let mut rigid_substitution: MutMap<Variable, Variable> = MutMap::default();
     for named in introduced_vars.iter_named() {
         use std::collections::hash_map::Entry::*;
 
+        // Flex variables are inferred, and are never shared with nested defs
+        if named.rigidity() == Rigidity::Flex {
+            new_infer_variables.push(named.variable());
+            continue;
+        }
+
         match ftv.entry(named.name().clone()) {
             Occupied(occupied) => {
                 let existing_rigid = occupied.get();
@@ -2756,9 +2765,6 @@ fn instantiate_rigids(
     // lambda set vars are always freshly introduced in this annotation
     new_rigid_variables.extend(introduced_vars.lambda_sets.iter().copied());
 
-    let new_infer_variables: Vec<Variable> =
-        introduced_vars.inferred.iter().map(|v| v.value).collect();
-
     // Instantiate rigid variables
     if !rigid_substitution.is_empty() {
         annotation.substitute_variables(&rigid_substitution);
@@ -2793,10 +2799,19 @@ fn instantiate_rigids_simple(
     let mut annotation = annotation.clone();
     let mut new_rigid_variables: Vec<Variable> = Vec::new();
 
+    let mut new_infer_variables: Vec<Variable> =
+        introduced_vars.inferred.iter().map(|v| v.value).collect();
+
     let mut rigid_substitution: MutMap<Variable, Variable> = MutMap::default();
     for named in introduced_vars.iter_named() {
         use std::collections::hash_map::Entry::*;
 
+        // Flex variables are inferred, and are never shared with nested defs
+        if named.rigidity() == Rigidity::Flex {
+            new_infer_variables.push(named.variable());
+            continue;
+        }
+
         match ftv.entry(named.name().clone()) {
             Occupied(occupied) => {
                 let existing_rigid = occupied.get();
@@ -2816,9 +2831,6 @@ fn instantiate_rigids_simple(
     // lambda set vars are always freshly introduced in this annotation
     new_rigid_variables.extend(introduced_vars.lambda_sets.iter().copied());
 
-    let new_infer_variables: Vec<Variable> =
-        introduced_vars.inferred.iter().map(|v| v.value).collect();
-
     // Instantiate rigid variables
     if !rigid_substitution.is_empty() {
         annotation.substitute_variables(&rigid_substitution);
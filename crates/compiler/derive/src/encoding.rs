@@ -14,7 +14,7 @@ use roc_module::symbol::Symbol;
 use roc_region::all::{Loc, Region};
 use roc_types::subs::{
     Content, ExhaustiveMark, FlatType, GetSubsSlice, LambdaSet, OptVariable, RecordFields,
-    RedundantMark, SubsSlice, UnionLambdas, UnionTags, Variable, VariableSubsSlice,
+    RedundantMark, SubsIndex, SubsSlice, UnionLambdas, UnionTags, Variable, VariableSubsSlice,
 };
 use roc_types::types::RecordField;
 
@@ -28,28 +28,32 @@ pub(crate) fn derive_to_encoder(
 ) -> DerivedBody {
     let (body, body_type) = match key {
         FlatEncodableKey::List() => to_encoder_list(env, def_symbol),
+        FlatEncodableKey::ListOfRecord(fields) => {
+            let (record_var, fields) = flex_record_var(env, fields);
+
+            to_encoder_list_of_record(env, record_var, fields, def_symbol)
+        }
         FlatEncodableKey::Set() => todo!(),
         FlatEncodableKey::Dict() => todo!(),
         FlatEncodableKey::Record(fields) => {
-            // Generalized record var so we can reuse this impl between many records:
-            // if fields = { a, b }, this is { a: t1, b: t2 } for fresh t1, t2.
-            let flex_fields = fields
-                .into_iter()
-                .map(|name| {
-                    (
-                        name,
-                        RecordField::Required(env.subs.fresh_unnamed_flex_var()),
-                    )
-                })
-                .collect::<Vec<(Lowercase, _)>>();
-            let fields = RecordFields::insert_into_subs(env.subs, flex_fields);
-            let record_var = synth_var(
-                env.subs,
-                Content::Structure(FlatType::Record(fields, Variable::EMPTY_RECORD)),
-            );
+            let (record_var, fields) = flex_record_var(env, fields);
 
             to_encoder_record(env, record_var, fields, def_symbol)
         }
+        FlatEncodableKey::RecordWithCustomEncoders(fields) => {
+            // The record is encoded like any other: `toEncoder` on a field holding an opaque
+            // resolves to that opaque's own implementation when it is specialized.
+            let field_names = fields.into_iter().map(|(name, _)| name).collect();
+            let (record_var, fields) = flex_record_var(env, field_names);
+
+            to_encoder_record(env, record_var, fields, def_symbol)
+        }
+        FlatEncodableKey::Tuple(arity) => {
+            let (record_var, fields) =
+                flex_record_var(env, (0..arity).map(|i| i.to_string().into()).collect());
+
+            to_encoder_tuple(env, record_var, fields, def_symbol)
+        }
         FlatEncodableKey::TagUnion(tags) => {
             // Generalized tag union var so we can reuse this impl between many unions:
             // if tags = [ A arity=2, B arity=1 ], this is [ A t1 t2, B t3 ] for fresh t1, t2, t3
@@ -84,6 +88,27 @@ pub(crate) fn derive_to_encoder(
     }
 }
 
+/// Generalized record var so we can reuse an impl between many records:
+/// if fields = { a, b }, this is { a: t1, b: t2 } for fresh t1, t2.
+fn flex_record_var(env: &mut Env<'_>, fields: Vec<Lowercase>) -> (Variable, RecordFields) {
+    let flex_fields = fields
+        .into_iter()
+        .map(|name| {
+            (
+                name,
+                RecordField::Required(env.subs.fresh_unnamed_flex_var()),
+            )
+        })
+        .collect::<Vec<(Lowercase, _)>>();
+    let fields = RecordFields::insert_into_subs(env.subs, flex_fields);
+    let record_var = synth_var(
+        env.subs,
+        Content::Structure(FlatType::Record(fields, Variable::EMPTY_RECORD)),
+    );
+
+    (record_var, fields)
+}
+
 fn to_encoder_list(env: &mut Env<'_>, fn_name: Symbol) -> (Expr, Variable) {
     // Build \lst -> Encode.list lst (\elem -> Encode.toEncoder elem)
     //
@@ -91,16 +116,10 @@ fn to_encoder_list(env: &mut Env<'_>, fn_name: Symbol) -> (Expr, Variable) {
 
     use Expr::*;
 
-    let lst_sym = env.new_symbol("lst");
     let elem_sym = env.new_symbol("elem");
 
-    // List elem
     let elem_var = env.subs.fresh_unnamed_flex_var();
     let elem_var_slice = SubsSlice::insert_into_subs(env.subs, [elem_var]);
-    let list_var = synth_var(
-        env.subs,
-        Content::Structure(FlatType::Apply(Symbol::LIST_LIST, elem_var_slice)),
-    );
 
     // build `toEncoder elem` type
     // val -[uls]-> Encoder fmt | fmt has EncoderFormatting
@@ -182,7 +201,62 @@ fn to_encoder_list(env: &mut Env<'_>, fn_name: Symbol) -> (Expr, Variable) {
         loc_body: Box::new(Loc::at_zero(to_encoder_call)),
     });
 
-    // build `Encode.list lst (\elem -> Encode.toEncoder elem)` type
+    encode_list_with(
+        env,
+        elem_var,
+        to_elem_encoder,
+        to_elem_encoder_fn_var,
+        fn_name,
+    )
+}
+
+fn to_encoder_list_of_record(
+    env: &mut Env<'_>,
+    record_var: Variable,
+    fields: RecordFields,
+    fn_name: Symbol,
+) -> (Expr, Variable) {
+    // Suppose elements are { a: t1, b: t2 }. Rather than deferring to each element's encoder,
+    // inline the record encoder to encode the whole list in one go:
+    //
+    // \lst -> Encode.list lst (\rcd -> Encode.record [
+    //      { key: "a", value: Encode.toEncoder rcd.a },
+    //      { key: "b", value: Encode.toEncoder rcd.b },
+    //   ])
+
+    let to_elem_encoder_sym = env.new_symbol("to_elem_encoder");
+    let (to_elem_encoder, to_elem_encoder_fn_var) =
+        to_encoder_record(env, record_var, fields, to_elem_encoder_sym);
+
+    encode_list_with(
+        env,
+        record_var,
+        to_elem_encoder,
+        to_elem_encoder_fn_var,
+        fn_name,
+    )
+}
+
+/// Builds `\lst -> Encode.list lst to_elem_encoder`, for a list of `elem_var`s.
+fn encode_list_with(
+    env: &mut Env<'_>,
+    elem_var: Variable,
+    to_elem_encoder: Expr,
+    to_elem_encoder_fn_var: Variable,
+    fn_name: Symbol,
+) -> (Expr, Variable) {
+    use Expr::*;
+
+    let lst_sym = env.new_symbol("lst");
+
+    // List elem
+    let elem_var_slice = SubsSlice::insert_into_subs(env.subs, [elem_var]);
+    let list_var = synth_var(
+        env.subs,
+        Content::Structure(FlatType::Apply(Symbol::LIST_LIST, elem_var_slice)),
+    );
+
+    // build `Encode.list lst to_elem_encoder` type
     // List e, (e -> Encoder fmt) -[uls]-> Encoder fmt | fmt has EncoderFormatting
     let encode_list_fn_var = env.import_builtin_symbol_var(Symbol::ENCODE_LIST);
 
@@ -299,8 +373,6 @@ fn to_encoder_record(
         .iter_all()
         .map(|(field_name_index, field_var_index, _)| {
             let field_name = env.subs[field_name_index].clone();
-            let field_var = env.subs[field_var_index];
-            let field_var_slice = VariableSubsSlice::new(field_var_index.index, 1);
 
             // key: "a"
             let key_field = Field {
@@ -309,50 +381,9 @@ fn to_encoder_record(
                 loc_expr: Box::new(Loc::at_zero(Str(field_name.as_str().into()))),
             };
 
-            // rcd.a
-            let field_access = Access {
-                record_var,
-                ext_var: env.subs.fresh_unnamed_flex_var(),
-                field_var,
-                loc_expr: Box::new(Loc::at_zero(Var(rcd_sym))),
-                field: field_name,
-            };
-
-            // build `toEncoder rcd.a` type
-            // val -[uls]-> Encoder fmt | fmt has EncoderFormatting
-            let to_encoder_fn_var = env.import_builtin_symbol_var(Symbol::ENCODE_TO_ENCODER);
-
-            // (typeof rcd.a) -[clos]-> t1
-            let to_encoder_clos_var = env.subs.fresh_unnamed_flex_var(); // clos
-            let encoder_var = env.subs.fresh_unnamed_flex_var(); // t1
-            let this_to_encoder_fn_var = synth_var(
-                env.subs,
-                Content::Structure(FlatType::Func(
-                    field_var_slice,
-                    to_encoder_clos_var,
-                    encoder_var,
-                )),
-            );
-
-            //   val            -[uls]->  Encoder fmt | fmt has EncoderFormatting
-            // ~ (typeof rcd.a) -[clos]-> t1
-            env.unify(to_encoder_fn_var, this_to_encoder_fn_var);
-
-            // toEncoder : (typeof rcd.a) -[clos]-> Encoder fmt | fmt has EncoderFormatting
-            let to_encoder_var = AbilityMember(Symbol::ENCODE_TO_ENCODER, None, to_encoder_fn_var);
-            let to_encoder_fn = Box::new((
-                to_encoder_fn_var,
-                Loc::at_zero(to_encoder_var),
-                to_encoder_clos_var,
-                encoder_var,
-            ));
-
             // toEncoder rcd.a
-            let to_encoder_call = Call(
-                to_encoder_fn,
-                vec![(field_var, Loc::at_zero(field_access))],
-                CalledVia::Space,
-            );
+            let (to_encoder_call, encoder_var) =
+                to_encoder_field(env, record_var, rcd_sym, field_name, field_var_index);
 
             // value: toEncoder rcd.a
             let value_field = Field {
@@ -440,6 +471,232 @@ fn to_encoder_record(
     let (body, this_encoder_var) =
         wrap_in_encode_custom(env, encode_record_call, encoder_var, rcd_sym, record_var);
 
+    // \rcd -[fn_name]-> Encode.record [ { key: .., value: .. }, .. ]
+    record_encoder_closure(env, fn_name, rcd_sym, record_var, body, this_encoder_var)
+}
+
+fn to_encoder_tuple(
+    env: &mut Env<'_>,
+    record_var: Variable,
+    fields: RecordFields,
+    fn_name: Symbol,
+) -> (Expr, Variable) {
+    // Suppose tup = { 0: t1, 1: t2 }. Build
+    //
+    // \tup -> Encode.list [ Encode.toEncoder tup.0, Encode.toEncoder tup.1 ] \enc -> enc
+
+    let tup_sym = env.new_symbol("tup");
+    let whole_encoder_var = env.subs.fresh_unnamed_flex_var(); // type of the encoders in the list
+
+    use Expr::*;
+
+    let mut fields: Vec<_> = fields.iter_all().collect();
+    fields.sort_by_key(|(field_name_index, _, _)| {
+        env.subs[*field_name_index]
+            .as_str()
+            .parse::<usize>()
+            .unwrap()
+    });
+
+    let encoders_list = fields
+        .into_iter()
+        .map(|(field_name_index, field_var_index, _)| {
+            let field_name = env.subs[field_name_index].clone();
+
+            // toEncoder tup.0
+            let (to_encoder_call, encoder_var) =
+                to_encoder_field(env, record_var, tup_sym, field_name, field_var_index);
+
+            // NOTE: must be done to unify the lambda sets under `encoder_var`
+            env.unify(encoder_var, whole_encoder_var);
+
+            Loc::at_zero(to_encoder_call)
+        })
+        .collect::<Vec<_>>();
+
+    // typeof [ toEncoder tup.0, toEncoder tup.1 ]
+    let whole_encoder_var_slice =
+        VariableSubsSlice::insert_into_subs(env.subs, once(whole_encoder_var));
+    let encoders_list_var = synth_var(
+        env.subs,
+        Content::Structure(FlatType::Apply(Symbol::LIST_LIST, whole_encoder_var_slice)),
+    );
+
+    // [ toEncoder tup.0, toEncoder tup.1 ]
+    let encoders_list = List {
+        elem_var: whole_encoder_var,
+        loc_elems: encoders_list,
+    };
+
+    // \enc -> enc
+    let enc_sym = env.new_symbol("enc");
+    let identity_sym = env.new_symbol("identity");
+
+    // Create fn_var for ambient capture; we fix it up below.
+    let identity_fn_var = synth_var(env.subs, Content::Error);
+
+    // -[identity]->
+    let identity_labels = UnionLambdas::insert_into_subs(env.subs, once((identity_sym, vec![])));
+    let identity_lset = synth_var(
+        env.subs,
+        Content::LambdaSet(LambdaSet {
+            solved: identity_labels,
+            recursion_var: OptVariable::NONE,
+            unspecialized: SubsSlice::default(),
+            ambient_function: identity_fn_var,
+        }),
+    );
+    // Encoder fmt -[identity]-> Encoder fmt
+    env.subs.set_content(
+        identity_fn_var,
+        Content::Structure(FlatType::Func(
+            whole_encoder_var_slice,
+            identity_lset,
+            whole_encoder_var,
+        )),
+    );
+
+    let identity = Closure(ClosureData {
+        function_type: identity_fn_var,
+        closure_type: identity_lset,
+        return_type: whole_encoder_var,
+        name: identity_sym,
+        captured_symbols: vec![],
+        recursive: Recursive::NotRecursive,
+        arguments: vec![(
+            whole_encoder_var,
+            AnnotatedMark::known_exhaustive(),
+            Loc::at_zero(Pattern::Identifier(enc_sym)),
+        )],
+        loc_body: Box::new(Loc::at_zero(Var(enc_sym))),
+    });
+
+    // build `Encode.list [ .. ] \enc -> enc` type
+    // List e, (e -> Encoder fmt) -[uls]-> Encoder fmt | fmt has EncoderFormatting
+    let encode_list_fn_var = env.import_builtin_symbol_var(Symbol::ENCODE_LIST);
+
+    // encoders_list_var, identity_fn_var -[clos]-> t1
+    let this_encode_list_args_slice =
+        VariableSubsSlice::insert_into_subs(env.subs, [encoders_list_var, identity_fn_var]);
+    let this_encode_list_clos_var = env.subs.fresh_unnamed_flex_var(); // clos
+    let this_list_encoder_var = env.subs.fresh_unnamed_flex_var(); // t1
+    let this_encode_list_fn_var = synth_var(
+        env.subs,
+        Content::Structure(FlatType::Func(
+            this_encode_list_args_slice,
+            this_encode_list_clos_var,
+            this_list_encoder_var,
+        )),
+    );
+
+    //   List e,            (e -> Encoder fmt) -[uls]->  Encoder fmt | fmt has EncoderFormatting
+    // ~ encoders_list_var, identity_fn_var    -[clos]-> t1
+    env.unify(encode_list_fn_var, this_encode_list_fn_var);
+
+    // Encode.list : encoders_list_var, identity_fn_var -[clos]-> Encoder fmt | fmt has EncoderFormatting
+    let encode_list = AbilityMember(Symbol::ENCODE_LIST, None, this_encode_list_fn_var);
+    let encode_list_fn = Box::new((
+        this_encode_list_fn_var,
+        Loc::at_zero(encode_list),
+        this_encode_list_clos_var,
+        this_list_encoder_var,
+    ));
+
+    // Encode.list [ .. ] \enc -> enc
+    let encode_list_call = Call(
+        encode_list_fn,
+        vec![
+            (encoders_list_var, Loc::at_zero(encoders_list)),
+            (identity_fn_var, Loc::at_zero(identity)),
+        ],
+        CalledVia::Space,
+    );
+
+    // Encode.custom \bytes, fmt -> Encode.appendWith bytes (Encode.list ..) fmt
+    let (body, this_encoder_var) = wrap_in_encode_custom(
+        env,
+        encode_list_call,
+        this_list_encoder_var,
+        tup_sym,
+        record_var,
+    );
+
+    // \tup -[fn_name]-> Encode.list [ .. ] \enc -> enc
+    record_encoder_closure(env, fn_name, tup_sym, record_var, body, this_encoder_var)
+}
+
+/// Builds `toEncoder rcd.a` for the field `a` of the record `rcd`, returning it along with the
+/// type of the encoder it makes.
+fn to_encoder_field(
+    env: &mut Env<'_>,
+    record_var: Variable,
+    rcd_sym: Symbol,
+    field_name: Lowercase,
+    field_var_index: SubsIndex<Variable>,
+) -> (Expr, Variable) {
+    use Expr::*;
+
+    let field_var = env.subs[field_var_index];
+    let field_var_slice = VariableSubsSlice::new(field_var_index.index, 1);
+
+    // rcd.a
+    let field_access = Access {
+        record_var,
+        ext_var: env.subs.fresh_unnamed_flex_var(),
+        field_var,
+        loc_expr: Box::new(Loc::at_zero(Var(rcd_sym))),
+        field: field_name,
+    };
+
+    // build `toEncoder rcd.a` type
+    // val -[uls]-> Encoder fmt | fmt has EncoderFormatting
+    let to_encoder_fn_var = env.import_builtin_symbol_var(Symbol::ENCODE_TO_ENCODER);
+
+    // (typeof rcd.a) -[clos]-> t1
+    let to_encoder_clos_var = env.subs.fresh_unnamed_flex_var(); // clos
+    let encoder_var = env.subs.fresh_unnamed_flex_var(); // t1
+    let this_to_encoder_fn_var = synth_var(
+        env.subs,
+        Content::Structure(FlatType::Func(
+            field_var_slice,
+            to_encoder_clos_var,
+            encoder_var,
+        )),
+    );
+
+    //   val            -[uls]->  Encoder fmt | fmt has EncoderFormatting
+    // ~ (typeof rcd.a) -[clos]-> t1
+    env.unify(to_encoder_fn_var, this_to_encoder_fn_var);
+
+    // toEncoder : (typeof rcd.a) -[clos]-> Encoder fmt | fmt has EncoderFormatting
+    let to_encoder_var = AbilityMember(Symbol::ENCODE_TO_ENCODER, None, to_encoder_fn_var);
+    let to_encoder_fn = Box::new((
+        to_encoder_fn_var,
+        Loc::at_zero(to_encoder_var),
+        to_encoder_clos_var,
+        encoder_var,
+    ));
+
+    // toEncoder rcd.a
+    let to_encoder_call = Call(
+        to_encoder_fn,
+        vec![(field_var, Loc::at_zero(field_access))],
+        CalledVia::Space,
+    );
+
+    (to_encoder_call, encoder_var)
+}
+
+/// Builds `\rcd -[fn_name]-> body`, the derived encoder of the record `rcd`, where `body` makes
+/// an encoder of type `encoder_var`.
+fn record_encoder_closure(
+    env: &mut Env<'_>,
+    fn_name: Symbol,
+    rcd_sym: Symbol,
+    record_var: Variable,
+    body: Expr,
+    encoder_var: Variable,
+) -> (Expr, Variable) {
     // Create fn_var for ambient capture; we fix it up below.
     let fn_var = synth_var(env.subs, Content::Error);
 
@@ -454,22 +711,18 @@ fn to_encoder_record(
             ambient_function: fn_var,
         }),
     );
-    // typeof rcd -[fn_name]-> (typeof Encode.record [ .. ] = Encoder fmt)
+    // typeof rcd -[fn_name]-> (typeof body = Encoder fmt)
     let record_var_slice = SubsSlice::insert_into_subs(env.subs, once(record_var));
     env.subs.set_content(
         fn_var,
-        Content::Structure(FlatType::Func(
-            record_var_slice,
-            fn_clos_var,
-            this_encoder_var,
-        )),
+        Content::Structure(FlatType::Func(record_var_slice, fn_clos_var, encoder_var)),
     );
 
-    // \rcd -[fn_name]-> Encode.record [ { key: .., value: .. }, .. ]
-    let clos = Closure(ClosureData {
+    // \rcd -[fn_name]-> body
+    let clos = Expr::Closure(ClosureData {
         function_type: fn_var,
         closure_type: fn_clos_var,
-        return_type: this_encoder_var,
+        return_type: encoder_var,
         name: fn_name,
         captured_symbols: vec![],
         recursive: Recursive::NotRecursive,
@@ -8,7 +8,7 @@ use roc_can::expr::Expr;
 use roc_can::pattern::Pattern;
 use roc_can::{def::Def, module::ExposedByModule};
 use roc_collections::{MutMap, VecMap};
-use roc_derive_key::DeriveKey;
+use roc_derive_key::{DeriveKey, DeriveOptions};
 use roc_module::symbol::{IdentIds, ModuleId, Symbol};
 use roc_region::all::Loc;
 use roc_types::subs::{
@@ -44,6 +44,7 @@ pub struct DerivedModule {
     map: MutMap<DeriveKey, (Symbol, Def, SpecializationLambdaSets)>,
     subs: Subs,
     derived_ident_ids: IdentIds,
+    options: DeriveOptions,
 }
 
 pub(crate) struct DerivedBody {
@@ -91,6 +92,19 @@ fn build_derived_body(
 }
 
 impl DerivedModule {
+    /// A derived module whose keys are classified with non-default [`DeriveOptions`].
+    pub fn with_options(options: DeriveOptions) -> Self {
+        Self {
+            options,
+            ..Default::default()
+        }
+    }
+
+    /// The options types are classified with before their keys are looked up in this module.
+    pub fn derive_options(&self) -> DeriveOptions {
+        self.options
+    }
+
     pub fn get_or_insert(
         &mut self,
         // TODO: we only need "exposed by builtin modules that expose builtin abilities"
@@ -208,6 +222,7 @@ impl DerivedModule {
             map: Default::default(),
             subs,
             derived_ident_ids: ident_ids,
+            options: Default::default(),
         }
     }
 
@@ -1,7 +1,7 @@
 use roc_can::{abilities::SpecializationLambdaSets, module::ExposedByModule};
 use roc_error_macros::internal_error;
 use roc_module::symbol::{IdentIds, Symbol};
-use roc_types::subs::{instantiate_rigids, Subs, Variable};
+use roc_types::subs::{instantiate_rigids, Subs, UlsOfVar, Variable};
 
 use crate::DERIVED_SYNTH;
 
@@ -67,25 +67,36 @@ impl Env<'_> {
     }
 
     pub fn unify(&mut self, left: Variable, right: Variable) {
-        use roc_unify::unify::{unify, Env, Mode, Unified};
+        self.unify_all(&[(left, right)])
+    }
 
-        let unified = unify(&mut Env::new(self.subs), left, right, Mode::EQ);
+    /// Unifies each pair of variables in turn, sharing one unification environment across the
+    /// whole batch.
+    pub fn unify_all(&mut self, pairs: &[(Variable, Variable)]) {
+        use roc_unify::unify::{unify, Env, Mode, Unified};
 
-        match unified {
-            Unified::Success {
-                vars: _,
-                must_implement_ability: _,
-                lambda_sets_to_specialize,
-                extra_metadata: _,
-            } => {
-                if !lambda_sets_to_specialize.is_empty() {
-                    internal_error!("Did not expect derivers to need to specialize unspecialized lambda sets, but we got some: {:?}", lambda_sets_to_specialize)
+        let mut unify_env = Env::new(self.subs);
+        let mut lambda_sets_to_specialize = UlsOfVar::default();
+
+        for &(left, right) in pairs {
+            match unify(&mut unify_env, left, right, Mode::EQ) {
+                Unified::Success {
+                    vars: _,
+                    must_implement_ability: _,
+                    lambda_sets_to_specialize: new_lambda_sets_to_specialize,
+                    extra_metadata: _,
+                } => {
+                    lambda_sets_to_specialize.union(new_lambda_sets_to_specialize);
+                }
+                Unified::Failure(..) | Unified::BadType(..) => {
+                    internal_error!("Unification failed in deriver - that's a deriver bug!")
                 }
-            }
-            Unified::Failure(..) | Unified::BadType(..) => {
-                internal_error!("Unification failed in deriver - that's a deriver bug!")
             }
         }
+
+        if !lambda_sets_to_specialize.is_empty() {
+            internal_error!("Did not expect derivers to need to specialize unspecialized lambda sets, but we got some: {:?}", lambda_sets_to_specialize)
+        }
     }
 
     pub fn get_specialization_lambda_sets(
@@ -153,3 +164,55 @@ impl Env<'_> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::Env;
+    use roc_can::module::ExposedByModule;
+    use roc_module::symbol::IdentIds;
+    use roc_types::subs::{Subs, Variable};
+
+    /// Pairs a few builtin types with fresh flex vars.
+    fn flex_and_builtin_pairs(subs: &mut Subs) -> Vec<(Variable, Variable)> {
+        [Variable::STR, Variable::U8, Variable::EMPTY_RECORD]
+            .into_iter()
+            .map(|builtin| (subs.fresh_unnamed_flex_var(), builtin))
+            .collect()
+    }
+
+    fn unify_with(subs: &mut Subs, f: impl FnOnce(&mut Env)) {
+        let exposed_types = ExposedByModule::default();
+        let mut derived_ident_ids = IdentIds::default();
+        let mut env = Env {
+            subs,
+            exposed_types: &exposed_types,
+            derived_ident_ids: &mut derived_ident_ids,
+        };
+        f(&mut env)
+    }
+
+    #[test]
+    fn unify_all_is_sequential_unify() {
+        let mut batched_subs = Subs::new();
+        let batched = flex_and_builtin_pairs(&mut batched_subs);
+        unify_with(&mut batched_subs, |env| env.unify_all(&batched));
+
+        let mut sequential_subs = Subs::new();
+        let sequential = flex_and_builtin_pairs(&mut sequential_subs);
+        unify_with(&mut sequential_subs, |env| {
+            for &(left, right) in sequential.iter() {
+                env.unify(left, right);
+            }
+        });
+
+        // Both subs were built the same way, so they should agree on every variable.
+        for (&(left, right), &(seq_left, seq_right)) in batched.iter().zip(sequential.iter()) {
+            assert!(batched_subs.equivalent_without_compacting(left, right));
+            assert!(sequential_subs.equivalent_without_compacting(seq_left, seq_right));
+            assert_eq!(
+                batched_subs.get_root_key_without_compacting(left),
+                sequential_subs.get_root_key_without_compacting(seq_left)
+            );
+        }
+    }
+}
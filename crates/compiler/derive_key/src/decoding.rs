@@ -1,12 +1,18 @@
+use roc_error_macros::internal_error;
 use roc_module::symbol::Symbol;
 use roc_types::subs::{Content, FlatType, Subs, Variable};
+use roc_types::types::AliasKind;
 
-use crate::DeriveError;
+use crate::num::{num_info, IntOrFrac, NumInfo};
+use crate::{ClassificationCache, DeriveError, DeriveOptions};
 
-#[derive(Hash)]
+#[derive(Hash, PartialEq, Eq, Debug, Clone)]
 pub enum FlatDecodable {
     Immediate(Symbol),
     Key(FlatDecodableKey),
+    /// Decode with the opaque type's own, hand-written implementation. Decoding the type the
+    /// opaque wraps instead would produce a bare value that is never wrapped in the opaque.
+    Opaque(Symbol),
 }
 
 #[derive(Hash, PartialEq, Eq, Debug, Clone)]
@@ -22,8 +28,45 @@ impl FlatDecodableKey {
     }
 }
 
+/// The debug name of how the type of `var` is decoded, under the default [`DeriveOptions`].
+pub fn debug_key_for(subs: &Subs, var: Variable) -> Result<String, DeriveError> {
+    let mut cache = ClassificationCache::default();
+    let decodable = FlatDecodable::from_var(subs, var, DeriveOptions::default(), &mut cache)?;
+
+    Ok(decodable.debug_name())
+}
+
 impl FlatDecodable {
-    pub(crate) fn from_var(subs: &Subs, var: Variable) -> Result<FlatDecodable, DeriveError> {
+    pub(crate) fn debug_name(&self) -> String {
+        match self {
+            FlatDecodable::Immediate(symbol) | FlatDecodable::Opaque(symbol) => symbol.to_string(),
+            FlatDecodable::Key(key) => key.debug_name(),
+        }
+    }
+
+    pub(crate) fn from_var(
+        subs: &Subs,
+        var: Variable,
+        options: DeriveOptions,
+        cache: &mut ClassificationCache,
+    ) -> Result<FlatDecodable, DeriveError> {
+        let root = subs.get_root_key_without_compacting(var);
+        if let Some(result) = cache.decodable.get(&root) {
+            return result.clone();
+        }
+
+        let result = Self::from_var_help(subs, var, options, cache);
+        cache.decodable.insert(root, result.clone());
+
+        result
+    }
+
+    fn from_var_help(
+        subs: &Subs,
+        var: Variable,
+        options: DeriveOptions,
+        cache: &mut ClassificationCache,
+    ) -> Result<FlatDecodable, DeriveError> {
         use DeriveError::*;
         use FlatDecodable::*;
         match *subs.get_content_without_compacting(var) {
@@ -45,40 +88,54 @@ impl FlatDecodable {
                 FlatType::EmptyRecord => {
                     Err(Underivable) // yet
                 }
-                FlatType::EmptyTagUnion => {
-                    Err(Underivable) // yet
-                }
+                // `[]` has no values, so there is nothing a decoder could produce.
+                FlatType::EmptyTagUnion => Err(UninhabitedType),
                 //
                 FlatType::Erroneous(_) => Err(Underivable),
                 FlatType::Func(..) => Err(Underivable),
             },
-            Content::Alias(sym, _, real_var, _) => match sym {
-                Symbol::NUM_U8 | Symbol::NUM_UNSIGNED8 => Ok(Immediate(Symbol::DECODE_U8)),
-                Symbol::NUM_U16 | Symbol::NUM_UNSIGNED16 => Ok(Immediate(Symbol::DECODE_U16)),
-                Symbol::NUM_U32 | Symbol::NUM_UNSIGNED32 => Ok(Immediate(Symbol::DECODE_U32)),
-                Symbol::NUM_U64 | Symbol::NUM_UNSIGNED64 => Ok(Immediate(Symbol::DECODE_U64)),
-                Symbol::NUM_U128 | Symbol::NUM_UNSIGNED128 => Ok(Immediate(Symbol::DECODE_U128)),
-                Symbol::NUM_I8 | Symbol::NUM_SIGNED8 => Ok(Immediate(Symbol::DECODE_I8)),
-                Symbol::NUM_I16 | Symbol::NUM_SIGNED16 => Ok(Immediate(Symbol::DECODE_I16)),
-                Symbol::NUM_I32 | Symbol::NUM_SIGNED32 => Ok(Immediate(Symbol::DECODE_I32)),
-                Symbol::NUM_I64 | Symbol::NUM_SIGNED64 => Ok(Immediate(Symbol::DECODE_I64)),
-                Symbol::NUM_I128 | Symbol::NUM_SIGNED128 => Ok(Immediate(Symbol::DECODE_I128)),
-                Symbol::NUM_DEC | Symbol::NUM_DECIMAL => Ok(Immediate(Symbol::DECODE_DEC)),
-                Symbol::NUM_F32 | Symbol::NUM_BINARY32 => Ok(Immediate(Symbol::DECODE_F32)),
-                Symbol::NUM_F64 | Symbol::NUM_BINARY64 => Ok(Immediate(Symbol::DECODE_F64)),
-                // NB: I believe it is okay to unwrap opaques here because derivers are only used
-                // by the backend, and the backend treats opaques like structural aliases.
-                _ => Self::from_var(subs, real_var),
-            },
+            Content::Alias(sym, _, real_var, kind) => {
+                if let Some(info) = num_info(sym) {
+                    return Ok(Immediate(num_decoder(info)));
+                }
+                match sym {
+                    // Decode booleans as true booleans, rather than the `[False, True]` tag union
+                    // they are defined as.
+                    Symbol::BOOL_BOOL => Ok(Immediate(Symbol::DECODE_BOOL)),
+                    // The decoded value must be wrapped in the opaque, which only the opaque's own
+                    // implementation does. A derived one decodes the wrapped type, as we do here.
+                    _ if kind == AliasKind::Opaque && cache.custom_decoders.contains(&sym) => {
+                        Ok(Opaque(sym))
+                    }
+                    _ => Self::from_var(subs, real_var, options, cache),
+                }
+            }
             Content::RangedNumber(_) => Err(Underivable),
             //
             Content::RecursionVar { .. } => Err(Underivable),
             Content::Error => Err(Underivable),
-            Content::FlexVar(_)
-            | Content::RigidVar(_)
-            | Content::FlexAbleVar(_, _)
-            | Content::RigidAbleVar(_, _) => Err(UnboundVar),
+            Content::FlexVar(_) | Content::FlexAbleVar(_, _) => Err(UnboundVar),
+            Content::RigidVar(_) | Content::RigidAbleVar(_, _) => Err(CannotDeriveForPolymorphic),
             Content::LambdaSet(_) => Err(Underivable),
         }
     }
 }
+
+fn num_decoder(info: NumInfo) -> Symbol {
+    match (info.kind, info.signed, info.bits) {
+        (IntOrFrac::Int, false, 8) => Symbol::DECODE_U8,
+        (IntOrFrac::Int, false, 16) => Symbol::DECODE_U16,
+        (IntOrFrac::Int, false, 32) => Symbol::DECODE_U32,
+        (IntOrFrac::Int, false, 64) => Symbol::DECODE_U64,
+        (IntOrFrac::Int, false, 128) => Symbol::DECODE_U128,
+        (IntOrFrac::Int, true, 8) => Symbol::DECODE_I8,
+        (IntOrFrac::Int, true, 16) => Symbol::DECODE_I16,
+        (IntOrFrac::Int, true, 32) => Symbol::DECODE_I32,
+        (IntOrFrac::Int, true, 64) => Symbol::DECODE_I64,
+        (IntOrFrac::Int, true, 128) => Symbol::DECODE_I128,
+        (IntOrFrac::Frac, _, 32) => Symbol::DECODE_F32,
+        (IntOrFrac::Frac, _, 64) => Symbol::DECODE_F64,
+        (IntOrFrac::Frac, _, 128) => Symbol::DECODE_DEC,
+        _ => internal_error!("No decoder for number {:?}", info),
+    }
+}
@@ -1,24 +1,76 @@
+use roc_collections::VecSet;
+use roc_error_macros::internal_error;
 use roc_module::{
     ident::{Lowercase, TagName},
     symbol::Symbol,
 };
 use roc_types::subs::{Content, FlatType, GetSubsSlice, Subs, Variable};
+use roc_types::types::AliasKind;
 
-use crate::DeriveError;
+use crate::num::{num_info, IntOrFrac, NumInfo};
+use crate::{util::tuple_arity, ClassificationCache, DeriveError, DeriveOptions};
 
-#[derive(Hash)]
+/// How to derive encoders for records whose extension is an unbound variable, like `{ a : Str }*`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpenRecordMode {
+    /// Open records are not derivable; this is the default.
+    Reject,
+    /// Encode only the known fields of an open record, treating its extension as empty. The
+    /// extension can't be iterated over anyway.
+    KnownFieldsOnly,
+}
+
+impl Default for OpenRecordMode {
+    fn default() -> Self {
+        OpenRecordMode::Reject
+    }
+}
+
+/// How to derive encoders for user-defined opaque types, like `Email := Str`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpaqueEncoders {
+    /// Encode an opaque like the type it wraps; this is the default. Whatever the opaque checks
+    /// was checked when it was constructed, so the wrapped value can be written as it is.
+    Unwrap,
+    /// Encode an opaque with its own implementation of `toEncoder`, which may be a custom one.
+    OwnImplementation,
+}
+
+impl Default for OpaqueEncoders {
+    fn default() -> Self {
+        OpaqueEncoders::Unwrap
+    }
+}
+
+#[derive(Hash, PartialEq, Eq, Debug, Clone)]
 pub enum FlatEncodable {
     Immediate(Symbol),
     Key(FlatEncodableKey),
+    /// Encode with the opaque type's own implementation. Only produced under
+    /// [`OpaqueEncoders::OwnImplementation`].
+    Opaque(Symbol),
 }
 
 #[derive(Hash, PartialEq, Eq, Debug, Clone)]
 pub enum FlatEncodableKey {
     List(/* takes one variable */),
+    /// A list whose elements are closed records with these fields. Distinguished from `List` so
+    /// that lists of records can be encoded in bulk.
+    ListOfRecord(Vec<Lowercase>),
     Set(/* takes one variable */),
     Dict(/* takes two variables */),
     // Unfortunate that we must allocate here, c'est la vie
     Record(Vec<Lowercase>),
+    /// A record with fields whose types are opaques that implement `toEncoder` by hand, each
+    /// recorded next to its field; the other fields have no opaque and are encoded like those of
+    /// a `Record`. Only produced for the opaques of
+    /// [`ClassificationCache::with_custom_implementations`][crate::ClassificationCache::with_custom_implementations].
+    RecordWithCustomEncoders(Vec<(Lowercase, Option<Symbol>)>),
+    /// A record whose fields are `0`, `1`, .., `n - 1`, which is encoded like a tuple.
+    Tuple(usize),
+    /// Tag names and the number of payloads of each tag. An explicit empty-record payload is a
+    /// payload like any other, so `A` and `A {}` have arities 0 and 1. Their keys must differ
+    /// because the derived implementation has to match on the tag's exact shape.
     TagUnion(Vec<(TagName, u16)>),
 }
 
@@ -27,18 +79,26 @@ impl FlatEncodableKey {
         match self {
             FlatEncodableKey::List() => "list".to_string(),
             FlatEncodableKey::Set() => "set".to_string(),
+            FlatEncodableKey::ListOfRecord(fields) => {
+                format!("list_of_{}", Self::record_debug_name(fields))
+            }
             FlatEncodableKey::Dict() => "dict".to_string(),
-            FlatEncodableKey::Record(fields) => {
+            FlatEncodableKey::Record(fields) => Self::record_debug_name(fields),
+            FlatEncodableKey::RecordWithCustomEncoders(fields) => {
                 let mut str = String::from('{');
-                fields.iter().enumerate().for_each(|(i, f)| {
+                fields.iter().enumerate().for_each(|(i, (f, opaque))| {
                     if i > 0 {
                         str.push(',');
                     }
                     str.push_str(f.as_str());
+                    if let Some(opaque) = opaque {
+                        str.push_str(&format!(":opaque({})", opaque));
+                    }
                 });
                 str.push('}');
                 str
             }
+            FlatEncodableKey::Tuple(arity) => format!("({})", arity),
             FlatEncodableKey::TagUnion(tags) => {
                 let mut str = String::from('[');
                 tags.iter().enumerate().for_each(|(i, (tag, arity))| {
@@ -54,6 +114,18 @@ impl FlatEncodableKey {
             }
         }
     }
+
+    fn record_debug_name(fields: &[Lowercase]) -> String {
+        let mut str = String::from('{');
+        fields.iter().enumerate().for_each(|(i, f)| {
+            if i > 0 {
+                str.push(',');
+            }
+            str.push_str(f.as_str());
+        });
+        str.push('}');
+        str
+    }
 }
 
 fn check_ext_var(
@@ -67,31 +139,266 @@ fn check_ext_var(
     } else {
         match ext_content {
             Content::FlexVar(_) => Err(DeriveError::UnboundVar),
+            Content::RigidVar(_) => Err(DeriveError::CannotDeriveForPolymorphic),
             _ => Err(DeriveError::Underivable),
         }
     }
 }
 
+/// Why no encoder can be derived for a type; see [`explain_underivable`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum UnderivableReason {
+    /// The type itself can't be encoded, like a function.
+    Type(DeriveError),
+    /// The type could be encoded, but these types within it can't.
+    Parts(Vec<(TypePosition, UnderivableReason)>),
+}
+
+/// Where a type is within the type that contains it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TypePosition {
+    Field(Lowercase),
+    ListElement,
+    SetElement,
+    DictKey,
+    DictValue,
+    /// The payload of a tag at this index.
+    TagPayload(TagName, usize),
+}
+
+/// Explains why no encoder can be derived for the type of `var`, under the default
+/// [`DeriveOptions`]. Deriving only looks at the surface of a type, and stops at the first
+/// problem; this looks at every type within it, like the fields of a record, and reports each one
+/// that can't be encoded. Returns `None` if the whole type can be encoded.
+pub fn explain_underivable(subs: &Subs, var: Variable) -> Option<UnderivableReason> {
+    let mut cache = ClassificationCache::default();
+
+    explain_underivable_help(subs, var, DeriveOptions::default(), &mut cache)
+}
+
+fn explain_underivable_help(
+    subs: &Subs,
+    var: Variable,
+    options: DeriveOptions,
+    cache: &mut ClassificationCache,
+) -> Option<UnderivableReason> {
+    match FlatEncodable::from_var(subs, var, options, cache) {
+        Err(error) => return Some(UnderivableReason::Type(error)),
+        // These are encoded by an implementation that doesn't need to be derived.
+        Ok(FlatEncodable::Immediate(_) | FlatEncodable::Opaque(_)) => return None,
+        Ok(FlatEncodable::Key(_)) => {}
+    }
+
+    let parts: Vec<_> = (encoded_parts(subs, var).into_iter())
+        .filter_map(|(position, part)| {
+            explain_underivable_help(subs, part, options, cache).map(|reason| (position, reason))
+        })
+        .collect();
+
+    if parts.is_empty() {
+        None
+    } else {
+        Some(UnderivableReason::Parts(parts))
+    }
+}
+
+/// The types within the type of `var` that its derived encoder encodes with their own encoders.
+/// References back to a recursive type are left out, since that type is being looked at already.
+fn encoded_parts(subs: &Subs, var: Variable) -> Vec<(TypePosition, Variable)> {
+    let mut parts = surface_parts(subs, var);
+    parts.retain(|(_, var)| !is_recursion_var(subs, *var));
+
+    parts
+}
+
+/// The payloads of the recursive tag union `var` that refer back to the union itself, like the
+/// second payload of `Cons` in `[Nil, Cons a Rec] as Rec`. The encoder derived for the union
+/// encodes these with a recursive call to itself. The key of the union doesn't record them,
+/// since it only describes the surface of the union; this is where to find them instead.
+pub fn recursion_points(subs: &Subs, var: Variable) -> Vec<TypePosition> {
+    (surface_parts(subs, var).into_iter())
+        .filter(|(_, var)| is_recursion_var(subs, *var))
+        .map(|(position, _)| position)
+        .collect()
+}
+
+fn is_recursion_var(subs: &Subs, var: Variable) -> bool {
+    matches!(
+        subs.get_content_without_compacting(var),
+        Content::RecursionVar { .. }
+    )
+}
+
+/// The types directly within the type of `var`, seen through any aliases.
+fn surface_parts(subs: &Subs, mut var: Variable) -> Vec<(TypePosition, Variable)> {
+    let flat_type = loop {
+        match *subs.get_content_without_compacting(var) {
+            Content::Alias(_, _, real_var, _) => var = real_var,
+            Content::Structure(flat_type) => break flat_type,
+            _ => return Vec::new(),
+        }
+    };
+
+    match flat_type {
+        FlatType::Apply(sym, vars) => {
+            let vars = subs.get_subs_slice(vars);
+            match sym {
+                Symbol::LIST_LIST => vec![(TypePosition::ListElement, vars[0])],
+                Symbol::SET_SET => vec![(TypePosition::SetElement, vars[0])],
+                Symbol::DICT_DICT => vec![
+                    (TypePosition::DictKey, vars[0]),
+                    (TypePosition::DictValue, vars[1]),
+                ],
+                _ => Vec::new(),
+            }
+        }
+        FlatType::Record(fields, _) => {
+            let names = subs.get_subs_slice(fields.field_names());
+            let vars = subs.get_subs_slice(fields.variables());
+            let mut fields: Vec<_> = names.iter().zip(vars).collect();
+            fields.sort_by(|(f1, _), (f2, _)| f1.cmp(f2));
+
+            (fields.into_iter())
+                .map(|(name, &var)| (TypePosition::Field(name.clone()), var))
+                .collect()
+        }
+        FlatType::TagUnion(tags, _) | FlatType::RecursiveTagUnion(_, tags, _) => {
+            let mut tags: Vec<_> = tags
+                .iter_all()
+                .map(|(name_index, payload_slice_index)| {
+                    (
+                        &subs[name_index],
+                        subs.get_subs_slice(subs[payload_slice_index]),
+                    )
+                })
+                .collect();
+            tags.sort_by(|(t1, _), (t2, _)| t1.cmp(t2));
+
+            (tags.into_iter())
+                .flat_map(|(name, payloads)| {
+                    (payloads.iter().enumerate())
+                        .map(|(i, &var)| (TypePosition::TagPayload(name.clone(), i), var))
+                })
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// The debug name of how the type of `var` is encoded, under the default [`DeriveOptions`].
+pub fn debug_key_for(subs: &Subs, var: Variable) -> Result<String, DeriveError> {
+    let mut cache = ClassificationCache::default();
+    let encodable = FlatEncodable::from_var(subs, var, DeriveOptions::default(), &mut cache)?;
+
+    Ok(encodable.debug_name())
+}
+
 impl FlatEncodable {
-    pub(crate) fn from_var(subs: &Subs, var: Variable) -> Result<FlatEncodable, DeriveError> {
+    pub(crate) fn debug_name(&self) -> String {
+        match self {
+            FlatEncodable::Immediate(symbol) => symbol.to_string(),
+            FlatEncodable::Key(key) => key.debug_name(),
+            FlatEncodable::Opaque(symbol) => format!("opaque({})", symbol),
+        }
+    }
+
+    pub(crate) fn from_var(
+        subs: &Subs,
+        var: Variable,
+        options: DeriveOptions,
+        cache: &mut ClassificationCache,
+    ) -> Result<FlatEncodable, DeriveError> {
+        let root = subs.get_root_key_without_compacting(var);
+        if let Some(result) = cache.encodable.get(&root) {
+            return result.clone();
+        }
+
+        // Skip to the end of a chain of transparent aliases, like `A := B`, `B := U8`, rather
+        // than classifying each link on the way there.
+        let real_var = skip_transparent_aliases(subs, var, options, &cache.custom_encoders);
+        let result = if real_var == var {
+            Self::from_var_help(subs, var, options, cache)
+        } else {
+            Self::from_var(subs, real_var, options, cache)
+        };
+        cache.encodable.insert(root, result.clone());
+
+        result
+    }
+
+    fn from_var_help(
+        subs: &Subs,
+        var: Variable,
+        options: DeriveOptions,
+        cache: &mut ClassificationCache,
+    ) -> Result<FlatEncodable, DeriveError> {
         use DeriveError::*;
         use FlatEncodable::*;
         match *subs.get_content_without_compacting(var) {
             Content::Structure(flat_type) => match flat_type {
-                FlatType::Apply(sym, _) => match sym {
-                    Symbol::LIST_LIST => Ok(Key(FlatEncodableKey::List())),
+                FlatType::Apply(sym, vars) => match sym {
+                    Symbol::LIST_LIST => {
+                        // Lists of closed records get their own key; every other list uses the
+                        // generic list encoder, which defers to the elements' encoders.
+                        let elem_var = subs[vars.into_iter().next().unwrap()];
+                        match Self::from_var(subs, elem_var, options, cache) {
+                            Ok(Key(FlatEncodableKey::Record(fields))) => {
+                                Ok(Key(FlatEncodableKey::ListOfRecord(fields)))
+                            }
+                            _ => Ok(Key(FlatEncodableKey::List())),
+                        }
+                    }
                     Symbol::SET_SET => Ok(Key(FlatEncodableKey::Set())),
-                    Symbol::DICT_DICT => Ok(Key(FlatEncodableKey::Dict())),
+                    Symbol::DICT_DICT => {
+                        // Keys that can't be encoded, like functions, make the dictionary
+                        // underivable.
+                        let key_var = subs[vars.into_iter().next().unwrap()];
+                        Self::from_var(subs, key_var, options, cache)?;
+
+                        Ok(Key(FlatEncodableKey::Dict()))
+                    }
                     Symbol::STR_STR => Ok(Immediate(Symbol::ENCODE_STRING)),
                     _ => Err(Underivable),
                 },
                 FlatType::Record(fields, ext) => {
-                    check_ext_var(subs, ext, |ext| {
-                        matches!(ext, Content::Structure(FlatType::EmptyRecord))
+                    check_ext_var(subs, ext, |ext| match ext {
+                        Content::Structure(FlatType::EmptyRecord) => true,
+                        Content::FlexVar(_) => {
+                            options.open_records == OpenRecordMode::KnownFieldsOnly
+                        }
+                        _ => false,
                     })?;
 
                     let mut field_names: Vec<_> =
                         subs.get_subs_slice(fields.field_names()).to_vec();
+
+                    if field_names.is_empty() {
+                        return Ok(Immediate(Symbol::ENCODE_EMPTY_RECORD));
+                    }
+                    if let Some(arity) = tuple_arity(&field_names) {
+                        return Ok(Key(FlatEncodableKey::Tuple(arity)));
+                    }
+
+                    // Fields holding an opaque with a hand-written encoder must be encoded with
+                    // it, so those opaques are part of the key. Fields of the same type are only
+                    // classified once.
+                    let opaques: Vec<_> = (subs.get_subs_slice(fields.variables()).iter())
+                        .map(
+                            |&field_var| match Self::from_var(subs, field_var, options, cache) {
+                                Ok(Opaque(sym)) if cache.custom_encoders.contains(&sym) => {
+                                    Some(sym)
+                                }
+                                _ => None,
+                            },
+                        )
+                        .collect();
+                    if opaques.iter().any(Option::is_some) {
+                        let mut fields: Vec<_> = field_names.into_iter().zip(opaques).collect();
+                        fields.sort_by(|(f1, _), (f2, _)| f1.cmp(f2));
+
+                        return Ok(Key(FlatEncodableKey::RecordWithCustomEncoders(fields)));
+                    }
+
                     field_names.sort();
 
                     Ok(Key(FlatEncodableKey::Record(field_names)))
@@ -105,7 +412,10 @@ impl FlatEncodable {
                     // and
                     //   [ A t1, B t1 t2 ] as R
                     // look the same on the surface, because `R` is only somewhere inside of the
-                    // `t`-prefixed payload types.
+                    // `t`-prefixed payload types. This also keeps the key finite for recursive
+                    // types: the recursion points are not part of the key, since the generated
+                    // encoder recurses through `toEncoder` on the payloads rather than unrolling
+                    // them. `recursion_points` finds them when they are needed.
                     check_ext_var(subs, ext, |ext| {
                         matches!(ext, Content::Structure(FlatType::EmptyTagUnion))
                     })?;
@@ -114,50 +424,102 @@ impl FlatEncodable {
                         .iter_all()
                         .map(|(name_index, payload_slice_index)| {
                             let payload_slice = subs[payload_slice_index];
-                            let payload_size = payload_slice.length;
+                            // Don't truncate the arity, or differing tags could share a key.
+                            let payload_size =
+                                u16::try_from(payload_slice.len()).map_err(|_| TagArityTooLarge)?;
                             let name = &subs[name_index];
-                            (name.clone(), payload_size)
+                            Ok((name.clone(), payload_size))
                         })
-                        .collect();
+                        .collect::<Result<_, _>>()?;
+                    if tag_names_and_payload_sizes.is_empty() {
+                        return Err(UninhabitedType);
+                    }
                     tag_names_and_payload_sizes.sort_by(|(t1, _), (t2, _)| t1.cmp(t2));
                     Ok(Key(FlatEncodableKey::TagUnion(tag_names_and_payload_sizes)))
                 }
                 FlatType::FunctionOrTagUnion(name_index, _, _) => Ok(Key(
                     FlatEncodableKey::TagUnion(vec![(subs[name_index].clone(), 0)]),
                 )),
-                FlatType::EmptyRecord => Ok(Key(FlatEncodableKey::Record(vec![]))),
-                FlatType::EmptyTagUnion => Ok(Key(FlatEncodableKey::TagUnion(vec![]))),
+                FlatType::EmptyRecord => Ok(Immediate(Symbol::ENCODE_EMPTY_RECORD)),
+                // `[]` has no values, so `toEncoder` can never be called on one.
+                FlatType::EmptyTagUnion => Err(UninhabitedType),
                 //
                 FlatType::Erroneous(_) => Err(Underivable),
                 FlatType::Func(..) => Err(Underivable),
             },
-            Content::Alias(sym, _, real_var, _) => match sym {
-                Symbol::NUM_U8 | Symbol::NUM_UNSIGNED8 => Ok(Immediate(Symbol::ENCODE_U8)),
-                Symbol::NUM_U16 | Symbol::NUM_UNSIGNED16 => Ok(Immediate(Symbol::ENCODE_U16)),
-                Symbol::NUM_U32 | Symbol::NUM_UNSIGNED32 => Ok(Immediate(Symbol::ENCODE_U32)),
-                Symbol::NUM_U64 | Symbol::NUM_UNSIGNED64 => Ok(Immediate(Symbol::ENCODE_U64)),
-                Symbol::NUM_U128 | Symbol::NUM_UNSIGNED128 => Ok(Immediate(Symbol::ENCODE_U128)),
-                Symbol::NUM_I8 | Symbol::NUM_SIGNED8 => Ok(Immediate(Symbol::ENCODE_I8)),
-                Symbol::NUM_I16 | Symbol::NUM_SIGNED16 => Ok(Immediate(Symbol::ENCODE_I16)),
-                Symbol::NUM_I32 | Symbol::NUM_SIGNED32 => Ok(Immediate(Symbol::ENCODE_I32)),
-                Symbol::NUM_I64 | Symbol::NUM_SIGNED64 => Ok(Immediate(Symbol::ENCODE_I64)),
-                Symbol::NUM_I128 | Symbol::NUM_SIGNED128 => Ok(Immediate(Symbol::ENCODE_I128)),
-                Symbol::NUM_DEC | Symbol::NUM_DECIMAL => Ok(Immediate(Symbol::ENCODE_DEC)),
-                Symbol::NUM_F32 | Symbol::NUM_BINARY32 => Ok(Immediate(Symbol::ENCODE_F32)),
-                Symbol::NUM_F64 | Symbol::NUM_BINARY64 => Ok(Immediate(Symbol::ENCODE_F64)),
-                // TODO: I believe it is okay to unwrap opaques here because derivers are only used
-                // by the backend, and the backend treats opaques like structural aliases.
-                _ => Self::from_var(subs, real_var),
-            },
+            Content::Alias(sym, _, real_var, kind) => {
+                if let Some(info) = num_info(sym) {
+                    return Ok(Immediate(num_encoder(info)));
+                }
+                match sym {
+                    _ if kind == AliasKind::Opaque
+                        && !sym.is_builtin()
+                        && (options.opaque_encoders == OpaqueEncoders::OwnImplementation
+                            || cache.custom_encoders.contains(&sym)) =>
+                    {
+                        Ok(Opaque(sym))
+                    }
+                    // TODO: I believe it is okay to unwrap opaques here because derivers are only
+                    // used by the backend, and the backend treats opaques like structural aliases.
+                    _ => Self::from_var(subs, real_var, options, cache),
+                }
+            }
             Content::RangedNumber(_) => Err(Underivable),
             //
             Content::RecursionVar { .. } => Err(Underivable),
             Content::Error => Err(Underivable),
-            Content::FlexVar(_)
-            | Content::RigidVar(_)
-            | Content::FlexAbleVar(_, _)
-            | Content::RigidAbleVar(_, _) => Err(UnboundVar),
+            Content::FlexVar(_) | Content::FlexAbleVar(_, _) => Err(UnboundVar),
+            Content::RigidVar(_) | Content::RigidAbleVar(_, _)
+                if (cache.encoding_bounds.iter())
+                    .any(|bound| subs.equivalent_without_compacting(*bound, var)) =>
+            {
+                Ok(Immediate(Symbol::ENCODE_TO_ENCODER))
+            }
+            Content::RigidVar(_) | Content::RigidAbleVar(_, _) => Err(CannotDeriveForPolymorphic),
             Content::LambdaSet(_) => Err(Underivable),
         }
     }
 }
+
+/// Follows `var` through aliases and opaques that are encoded exactly like the type they stand
+/// for, under `options` and given the opaques with `custom_encoders`.
+fn skip_transparent_aliases(
+    subs: &Subs,
+    mut var: Variable,
+    options: DeriveOptions,
+    custom_encoders: &VecSet<Symbol>,
+) -> Variable {
+    while let Content::Alias(sym, _, real_var, kind) = *subs.get_content_without_compacting(var) {
+        let is_transparent = num_info(sym).is_none()
+            && (sym.is_builtin()
+                || kind == AliasKind::Structural
+                || (options.opaque_encoders == OpaqueEncoders::Unwrap
+                    && !custom_encoders.contains(&sym)));
+        if !is_transparent {
+            break;
+        }
+
+        var = real_var;
+    }
+
+    var
+}
+
+fn num_encoder(info: NumInfo) -> Symbol {
+    match (info.kind, info.signed, info.bits) {
+        (IntOrFrac::Int, false, 8) => Symbol::ENCODE_U8,
+        (IntOrFrac::Int, false, 16) => Symbol::ENCODE_U16,
+        (IntOrFrac::Int, false, 32) => Symbol::ENCODE_U32,
+        (IntOrFrac::Int, false, 64) => Symbol::ENCODE_U64,
+        (IntOrFrac::Int, false, 128) => Symbol::ENCODE_U128,
+        (IntOrFrac::Int, true, 8) => Symbol::ENCODE_I8,
+        (IntOrFrac::Int, true, 16) => Symbol::ENCODE_I16,
+        (IntOrFrac::Int, true, 32) => Symbol::ENCODE_I32,
+        (IntOrFrac::Int, true, 64) => Symbol::ENCODE_I64,
+        (IntOrFrac::Int, true, 128) => Symbol::ENCODE_I128,
+        (IntOrFrac::Frac, _, 32) => Symbol::ENCODE_F32,
+        (IntOrFrac::Frac, _, 64) => Symbol::ENCODE_F64,
+        (IntOrFrac::Frac, _, 128) => Symbol::ENCODE_DEC,
+        _ => internal_error!("No encoder for number {:?}", info),
+    }
+}
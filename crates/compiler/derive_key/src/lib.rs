@@ -15,20 +15,43 @@
 
 pub mod decoding;
 pub mod encoding;
+pub mod num;
+mod util;
 
 use decoding::{FlatDecodable, FlatDecodableKey};
-use encoding::{FlatEncodable, FlatEncodableKey};
+use encoding::{FlatEncodable, FlatEncodableKey, OpaqueEncoders, OpenRecordMode};
 
+use roc_can::abilities::{AbilitiesStore, ResolvedImpl};
+use roc_can::module::ExposedByModule;
+use roc_collections::{MutMap, MutSet, VecMap, VecSet};
 use roc_module::symbol::Symbol;
-use roc_types::subs::{Subs, Variable};
+use roc_types::subs::{Content, FlatType, GetSubsSlice, Subs, Variable};
+use roc_types::types::MemberImpl;
 
-#[derive(Debug, PartialEq)]
+/// Options for types that are not derivable, or derived differently, by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DeriveOptions {
+    /// Only affects encoders; decoders never accept open records.
+    pub open_records: OpenRecordMode,
+    /// Only affects encoders; decoders use an opaque's own implementation whenever it has one.
+    pub opaque_encoders: OpaqueEncoders,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum DeriveError {
     /// Unbound variable present in the type-to-derive. It may be possible to derive for this type
     /// once the unbound variable is resolved.
     UnboundVar,
+    /// Rigid variable present in the type-to-derive, like the `a` in `List a` of an annotation.
+    /// Unlike an unbound variable it will never be resolved, so the type must be made concrete,
+    /// or the variable bound to the ability, for an implementation to be derived.
+    CannotDeriveForPolymorphic,
     /// The type is underivable for the given ability member.
     Underivable,
+    /// A tag in the type has more payloads than a derive key can record.
+    TagArityTooLarge,
+    /// The type has no values, like `[]`, so there is nothing to derive an implementation for.
+    UninhabitedType,
 }
 
 #[derive(Hash, PartialEq, Eq, Debug, Clone)]
@@ -47,7 +70,7 @@ impl DeriveKey {
     }
 }
 
-#[derive(Hash, PartialEq, Eq, Debug)]
+#[derive(Hash, PartialEq, Eq, Debug, Clone)]
 pub enum Derived {
     /// If a derived implementation name is well-known ahead-of-time, we can inline the symbol
     /// directly rather than associating a key for an implementation to be made later on.
@@ -56,6 +79,8 @@ pub enum Derived {
     /// names to a key, when the key is known ahead-of-time but the implementation (and it's name)
     /// is yet-to-be-made.
     Key(DeriveKey),
+    /// The implementation is the one the opaque type declares for the ability member.
+    Opaque(Symbol),
 }
 
 /// The builtin ability member to derive.
@@ -65,6 +90,117 @@ pub enum DeriveBuiltin {
     Decoder,
 }
 
+/// Remembers how the types seen during one derivation pass were classified, by the root of
+/// their variable, so that types reached more than once are only traversed once. A cache must
+/// only be used with one [`Subs`] and one set of [`DeriveOptions`], and only while that `Subs`
+/// is not changed.
+#[derive(Debug, Default)]
+pub struct ClassificationCache {
+    encodable: MutMap<Variable, Result<FlatEncodable, DeriveError>>,
+    decodable: MutMap<Variable, Result<FlatDecodable, DeriveError>>,
+    /// Opaques that implement `toEncoder` themselves; see
+    /// [`ClassificationCache::with_custom_implementations`].
+    custom_encoders: VecSet<Symbol>,
+    /// Opaques that implement `decoder` themselves; see
+    /// [`ClassificationCache::with_custom_implementations`].
+    custom_decoders: VecSet<Symbol>,
+    /// Rigid variables known to be bound to `Encoding`; see
+    /// [`ClassificationCache::assume_encoding`].
+    encoding_bounds: VecSet<Variable>,
+}
+
+impl ClassificationCache {
+    /// A cache for a pass that encodes and decodes opaques with their own `toEncoder` and
+    /// `decoder`, rather than unwrapping them, whenever `exposed_types` says that implementation
+    /// was written by hand, like the one a JSON library writes for its `Value`. Opaques that
+    /// derive these members are unwrapped as usual, since the derived implementation would do the
+    /// same.
+    pub fn with_custom_implementations(exposed_types: &ExposedByModule) -> Self {
+        let custom = |member| {
+            (exposed_types.iter_all())
+                .flat_map(|(_, module)| module.resolved_implementations.iter())
+                .filter(|(impl_key, resolved)| {
+                    impl_key.ability_member == member && matches!(resolved, ResolvedImpl::Impl(_))
+                })
+                .map(|(impl_key, _)| impl_key.opaque)
+                .collect()
+        };
+
+        Self {
+            custom_encoders: custom(Symbol::ENCODE_TO_ENCODER),
+            custom_decoders: custom(Symbol::DECODE_DECODER),
+            ..Default::default()
+        }
+    }
+
+    /// Also decodes the opaques that implement `decoder` by hand in `abilities_store`, which
+    /// holds the implementations of the module being compiled and those it imports.
+    pub fn add_custom_decoders(&mut self, abilities_store: &AbilitiesStore) {
+        let custom_decoders = (abilities_store.iter_declared_implementations())
+            .filter(|(impl_key, member_impl)| {
+                impl_key.ability_member == Symbol::DECODE_DECODER
+                    && matches!(member_impl, MemberImpl::Impl(_))
+            })
+            .map(|(impl_key, _)| impl_key.opaque);
+
+        self.custom_decoders.extend(custom_decoders);
+    }
+
+    /// Treats the rigid variable `var` as bound to `Encoding` by the surrounding context, like the
+    /// `a` of `Wrapper a` in a signature with `a has Encoding`. Rather than being underivable, it
+    /// is encoded with the `toEncoder` of whatever type it is eventually instantiated with. Must
+    /// be called before any type containing `var` is classified.
+    pub fn assume_encoding(&mut self, var: Variable) {
+        self.encoding_bounds.insert(var);
+    }
+
+    /// Assumes `Encoding` for every rigid variable reachable from `var` that is bound to it, as
+    /// with [`ClassificationCache::assume_encoding`].
+    pub fn assume_encoding_bounds(&mut self, subs: &Subs, var: Variable) {
+        walk_reachable(subs, var, |var, content| {
+            if let Content::RigidAbleVar(_, Symbol::ENCODE_ENCODING) = content {
+                self.encoding_bounds.insert(var);
+            }
+            false
+        });
+    }
+
+    /// Forgets the types classified so far and the variables assumed to have `Encoding`, for when
+    /// the [`Subs`] they were classified in has changed, like after a unification between two
+    /// derivations.
+    pub fn forget_classifications(&mut self) {
+        self.encodable.clear();
+        self.decodable.clear();
+        self.encoding_bounds = VecSet::default();
+    }
+
+    /// The number of types classified so far.
+    pub fn len(&self) -> usize {
+        self.encodable.len() + self.decodable.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Derived implementations for many variables at once; see [`Derived::builtin_for_all`].
+#[derive(Debug, Default)]
+pub struct DerivedBatch {
+    /// Each distinct derived implementation, in the order they were first needed.
+    pub derived: Vec<Derived>,
+    /// For each variable, the index of its implementation in `derived`, or why there is none.
+    pub by_var: VecMap<Variable, Result<usize, DeriveError>>,
+}
+
+impl DerivedBatch {
+    pub fn get(&self, var: Variable) -> Option<Result<&Derived, &DeriveError>> {
+        self.by_var
+            .get(&var)
+            .map(|result| result.as_ref().map(|&index| &self.derived[index]))
+    }
+}
+
 impl TryFrom<Symbol> for DeriveBuiltin {
     type Error = Symbol;
 
@@ -82,16 +218,161 @@ impl Derived {
         builtin: DeriveBuiltin,
         subs: &Subs,
         var: Variable,
+    ) -> Result<Self, DeriveError> {
+        Self::builtin_with_open_records(builtin, subs, var, OpenRecordMode::default())
+    }
+
+    /// Like [`Derived::builtin`], but decides how records with an open extension are encoded.
+    pub fn builtin_with_open_records(
+        builtin: DeriveBuiltin,
+        subs: &Subs,
+        var: Variable,
+        open_records: OpenRecordMode,
+    ) -> Result<Self, DeriveError> {
+        let options = DeriveOptions {
+            open_records,
+            ..Default::default()
+        };
+
+        Self::builtin_with_options(builtin, subs, var, options)
+    }
+
+    /// Computes the derived implementations of every variable in `vars` in one pass, so that a
+    /// deriver can warm its cache up front rather than at each use site. Variables of the same
+    /// shape share one entry.
+    pub fn builtin_for_all(
+        builtin: DeriveBuiltin,
+        subs: &Subs,
+        vars: &[Variable],
+        options: DeriveOptions,
+    ) -> DerivedBatch {
+        let mut batch = DerivedBatch::default();
+        let mut index_of: MutMap<Derived, usize> = MutMap::default();
+        let mut result_of_root: MutMap<Variable, Result<usize, DeriveError>> = MutMap::default();
+        let mut cache = ClassificationCache::default();
+
+        for &var in vars {
+            // Variables unified with one we have already seen have the same implementation.
+            let root = subs.get_root_key_without_compacting(var);
+            let result = *result_of_root.entry(root).or_insert_with(|| {
+                let derived = Self::builtin_with_cache(builtin, subs, var, options, &mut cache)?;
+                let next_index = batch.derived.len();
+                let index = *index_of.entry(derived.clone()).or_insert(next_index);
+                if index == next_index {
+                    batch.derived.push(derived);
+                }
+
+                Ok(index)
+            });
+
+            batch.by_var.insert(var, result);
+        }
+
+        batch
+    }
+
+    /// Like [`Derived::builtin`], but with non-default [`DeriveOptions`].
+    pub fn builtin_with_options(
+        builtin: DeriveBuiltin,
+        subs: &Subs,
+        var: Variable,
+        options: DeriveOptions,
+    ) -> Result<Self, DeriveError> {
+        let mut cache = ClassificationCache::default();
+
+        Self::builtin_with_cache(builtin, subs, var, options, &mut cache)
+    }
+
+    /// Like [`Derived::builtin_with_options`], but reuses and extends the classifications of a
+    /// derivation pass that is already under way.
+    pub fn builtin_with_cache(
+        builtin: DeriveBuiltin,
+        subs: &Subs,
+        var: Variable,
+        options: DeriveOptions,
+        cache: &mut ClassificationCache,
     ) -> Result<Self, DeriveError> {
         match builtin {
-            DeriveBuiltin::ToEncoder => match encoding::FlatEncodable::from_var(subs, var)? {
-                FlatEncodable::Immediate(imm) => Ok(Derived::Immediate(imm)),
-                FlatEncodable::Key(repr) => Ok(Derived::Key(DeriveKey::ToEncoder(repr))),
-            },
-            DeriveBuiltin::Decoder => match decoding::FlatDecodable::from_var(subs, var)? {
-                FlatDecodable::Immediate(imm) => Ok(Derived::Immediate(imm)),
-                FlatDecodable::Key(repr) => Ok(Derived::Key(DeriveKey::Decoder(repr))),
+            DeriveBuiltin::ToEncoder => {
+                match encoding::FlatEncodable::from_var(subs, var, options, cache)? {
+                    FlatEncodable::Immediate(imm) => Ok(Derived::Immediate(imm)),
+                    FlatEncodable::Key(repr) => Ok(Derived::Key(DeriveKey::ToEncoder(repr))),
+                    FlatEncodable::Opaque(opaque) => Ok(Derived::Opaque(opaque)),
+                }
+            }
+            DeriveBuiltin::Decoder => {
+                match decoding::FlatDecodable::from_var(subs, var, options, cache)? {
+                    FlatDecodable::Immediate(imm) => Ok(Derived::Immediate(imm)),
+                    FlatDecodable::Key(repr) => Ok(Derived::Key(DeriveKey::Decoder(repr))),
+                    FlatDecodable::Opaque(opaque) => Ok(Derived::Opaque(opaque)),
+                }
+            }
+        }
+    }
+}
+
+/// Whether a recursive type is reachable from `var`, so that an implementation derived for it
+/// must recurse rather than unroll the type.
+pub fn is_recursive(subs: &Subs, var: Variable) -> bool {
+    walk_reachable(subs, var, |_, content| {
+        matches!(
+            content,
+            Content::RecursionVar { .. } | Content::Structure(FlatType::RecursiveTagUnion(..))
+        )
+    })
+}
+
+/// Visits every variable reachable from `var` once, until `visit` returns `true`; returns whether
+/// it did.
+fn walk_reachable(
+    subs: &Subs,
+    var: Variable,
+    mut visit: impl FnMut(Variable, &Content) -> bool,
+) -> bool {
+    let mut stack = vec![var];
+    let mut seen = MutSet::default();
+
+    while let Some(var) = stack.pop() {
+        if !seen.insert(subs.get_root_key_without_compacting(var)) {
+            continue;
+        }
+
+        let content = subs.get_content_without_compacting(var);
+        if visit(var, content) {
+            return true;
+        }
+
+        match *content {
+            Content::RecursionVar { structure, .. } => stack.push(structure),
+            Content::Structure(flat_type) => match flat_type {
+                FlatType::Apply(_, args) => stack.extend(subs.get_subs_slice(args)),
+                FlatType::Func(args, _closure, ret) => {
+                    stack.extend(subs.get_subs_slice(args));
+                    stack.push(ret);
+                }
+                FlatType::Record(fields, ext) => {
+                    stack.extend(subs.get_subs_slice(fields.variables()));
+                    stack.push(ext);
+                }
+                FlatType::TagUnion(tags, ext) | FlatType::RecursiveTagUnion(_, tags, ext) => {
+                    for payloads in tags.variables() {
+                        stack.extend(subs.get_subs_slice(subs[payloads]));
+                    }
+                    stack.push(ext);
+                }
+                FlatType::FunctionOrTagUnion(_, _, ext) => stack.push(ext),
+                FlatType::EmptyRecord | FlatType::EmptyTagUnion | FlatType::Erroneous(_) => {}
             },
+            Content::Alias(_, _, real_var, _) => stack.push(real_var),
+            Content::FlexVar(_)
+            | Content::RigidVar(_)
+            | Content::FlexAbleVar(..)
+            | Content::RigidAbleVar(..)
+            | Content::LambdaSet(_)
+            | Content::RangedNumber(_)
+            | Content::Error => {}
         }
     }
+
+    false
 }
@@ -0,0 +1,110 @@
+//! Structured information about the builtin number types, shared by derivers that handle numbers
+//! specially.
+
+use roc_module::symbol::{ModuleId, Symbol};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IntOrFrac {
+    Int,
+    Frac,
+}
+
+/// The signedness, width, and kind of a builtin number type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NumInfo {
+    pub signed: bool,
+    pub bits: u8,
+    pub kind: IntOrFrac,
+}
+
+impl NumInfo {
+    const fn int(signed: bool, bits: u8) -> Self {
+        Self {
+            signed,
+            bits,
+            kind: IntOrFrac::Int,
+        }
+    }
+
+    const fn frac(bits: u8) -> Self {
+        Self {
+            signed: true,
+            bits,
+            kind: IntOrFrac::Frac,
+        }
+    }
+}
+
+/// Every builtin number alias, including the ones standing for storage types, like `Signed8`.
+const NUM_ALIASES: [(Symbol, NumInfo); 28] = [
+    (Symbol::NUM_U8, NumInfo::int(false, 8)),
+    (Symbol::NUM_UNSIGNED8, NumInfo::int(false, 8)),
+    (Symbol::NUM_U16, NumInfo::int(false, 16)),
+    (Symbol::NUM_UNSIGNED16, NumInfo::int(false, 16)),
+    (Symbol::NUM_U32, NumInfo::int(false, 32)),
+    (Symbol::NUM_UNSIGNED32, NumInfo::int(false, 32)),
+    (Symbol::NUM_U64, NumInfo::int(false, 64)),
+    (Symbol::NUM_UNSIGNED64, NumInfo::int(false, 64)),
+    (Symbol::NUM_U128, NumInfo::int(false, 128)),
+    (Symbol::NUM_UNSIGNED128, NumInfo::int(false, 128)),
+    // TODO: Nat is platform specific! The typechecker treats it as 64 bits wide
+    // (see `IntLitWidth::Nat`), so we do the same here.
+    (Symbol::NUM_NAT, NumInfo::int(false, 64)),
+    (Symbol::NUM_NATURAL, NumInfo::int(false, 64)),
+    (Symbol::NUM_I8, NumInfo::int(true, 8)),
+    (Symbol::NUM_SIGNED8, NumInfo::int(true, 8)),
+    (Symbol::NUM_I16, NumInfo::int(true, 16)),
+    (Symbol::NUM_SIGNED16, NumInfo::int(true, 16)),
+    (Symbol::NUM_I32, NumInfo::int(true, 32)),
+    (Symbol::NUM_SIGNED32, NumInfo::int(true, 32)),
+    (Symbol::NUM_I64, NumInfo::int(true, 64)),
+    (Symbol::NUM_SIGNED64, NumInfo::int(true, 64)),
+    (Symbol::NUM_I128, NumInfo::int(true, 128)),
+    (Symbol::NUM_SIGNED128, NumInfo::int(true, 128)),
+    (Symbol::NUM_DEC, NumInfo::frac(128)),
+    (Symbol::NUM_DECIMAL, NumInfo::frac(128)),
+    (Symbol::NUM_F32, NumInfo::frac(32)),
+    (Symbol::NUM_BINARY32, NumInfo::frac(32)),
+    (Symbol::NUM_F64, NumInfo::frac(64)),
+    (Symbol::NUM_BINARY64, NumInfo::frac(64)),
+];
+
+const NUM_INFO_TABLE_LEN: usize = {
+    let mut len = 0;
+    let mut i = 0;
+    while i < NUM_ALIASES.len() {
+        let index = NUM_ALIASES[i].0.ident_id().index();
+        if index >= len {
+            len = index + 1;
+        }
+        i += 1;
+    }
+    len
+};
+
+/// [`NUM_ALIASES`] indexed by the identifier of each alias within the `Num` module, which all of
+/// them belong to. Classifying a type looks up every alias it goes through, so this saves
+/// matching on each number alias in turn.
+static NUM_INFO_BY_IDENT: [Option<NumInfo>; NUM_INFO_TABLE_LEN] = {
+    let mut table = [None; NUM_INFO_TABLE_LEN];
+    let mut i = 0;
+    while i < NUM_ALIASES.len() {
+        let (symbol, info) = NUM_ALIASES[i];
+        table[symbol.ident_id().index()] = Some(info);
+        i += 1;
+    }
+    table
+};
+
+/// Classifies a builtin number alias, like `U8` or `Signed8`. Returns [`None`] for any other
+/// symbol.
+pub fn num_info(symbol: Symbol) -> Option<NumInfo> {
+    if symbol.module_id() != ModuleId::NUM {
+        return None;
+    }
+
+    NUM_INFO_BY_IDENT
+        .get(symbol.ident_id().index())
+        .copied()
+        .flatten()
+}
@@ -0,0 +1,23 @@
+use roc_module::ident::Lowercase;
+
+/// If the given record field names are exactly `0`, `1`, .., `n - 1` (in any order), the record
+/// models an `n`-tuple; returns `n`.
+pub(crate) fn tuple_arity(field_names: &[Lowercase]) -> Option<usize> {
+    let arity = field_names.len();
+    if arity == 0 {
+        // `{}` is the empty record, not the empty tuple.
+        return None;
+    }
+
+    let mut seen = vec![false; arity];
+    for name in field_names {
+        let index: usize = name.as_str().parse().ok()?;
+        // Reject spellings like `01` or `+1`.
+        if index >= arity || seen[index] || index.to_string() != name.as_str() {
+            return None;
+        }
+        seen[index] = true;
+    }
+
+    Some(arity)
+}
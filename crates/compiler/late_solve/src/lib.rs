@@ -183,6 +183,14 @@ impl Phase for LatePhase<'_> {
         self.abilities.with_module_abilities_store(module, f)
     }
 
+    #[inline(always)]
+    fn with_home_abilities_store<T, F>(&self, f: F) -> T
+    where
+        F: FnMut(&AbilitiesStore) -> T,
+    {
+        self.abilities.with_module_abilities_store(self.home, f)
+    }
+
     #[inline(always)]
     fn copy_lambda_set_ambient_function_to_home_subs(
         &self,
@@ -1392,6 +1392,7 @@ define_builtins! {
         23 ENCODE_APPEND_WITH: "appendWith"
         24 ENCODE_APPEND: "append"
         25 ENCODE_TO_BYTES: "toBytes"
+        26 ENCODE_EMPTY_RECORD: "emptyRecord"
     }
     12 DECODE: "Decode" => {
         0 DECODE_DECODE_ERROR: "DecodeError"
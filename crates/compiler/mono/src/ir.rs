@@ -33,6 +33,7 @@ use roc_types::subs::{
     instantiate_rigids, Content, ExhaustiveMark, FlatType, RedundantMark, StorageSubs, Subs,
     Variable, VariableSubsSlice,
 };
+use roc_types::types::MemberImpl;
 use std::collections::HashMap;
 use ven_pretty::{BoxAllocator, DocAllocator, DocBuilder};
 
@@ -5244,10 +5245,26 @@ fn late_resolve_ability_specialization<'a>(
         match specialization {
             Resolved::Specialization(symbol) => symbol,
             Resolved::NeedsGenerated(var) => {
-                let derive_key = roc_derive_key::Derived::builtin(
+                // Classify like solving did, so that both agree on the implementation to use.
+                let derive_options = env
+                    .derived_module
+                    .lock()
+                    .expect("derived module unavailable")
+                    .derive_options();
+                let mut classification_cache =
+                    roc_derive_key::ClassificationCache::with_custom_implementations(
+                        env.exposed_by_module,
+                    );
+                env.abilities
+                    .with_module_abilities_store(env.home, |store| {
+                        classification_cache.add_custom_decoders(store)
+                    });
+                let derive_key = roc_derive_key::Derived::builtin_with_cache(
                     member.try_into().expect("derived symbols must be builtins"),
                     env.subs,
                     var,
+                    derive_options,
+                    &mut classification_cache,
                 )
                 .expect("specialization var not derivable!");
 
@@ -5266,6 +5283,27 @@ fn late_resolve_ability_specialization<'a>(
                             .get_or_insert(env.exposed_by_module, derive_key)
                             .0
                     }
+                    roc_derive_key::Derived::Opaque(opaque) => {
+                        let impl_key = roc_can::abilities::ImplKey {
+                            opaque,
+                            ability_member: member,
+                        };
+                        let member_impl = env
+                            .abilities
+                            .with_module_abilities_store(opaque.module_id(), |store| {
+                                store.get_implementation(impl_key).copied()
+                            });
+
+                        match member_impl {
+                            Some(MemberImpl::Impl(spec_symbol)) => spec_symbol,
+                            Some(MemberImpl::Derived) => {
+                                todo_abilities!("derived implementations for opaques")
+                            }
+                            Some(MemberImpl::Error) | None => {
+                                internal_error!("{:?} does not implement {:?}", opaque, member)
+                            }
+                        }
+                    }
                 }
             }
         }
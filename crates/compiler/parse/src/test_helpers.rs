@@ -31,6 +31,18 @@ pub fn parse_loc_with<'a>(
     }
 }
 
+pub fn parse_annotation_with<'a>(
+    arena: &'a Bump,
+    input: &'a str,
+) -> Result<Loc<ast::TypeAnnotation<'a>>, SyntaxError<'a>> {
+    let state = State::new(input.trim().as_bytes());
+
+    match crate::type_annotation::located(0, false).parse(arena, state) {
+        Ok((_, loc_annotation, _)) => Ok(loc_annotation),
+        Err((_, fail, _)) => Err(SyntaxError::Type(fail)),
+    }
+}
+
 pub fn parse_defs_with<'a>(arena: &'a Bump, input: &'a str) -> Result<Defs<'a>, SyntaxError<'a>> {
     let state = State::new(input.trim().as_bytes());
 
@@ -177,10 +177,23 @@ fn loc_wildcard<'a>() -> impl Parser<'a, Loc<TypeAnnotation<'a>>, EType<'a>> {
     })
 }
 
-/// The `_` indicating an inferred type, e.g. in (List _)
+/// The `_` indicating an inferred type, e.g. in (List _), or a named inferred type like the `_a`
+/// in (_a -> _a), all occurrences of which are inferred to be the same type.
 fn loc_inferred<'a>() -> impl Parser<'a, Loc<TypeAnnotation<'a>>, EType<'a>> {
-    map!(loc!(word1(b'_', EType::TInferred)), |loc_val: Loc<()>| {
-        loc_val.map(|_| TypeAnnotation::Inferred)
+    loc!(move |arena, state: State<'a>| {
+        let initial_bytes = state.bytes();
+        let (_, (), state) = word1(b'_', EType::TInferred).parse(arena, state)?;
+
+        match lowercase_ident().parse(arena, state.clone()) {
+            Ok((_, name, state)) => {
+                // The name includes the leading underscore
+                let name =
+                    unsafe { std::str::from_utf8_unchecked(&initial_bytes[..name.len() + 1]) };
+
+                Ok((MadeProgress, TypeAnnotation::BoundVariable(name), state))
+            }
+            Err(_) => Ok((MadeProgress, TypeAnnotation::Inferred, state)),
+        }
     })
 }
 
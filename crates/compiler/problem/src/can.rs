@@ -101,10 +101,73 @@ pub enum Problem {
         def_region: Region,
         differing_recursion_region: Region,
     },
+    /// An `as` alias without parameters, like `Foo` in `Bar as Foo`, that just renames the
+    /// existing alias `Bar`.
+    RedundantAsAlias {
+        alias: Symbol,
+        existing: Symbol,
+        region: Region,
+    },
+    /// An `as` alias that refers to itself somewhere other than in the payload of a tag, like
+    /// `{ next : Node } as Node`, which would describe an infinitely large type.
+    InfiniteType {
+        alias: Symbol,
+        region: Region,
+    },
     InvalidExtensionType {
         region: Region,
         kind: ExtensionTypeKind,
     },
+    /// A `has` clause bound a variable the annotation never uses, like `a` in
+    /// `Str -> Str | a has Hash`, so the constraint could never apply to anything.
+    UnusedHasClauseVariable {
+        name: Lowercase,
+        region: Region,
+    },
+    /// An annotation of a type that no value can have, like `[]`.
+    UninhabitedType {
+        region: Region,
+    },
+    /// A `has Phantom` clause written where a type or ability named `Phantom` is in scope, so it
+    /// refers to that rather than marking its variable as intentionally phantom.
+    PhantomMarkerShadowed {
+        symbol: Symbol,
+        region: Region,
+    },
+    /// A function argument annotated with a closed record or tag union, like `{ name : Str }`,
+    /// where an open one like `{ name : Str }*` is usually meant. Only reported when
+    /// `Env::suggest_open_extensions` is set.
+    ClosedArgumentExtension {
+        region: Region,
+        kind: ExtensionTypeKind,
+    },
+    /// An annotation introduced more `_` and `*` variables than `Env::max_inferred_variables`
+    /// allows. The region is that of the first variable past the limit.
+    TooManyInferredVariables {
+        limit: usize,
+        region: Region,
+    },
+    /// An annotation referred to a type that `Env::symbol_policy` forbids.
+    DisallowedTypeReference {
+        symbol: Symbol,
+        region: Region,
+    },
+    /// An annotation referred to a builtin type that is not in `Env::allowed_builtin_types`.
+    TypeNotAllowedInContext {
+        symbol: Symbol,
+        region: Region,
+    },
+    /// A record type was projected to a field it does not have.
+    FieldNotInRecord {
+        field: Lowercase,
+        region: Region,
+    },
+    /// An annotation referred to an opaque type of another module that is not re-exported. Only
+    /// reported when `Env::reexported_opaques` is set.
+    OpaqueNotExported {
+        opaque: Symbol,
+        region: Region,
+    },
     AbilityHasTypeVariables {
         name: Symbol,
         variables_region: Region,
@@ -120,6 +183,12 @@ pub enum Problem {
         ability: Symbol,
         region: Region,
     },
+    AbilityMemberMissingSelf {
+        member: Symbol,
+        ability: Symbol,
+        var_name: Lowercase,
+        region: Region,
+    },
     AbilityMemberMultipleBoundVars {
         member: Symbol,
         ability: Symbol,
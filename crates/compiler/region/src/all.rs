@@ -37,19 +37,20 @@ impl Region {
     where
         I: IntoIterator<Item = &'a Region>,
     {
-        let mut it = regions.into_iter();
-
-        if let Some(first) = it.next() {
-            let mut result = *first;
+        Self::span_across_all(regions.into_iter().copied()).unwrap_or_else(Self::zero)
+    }
 
-            for r in it {
-                result = Self::span_across(&result, r);
-            }
+    /// The region from the start of the first of `regions` to the end of the last, like the span
+    /// of all the arguments in `Dict Str U64`. `None` if there are no regions.
+    pub fn span_across_all<I>(regions: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = Region>,
+    {
+        let mut it = regions.into_iter();
+        let first = it.next()?;
+        let last = it.last().unwrap_or(first);
 
-            result
-        } else {
-            Self::zero()
-        }
+        Some(Self::span_across(&first, &last))
     }
 
     pub const fn from_pos(pos: Position) -> Self {
@@ -453,3 +454,15 @@ fn test_line_info() {
 
     check_correctness(&["", ""]);
 }
+
+#[test]
+fn test_span_across_all() {
+    let region = |start, end| Region::new(Position::new(start), Position::new(end));
+
+    assert_eq!(Region::span_across_all([]), None);
+    assert_eq!(Region::span_across_all([region(3, 5)]), Some(region(3, 5)));
+    assert_eq!(
+        Region::span_across_all([region(3, 5), region(6, 9), region(10, 14)]),
+        Some(region(3, 14))
+    );
+}
@@ -12,7 +12,7 @@ use roc_debug_flags::dbg_do;
 #[cfg(debug_assertions)]
 use roc_debug_flags::ROC_TRACE_COMPACTION;
 use roc_derive::SharedDerivedModule;
-use roc_derive_key::{DeriveError, DeriveKey};
+use roc_derive_key::{ClassificationCache, DeriveBuiltin, DeriveError, DeriveKey, DeriveOptions};
 use roc_error_macros::{internal_error, todo_abilities};
 use roc_module::symbol::{ModuleId, Symbol};
 use roc_types::{
@@ -40,6 +40,11 @@ pub trait Phase {
     where
         F: FnMut(&AbilitiesStore) -> T;
 
+    /// Like [`Phase::with_module_abilities_store`], for the module being compiled.
+    fn with_home_abilities_store<T, F>(&self, f: F) -> T
+    where
+        F: FnMut(&AbilitiesStore) -> T;
+
     /// Given a known lambda set's ambient function in an external module, copy that ambient
     /// function into the given subs.
     fn copy_lambda_set_ambient_function_to_home_subs(
@@ -73,6 +78,13 @@ impl Phase for SolvePhase<'_> {
         f(self.abilities_store)
     }
 
+    fn with_home_abilities_store<T, F>(&self, mut f: F) -> T
+    where
+        F: FnMut(&AbilitiesStore) -> T,
+    {
+        f(self.abilities_store)
+    }
+
     fn copy_lambda_set_ambient_function_to_home_subs(
         &self,
         external_lambda_set_var: Variable,
@@ -311,6 +323,10 @@ pub fn compact_lambda_sets_of_vars<P: Phase>(
 ) -> CompactionResult {
     let mut must_implement = MustImplementConstraints::default();
     let mut awaiting_specialization = AwaitingSpecializations::default();
+    let derive_options = derived_env.derived_module.lock().unwrap().derive_options();
+    let mut classification_cache =
+        ClassificationCache::with_custom_implementations(derived_env.exposed_types);
+    phase.with_home_abilities_store(|store| classification_cache.add_custom_decoders(store));
 
     let mut uls_of_var_queue = VecDeque::with_capacity(uls_of_var.len());
     uls_of_var_queue.extend(uls_of_var.drain());
@@ -436,8 +452,17 @@ pub fn compact_lambda_sets_of_vars<P: Phase>(
         //    3. Unify `t_f1 ~ t_f2`.
         trace_compact!(3start.);
         for l in uls_a {
-            let compaction_result =
-                compact_lambda_set(subs, derived_env, arena, pools, c_a, l, phase);
+            let compaction_result = compact_lambda_set(
+                subs,
+                derived_env,
+                derive_options,
+                &mut classification_cache,
+                arena,
+                pools,
+                c_a,
+                l,
+                phase,
+            );
 
             match compaction_result {
                 OneCompactionResult::Compacted {
@@ -473,6 +498,8 @@ enum OneCompactionResult {
 fn compact_lambda_set<P: Phase>(
     subs: &mut Subs,
     derived_env: &DerivedEnv,
+    derive_options: DeriveOptions,
+    classification_cache: &mut ClassificationCache,
     arena: &Bump,
     pools: &mut Pools,
     resolved_concrete: Variable,
@@ -506,7 +533,11 @@ fn compact_lambda_set<P: Phase>(
     // - proceed with specialization
     // - simply drop the specialization lambda set (due to an error)
     // - or do we need to wait, because we don't know enough information for the specialization yet?
-    let specialization_decision = make_specialization_decision(subs, phase, c, f);
+    //
+    // Each compaction unifies types, so what was classified for the last one may be stale.
+    classification_cache.forget_classifications();
+    let specialization_decision =
+        make_specialization_decision(subs, phase, derive_options, classification_cache, c, f);
     let specialization_key_or_drop = match specialization_decision {
         SpecializeDecision::Specialize(key) => Ok(key),
         SpecializeDecision::Drop => Err(()),
@@ -616,6 +647,8 @@ enum SpecializeDecision {
 fn make_specialization_decision<P: Phase>(
     subs: &Subs,
     phase: &P,
+    derive_options: DeriveOptions,
+    classification_cache: &mut ClassificationCache,
     var: Variable,
     ability_member: Symbol,
 ) -> SpecializeDecision {
@@ -623,45 +656,26 @@ fn make_specialization_decision<P: Phase>(
     use SpecializationTypeKey::*;
     match subs.get_content_without_compacting(var) {
         Alias(opaque, _, _, AliasKind::Opaque) if opaque.module_id() != ModuleId::NUM => {
-            if P::IS_LATE {
-                SpecializeDecision::Specialize(Opaque(*opaque))
-            } else {
-                // Solving within a module.
-                phase.with_module_abilities_store(opaque.module_id(), |abilities_store| {
-                    let impl_key = ImplKey {
-                        opaque: *opaque,
-                        ability_member,
-                    };
-                    match abilities_store.get_implementation(impl_key) {
-                        None => {
-                            // Doesn't specialize; an error will already be reported for this.
-                            SpecializeDecision::Drop
-                        }
-                        Some(MemberImpl::Error | MemberImpl::Derived) => {
-                            // TODO: probably not right, we may want to choose a derive decision!
-                            SpecializeDecision::Specialize(Opaque(*opaque))
-                        }
-                        Some(MemberImpl::Impl(specialization_symbol)) => {
-                            match abilities_store.specialization_info(*specialization_symbol) {
-                                Some(_) => SpecializeDecision::Specialize(Opaque(*opaque)),
-
-                                // If we expect a specialization impl but don't yet know it, we must hold off
-                                // compacting the lambda set until the specialization is well-known.
-                                None => SpecializeDecision::PendingSpecialization(impl_key),
-                            }
-                        }
-                    }
-                })
-            }
+            opaque_specialization_decision(phase, *opaque, ability_member)
         }
         Structure(_) | Alias(_, _, _, _) => {
             let builtin = match ability_member.try_into() {
                 Ok(builtin) => builtin,
                 Err(_) => return SpecializeDecision::Drop,
             };
+            if let DeriveBuiltin::ToEncoder = builtin {
+                // Rigids the signature binds to `Encoding` are encoded with their own `toEncoder`.
+                classification_cache.assume_encoding_bounds(subs, var);
+            }
 
             // This is a structural type, find the derived ability function it should use.
-            match roc_derive_key::Derived::builtin(builtin, subs, var) {
+            match roc_derive_key::Derived::builtin_with_cache(
+                builtin,
+                subs,
+                var,
+                derive_options,
+                classification_cache,
+            ) {
                 Ok(derived) => match derived {
                     roc_derive_key::Derived::Immediate(imm) => {
                         SpecializeDecision::Specialize(Immediate(imm))
@@ -669,6 +683,9 @@ fn make_specialization_decision<P: Phase>(
                     roc_derive_key::Derived::Key(derive_key) => {
                         SpecializeDecision::Specialize(Derived(derive_key))
                     }
+                    roc_derive_key::Derived::Opaque(opaque) => {
+                        opaque_specialization_decision(phase, opaque, ability_member)
+                    }
                 },
                 Err(DeriveError::UnboundVar) => {
                     // not specialized yet, but that also means that it can't possibly be derivable
@@ -676,7 +693,12 @@ fn make_specialization_decision<P: Phase>(
                     // TODO: is this right? Revisit if it causes us problems in the future.
                     SpecializeDecision::Drop
                 }
-                Err(DeriveError::Underivable) => {
+                Err(
+                    DeriveError::CannotDeriveForPolymorphic
+                    | DeriveError::Underivable
+                    | DeriveError::TagArityTooLarge
+                    | DeriveError::UninhabitedType,
+                ) => {
                     // we should have reported an error for this; drop the lambda set.
                     SpecializeDecision::Drop
                 }
@@ -695,6 +717,45 @@ fn make_specialization_decision<P: Phase>(
     }
 }
 
+fn opaque_specialization_decision<P: Phase>(
+    phase: &P,
+    opaque: Symbol,
+    ability_member: Symbol,
+) -> SpecializeDecision {
+    use SpecializationTypeKey::*;
+
+    if P::IS_LATE {
+        SpecializeDecision::Specialize(Opaque(opaque))
+    } else {
+        // Solving within a module.
+        phase.with_module_abilities_store(opaque.module_id(), |abilities_store| {
+            let impl_key = ImplKey {
+                opaque,
+                ability_member,
+            };
+            match abilities_store.get_implementation(impl_key) {
+                None => {
+                    // Doesn't specialize; an error will already be reported for this.
+                    SpecializeDecision::Drop
+                }
+                Some(MemberImpl::Error | MemberImpl::Derived) => {
+                    // TODO: probably not right, we may want to choose a derive decision!
+                    SpecializeDecision::Specialize(Opaque(opaque))
+                }
+                Some(MemberImpl::Impl(specialization_symbol)) => {
+                    match abilities_store.specialization_info(*specialization_symbol) {
+                        Some(_) => SpecializeDecision::Specialize(Opaque(opaque)),
+
+                        // If we expect a specialization impl but don't yet know it, we must hold off
+                        // compacting the lambda set until the specialization is well-known.
+                        None => SpecializeDecision::PendingSpecialization(impl_key),
+                    }
+                }
+            }
+        })
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 fn get_specialization_lambda_set_ambient_function<P: Phase>(
     subs: &mut Subs,
@@ -1043,6 +1043,51 @@ mod solve_expr {
         );
     }
 
+    #[test]
+    fn named_inferred_variables_are_shared() {
+        infer_eq_without_problem(
+            indoc!(
+                r#"
+                    f : _a -> _a
+                    f = \_ -> "stuff"
+
+                    f
+                "#
+            ),
+            "Str -> Str",
+        );
+    }
+
+    #[test]
+    fn named_inferred_variables_unify_with_concrete_types() {
+        infer_eq_without_problem(
+            indoc!(
+                r#"
+                    f : _a -> Str
+                    f = \x -> Str.concat x "!"
+
+                    f
+                "#
+            ),
+            "Str -> Str",
+        );
+    }
+
+    #[test]
+    fn top_level_named_inferred_variables_unify_with_concrete_types() {
+        infer_eq_without_problem(
+            indoc!(
+                r#"
+                app "test" provides [main] to "./platform"
+
+                main : _a -> Str
+                main = \x -> Str.concat x "!"
+                "#
+            ),
+            "Str -> Str",
+        );
+    }
+
     #[test]
     fn identity_returns_given_type() {
         infer_eq(
@@ -3970,6 +4015,48 @@ mod solve_expr {
         );
     }
 
+    #[test]
+    fn int_wildcard_annotation_stays_polymorphic() {
+        infer_eq_without_problem(
+            indoc!(
+                r#"
+                    anyInt : Int *
+                    anyInt = 5
+
+                    small : U8
+                    small = anyInt
+
+                    big : I128
+                    big = anyInt
+
+                    { anyInt, small, big }
+                "#
+            ),
+            "{ anyInt : Int *, big : I128, small : U8 }",
+        );
+    }
+
+    #[test]
+    fn frac_wildcard_annotation_stays_polymorphic() {
+        infer_eq_without_problem(
+            indoc!(
+                r#"
+                    anyFrac : Frac *
+                    anyFrac = 0.5
+
+                    single : F32
+                    single = anyFrac
+
+                    decimal : Dec
+                    decimal = anyFrac
+
+                    { anyFrac, single, decimal }
+                "#
+            ),
+            "{ anyFrac : Float *, decimal : Dec, single : F32 }",
+        );
+    }
+
     #[test]
     fn optional_field_unifies_with_present() {
         infer_eq_without_problem(
@@ -5957,6 +6044,23 @@ mod solve_expr {
         )
     }
 
+    #[test]
+    fn has_clause_references_ability_defined_later() {
+        infer_eq_without_problem(
+            indoc!(
+                r#"
+                app "test" provides [hashTwice] to "./platform"
+
+                hashTwice : a -> U64 | a has Hash
+                hashTwice = \x -> hash x + hash x
+
+                Hash has hash : a -> U64 | a has Hash
+                "#
+            ),
+            "a -> U64 | a has Hash",
+        )
+    }
+
     #[test]
     fn single_ability_single_member_specializations() {
         check_inferred_abilities(
@@ -6525,6 +6629,49 @@ mod solve_expr {
         )
     }
 
+    #[test]
+    fn encode_empty_record() {
+        infer_queries!(
+            indoc!(
+                r#"
+                app "test"
+                    imports [Encode.{ toEncoder }]
+                    provides [main] to "./platform"
+
+                main = toEncoder {}
+                     # ^^^^^^^^^
+                "#
+            ),
+            @"Encoding#toEncoder(2) : {} -[[] + fmt:Encode.emptyRecord(26):1]-> Encoder fmt | fmt has EncoderFormatting"
+        )
+    }
+
+    #[test]
+    fn decode_alias_of_opaque_with_opaque_impl() {
+        infer_queries!(
+            indoc!(
+                r#"
+                app "test"
+                    imports [Decode.{ Decoder, Decoding, DecoderFormatting, decoder, custom, decodeWith }]
+                    provides [main] to "./platform"
+
+                Email := Str has [Decoding {decoder: decodeEmail}]
+
+                decodeEmail = custom \bytes, fmt ->
+                    when decodeWith bytes Decode.string fmt is
+                        { result, rest } -> { result: Result.map result (\s -> @Email s), rest }
+
+                Contact : Email
+
+                main : Decoder Contact fmt | fmt has DecoderFormatting
+                main = decoder
+                     # ^^^^^^^
+                "#
+            ),
+            @"Email#decoder(3) : Decoder Contact fmt | fmt has DecoderFormatting"
+        )
+    }
+
     #[test]
     fn encode_record_with_nested_custom_impl() {
         infer_queries!(
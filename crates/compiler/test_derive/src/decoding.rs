@@ -5,14 +5,17 @@
 #![allow(non_snake_case)]
 
 use crate::{
-    util::{check_immediate, derive_test},
+    util::{check_immediate, derive_test, exposed_implementations, user_alias},
     v,
 };
 use insta::assert_snapshot;
 use roc_module::symbol::Symbol;
-use roc_types::subs::Variable;
+use roc_types::{
+    subs::{Subs, Variable},
+    types::AliasKind,
+};
 
-use roc_derive_key::DeriveBuiltin::Decoder;
+use roc_derive_key::{ClassificationCache, DeriveBuiltin::Decoder, DeriveError, Derived};
 
 #[test]
 fn immediates() {
@@ -21,6 +24,7 @@ fn immediates() {
     check_immediate(Decoder, v!(U32), Symbol::DECODE_U32);
     check_immediate(Decoder, v!(U64), Symbol::DECODE_U64);
     check_immediate(Decoder, v!(U128), Symbol::DECODE_U128);
+    check_immediate(Decoder, v!(NAT), Symbol::DECODE_U64);
     check_immediate(Decoder, v!(I8), Symbol::DECODE_I8);
     check_immediate(Decoder, v!(I16), Symbol::DECODE_I16);
     check_immediate(Decoder, v!(I32), Symbol::DECODE_I32);
@@ -30,6 +34,87 @@ fn immediates() {
     check_immediate(Decoder, v!(F32), Symbol::DECODE_F32);
     check_immediate(Decoder, v!(F64), Symbol::DECODE_F64);
     check_immediate(Decoder, v!(STR), Symbol::DECODE_STRING);
+    check_immediate(Decoder, v!(BOOL), Symbol::DECODE_BOOL);
+}
+
+#[test]
+fn empty_tag_union() {
+    let mut subs = Subs::new();
+    let var = v!(EMPTY_TAG_UNION)(&mut subs);
+
+    assert_eq!(
+        Derived::builtin(Decoder, &subs, var),
+        Err(DeriveError::UninhabitedType)
+    );
+}
+
+#[test]
+fn flex_and_rigid_variables() {
+    let mut subs = Subs::new();
+    let flex = v!(*)(&mut subs);
+    let rigid = v!(*)(&mut subs);
+    subs.rigid_var(rigid, "a".into());
+    let able_rigid = v!(*)(&mut subs);
+    subs.rigid_able_var(able_rigid, "b".into(), Symbol::DECODE_DECODING);
+
+    // A flex variable may still be resolved to something derivable...
+    assert_eq!(
+        Derived::builtin(Decoder, &subs, flex),
+        Err(DeriveError::UnboundVar)
+    );
+    // ...but a rigid one, bound to an ability or not, never will be.
+    for var in [rigid, able_rigid] {
+        assert_eq!(
+            Derived::builtin(Decoder, &subs, var),
+            Err(DeriveError::CannotDeriveForPolymorphic)
+        );
+    }
+}
+
+#[test]
+fn debug_keys() {
+    use roc_derive_key::decoding::debug_key_for;
+
+    let mut subs = Subs::new();
+    let list = v!(Symbol::LIST_LIST v!(U8))(&mut subs);
+    let record = v!({ a: v!(U8), })(&mut subs);
+
+    assert_eq!(debug_key_for(&subs, list), Ok("list".to_string()));
+    assert_eq!(debug_key_for(&subs, record), Err(DeriveError::Underivable));
+}
+
+#[test]
+fn opaques_decode_with_their_own_implementation() {
+    let mut subs = Subs::new();
+    // Email := Str has [Decoding {decoder: decodeEmail}]
+    let (email_symbol, email) = user_alias(&mut subs, "Email", Variable::STR, AliasKind::Opaque);
+    // Contact : Email
+    let (_, contact) = user_alias(&mut subs, "Contact", email, AliasKind::Structural);
+    // Name := Str has [Decoding]
+    let (name_symbol, name) = user_alias(&mut subs, "Name", Variable::STR, AliasKind::Opaque);
+    // Nickname : Name
+    let (_, nickname) = user_alias(&mut subs, "Nickname", name, AliasKind::Structural);
+
+    let exposed_types = exposed_implementations(
+        email_symbol.module_id(),
+        &[
+            (email_symbol, Symbol::DECODE_DECODER, true),
+            (name_symbol, Symbol::DECODE_DECODER, false),
+        ],
+    );
+    let derive = |var| {
+        let mut cache = ClassificationCache::with_custom_implementations(&exposed_types);
+        Derived::builtin_with_cache(Decoder, &subs, var, Default::default(), &mut cache)
+    };
+
+    // Decoding an `Email` as a `Str` would produce a bare `Str`, never wrapped in `Email`.
+    assert_eq!(derive(email), Ok(Derived::Opaque(email_symbol)));
+    assert_eq!(derive(contact), Ok(Derived::Opaque(email_symbol)));
+    // A derived implementation decodes the wrapped type itself, so it is decoded the same way.
+    assert_eq!(
+        derive(nickname),
+        Ok(Derived::Immediate(Symbol::DECODE_STRING))
+    );
 }
 
 #[test]
@@ -49,3 +134,21 @@ fn list() {
         )
     })
 }
+
+#[test]
+fn list_of_bool() {
+    derive_test(Decoder, v!(Symbol::LIST_LIST v!(BOOL)), |golden| {
+        assert_snapshot!(golden, @r###"
+        # derived for List Bool
+        # Decoder (List val) fmt | fmt has DecoderFormatting, val has Decoding
+        # List U8, fmt -[[custom(3)]]-> { rest : List U8, result : [Err [TooShort], Ok (List val)] } | fmt has DecoderFormatting, val has Decoding
+        # Specialization lambda sets:
+        #   @<1>: [[custom(3)]]
+        #Derived.decoder_list =
+          Decode.custom
+            \#Derived.bytes, #Derived.fmt ->
+              Decode.decodeWith #Derived.bytes (Decode.list Decode.decoder) #Derived.fmt
+        "###
+        )
+    })
+}
@@ -8,12 +8,21 @@ use insta::assert_snapshot;
 
 use crate::{
     test_hash_eq, test_hash_neq,
-    util::{check_immediate, derive_test},
+    util::{check_immediate, derive_test, exposed_implementations, user_alias},
     v,
 };
-use roc_derive_key::DeriveBuiltin::ToEncoder;
-use roc_module::symbol::Symbol;
-use roc_types::subs::Variable;
+use roc_derive::synth_var;
+use roc_derive_key::{
+    encoding::{FlatEncodableKey, OpaqueEncoders, OpenRecordMode},
+    is_recursive, ClassificationCache,
+    DeriveBuiltin::ToEncoder,
+    DeriveError, DeriveKey, DeriveOptions, Derived,
+};
+use roc_module::{ident::TagName, symbol::Symbol};
+use roc_types::{
+    subs::{Subs, Variable},
+    types::AliasKind,
+};
 
 // {{{ hash tests
 
@@ -27,6 +36,8 @@ test_hash_eq! {
     same_record_fields_any_order:
         v!({ a: v!(U8), b: v!(U8), c: v!(U8), }),
         v!({ c: v!(U8), a: v!(U8), b: v!(U8), })
+    same_tuple_diff_types:
+        v!((v!(U8), v!(U8),)), v!((v!(STR), v!(U16),))
     explicit_empty_record_and_implicit_empty_record:
         v!(EMPTY_RECORD), v!({})
     same_record_fields_required_vs_optional:
@@ -46,6 +57,8 @@ test_hash_eq! {
         v!([ Nil, Cons v!(^lst)] as lst), v!([ Nil, Cons v!(^lst)] as lst)
     same_tag_union_and_recursive_tag_union_fields:
         v!([ Nil, Cons v!(STR)]), v!([ Nil, Cons v!(^lst)] as lst)
+    recursive_linked_list_and_its_surface:
+        v!([ Nil, Cons v!(U8) v!(^lst)] as lst), v!([ Nil, Cons v!(STR) v!(STR)])
 
     list_list_diff_types:
         v!(Symbol::LIST_LIST v!(STR)), v!(Symbol::LIST_LIST v!(U8))
@@ -53,6 +66,8 @@ test_hash_eq! {
         v!(Symbol::SET_SET v!(STR)), v!(Symbol::SET_SET v!(U8))
     dict_dict_diff_types:
         v!(Symbol::DICT_DICT v!(STR) v!(STR)), v!(Symbol::DICT_DICT v!(U8) v!(U8))
+    list_of_record_diff_types:
+        v!(Symbol::LIST_LIST v!({ a: v!(U8), })), v!(Symbol::LIST_LIST v!({ a: v!(STR), }))
     str_str:
         v!(Symbol::STR_STR), v!(Symbol::STR_STR)
 
@@ -73,6 +88,14 @@ test_hash_eq! {
 test_hash_neq! {
     ToEncoder,
 
+    tuple_vs_record:
+        v!((v!(U8), v!(U8),)), v!({ a: v!(U8), b: v!(U8), })
+    tuple_diff_arity:
+        v!((v!(U8),)), v!((v!(U8), v!(U8),))
+
+    tag_without_payload_vs_empty_record_payload:
+        v!([ A ]), v!([ A v!(EMPTY_RECORD) ])
+
     different_record_fields:
         v!({ a: v!(U8), }), v!({ b: v!(U8), })
     record_empty_vs_nonempty:
@@ -85,6 +108,11 @@ test_hash_neq! {
     different_recursive_tag_union_tags:
         v!([ Nil, Cons v!(^lst) ] as lst), v!([ Nil, Next v!(^lst) ] as lst)
 
+    list_of_record_vs_list:
+        v!(Symbol::LIST_LIST v!({ a: v!(U8), })), v!(Symbol::LIST_LIST v!(U8))
+    list_of_record_diff_fields:
+        v!(Symbol::LIST_LIST v!({ a: v!(U8), })), v!(Symbol::LIST_LIST v!({ b: v!(U8), }))
+
     same_alias_diff_real_type:
         v!(Symbol::BOOL_BOOL => v!([ True, False ])), v!(Symbol::BOOL_BOOL => v!([ False, True, Maybe ]))
     diff_alias_diff_real_type:
@@ -107,6 +135,7 @@ fn immediates() {
     check_immediate(ToEncoder, v!(U32), Symbol::ENCODE_U32);
     check_immediate(ToEncoder, v!(U64), Symbol::ENCODE_U64);
     check_immediate(ToEncoder, v!(U128), Symbol::ENCODE_U128);
+    check_immediate(ToEncoder, v!(NAT), Symbol::ENCODE_U64);
     check_immediate(ToEncoder, v!(I8), Symbol::ENCODE_I8);
     check_immediate(ToEncoder, v!(I16), Symbol::ENCODE_I16);
     check_immediate(ToEncoder, v!(I32), Symbol::ENCODE_I32);
@@ -116,46 +145,261 @@ fn immediates() {
     check_immediate(ToEncoder, v!(F32), Symbol::ENCODE_F32);
     check_immediate(ToEncoder, v!(F64), Symbol::ENCODE_F64);
     check_immediate(ToEncoder, v!(STR), Symbol::ENCODE_STRING);
+    check_immediate(ToEncoder, v!(EMPTY_RECORD), Symbol::ENCODE_EMPTY_RECORD);
+    check_immediate(ToEncoder, v!({}), Symbol::ENCODE_EMPTY_RECORD);
+}
+
+fn open_record_and_closed_record(subs: &mut Subs) -> (Variable, Variable) {
+    use roc_types::subs::{Content, FlatType, RecordFields};
+    use roc_types::types::RecordField;
+
+    // { a : U8 }*
+    let fields =
+        RecordFields::insert_into_subs(subs, [("a".into(), RecordField::Required(Variable::U8))]);
+    let ext = synth_var(subs, Content::FlexVar(None));
+    let open = synth_var(subs, Content::Structure(FlatType::Record(fields, ext)));
+
+    // { a : U8 }
+    let closed = v!({ a: v!(U8), })(subs);
+
+    (open, closed)
 }
 
 #[test]
-fn empty_record() {
-    derive_test(ToEncoder, v!(EMPTY_RECORD), |golden| {
-        assert_snapshot!(golden, @r###"
-        # derived for {}
-        # {} -[[toEncoder_{}(0)]]-> Encoder fmt | fmt has EncoderFormatting
-        # {} -[[toEncoder_{}(0)]]-> (List U8, fmt -[[custom(2) {}]]-> List U8) | fmt has EncoderFormatting
-        # Specialization lambda sets:
-        #   @<1>: [[toEncoder_{}(0)]]
-        #   @<2>: [[custom(2) {}]]
-        #Derived.toEncoder_{} =
-          \#Derived.rcd ->
-            Encode.custom
-              \#Derived.bytes, #Derived.fmt ->
-                Encode.appendWith #Derived.bytes (Encode.record []) #Derived.fmt
-        "###
-        )
-    })
+fn open_record_rejected_by_default() {
+    let mut subs = Subs::new();
+    let (open, _) = open_record_and_closed_record(&mut subs);
+
+    assert_eq!(
+        Derived::builtin(ToEncoder, &subs, open),
+        Err(DeriveError::UnboundVar)
+    );
+    assert_eq!(
+        Derived::builtin_with_open_records(ToEncoder, &subs, open, OpenRecordMode::Reject),
+        Err(DeriveError::UnboundVar)
+    );
 }
 
 #[test]
-fn zero_field_record() {
-    derive_test(ToEncoder, v!({}), |golden| {
-        assert_snapshot!(golden, @r###"
-        # derived for {}
-        # {} -[[toEncoder_{}(0)]]-> Encoder fmt | fmt has EncoderFormatting
-        # {} -[[toEncoder_{}(0)]]-> (List U8, fmt -[[custom(2) {}]]-> List U8) | fmt has EncoderFormatting
-        # Specialization lambda sets:
-        #   @<1>: [[toEncoder_{}(0)]]
-        #   @<2>: [[custom(2) {}]]
-        #Derived.toEncoder_{} =
-          \#Derived.rcd ->
-            Encode.custom
-              \#Derived.bytes, #Derived.fmt ->
-                Encode.appendWith #Derived.bytes (Encode.record []) #Derived.fmt
-        "###
-        )
-    })
+fn open_record_encodes_known_fields_only() {
+    let mut subs = Subs::new();
+    let (open, closed) = open_record_and_closed_record(&mut subs);
+
+    let key =
+        Derived::builtin_with_open_records(ToEncoder, &subs, open, OpenRecordMode::KnownFieldsOnly);
+
+    assert!(matches!(key, Ok(Derived::Key(_))));
+    assert_eq!(key, Derived::builtin(ToEncoder, &subs, closed));
+}
+
+/// `Emails : List Str`, defined in a user module.
+fn emails_alias(subs: &mut Subs) -> (Symbol, Variable) {
+    let real_var = v!(Symbol::LIST_LIST v!(STR))(subs);
+
+    user_alias(subs, "Emails", real_var, AliasKind::Structural)
+}
+
+#[test]
+fn aliases_are_transparent_by_default() {
+    let mut subs = Subs::new();
+    let (_, emails) = emails_alias(&mut subs);
+
+    assert_eq!(
+        Derived::builtin(ToEncoder, &subs, emails),
+        Ok(Derived::Key(DeriveKey::ToEncoder(FlatEncodableKey::List())))
+    );
+}
+
+#[test]
+fn opaque_wrapping_str_encodes_as_str() {
+    let mut subs = Subs::new();
+    // PhoneNumber := Str
+    let (_, phone_number) = user_alias(&mut subs, "PhoneNumber", Variable::STR, AliasKind::Opaque);
+
+    assert_eq!(
+        Derived::builtin(ToEncoder, &subs, phone_number),
+        Ok(Derived::Immediate(Symbol::ENCODE_STRING))
+    );
+}
+
+#[test]
+fn opaque_wrapping_bytes_encodes_as_list() {
+    let mut subs = Subs::new();
+    // Utf8 := List U8
+    let bytes = v!(Symbol::LIST_LIST v!(U8))(&mut subs);
+    let (_, utf8) = user_alias(&mut subs, "Utf8", bytes, AliasKind::Opaque);
+
+    // There is no dedicated encoding for bytes, so they are encoded like any other list.
+    assert_eq!(
+        Derived::builtin(ToEncoder, &subs, utf8),
+        Derived::builtin(ToEncoder, &subs, bytes),
+    );
+    assert_eq!(
+        Derived::builtin(ToEncoder, &subs, utf8),
+        Ok(Derived::Key(DeriveKey::ToEncoder(FlatEncodableKey::List())))
+    );
+}
+
+#[test]
+fn opaque_encoders_can_use_the_opaques_own_implementation() {
+    let mut subs = Subs::new();
+    // Email := Str
+    let (email_symbol, email) = user_alias(&mut subs, "Email", Variable::STR, AliasKind::Opaque);
+    // Contact : Email
+    let (_, contact) = user_alias(&mut subs, "Contact", email, AliasKind::Structural);
+
+    let own_implementation = DeriveOptions {
+        opaque_encoders: OpaqueEncoders::OwnImplementation,
+        ..Default::default()
+    };
+
+    for var in [email, contact] {
+        // By default, the string inside is written as it is...
+        assert_eq!(
+            Derived::builtin(ToEncoder, &subs, var),
+            Ok(Derived::Immediate(Symbol::ENCODE_STRING))
+        );
+        // ...but an opaque can also be written by its own, possibly custom, encoder.
+        assert_eq!(
+            Derived::builtin_with_options(ToEncoder, &subs, var, own_implementation),
+            Ok(Derived::Opaque(email_symbol))
+        );
+    }
+
+    // Builtin opaques have no implementation of their own to defer to.
+    let u8 = v!(U8)(&mut subs);
+    assert_eq!(
+        Derived::builtin_with_options(ToEncoder, &subs, u8, own_implementation),
+        Ok(Derived::Immediate(Symbol::ENCODE_U8))
+    );
+}
+
+/// The opaques of a JSON platform, as `(symbol, variable)`: `Value := [Null, String Str]`, which
+/// implements `toEncoder` by hand, and `Email := Str`, which derives it. Also returns the exposed
+/// types that record those implementations.
+fn json_platform_opaques(
+    subs: &mut Subs,
+) -> (
+    (Symbol, Variable),
+    (Symbol, Variable),
+    roc_can::module::ExposedByModule,
+) {
+    use roc_module::symbol::{IdentIds, ModuleIds};
+    use roc_types::subs::{AliasVariables, Content};
+
+    // `Json` names a builtin module, so the platform's module goes by another name.
+    let json_module = ModuleIds::default().get_or_insert(&"JsonPlatform".into());
+    let mut ident_ids = IdentIds::default();
+    let mut opaque = |subs: &mut Subs, name, real_var| {
+        let symbol = Symbol::new(json_module, ident_ids.add_str(name));
+        let no_args = AliasVariables::insert_into_subs::<Vec<_>, Vec<_>>(subs, vec![], vec![]);
+        let var = synth_var(
+            subs,
+            Content::Alias(symbol, no_args, real_var, AliasKind::Opaque),
+        );
+        (symbol, var)
+    };
+    let json = v!([ Null, String v!(STR) ])(subs);
+    let (value_symbol, value) = opaque(subs, "Value", json);
+    let (email_symbol, email) = opaque(subs, "Email", Variable::STR);
+
+    let exposed_types = exposed_implementations(
+        json_module,
+        &[
+            (value_symbol, Symbol::ENCODE_TO_ENCODER, true),
+            (email_symbol, Symbol::ENCODE_TO_ENCODER, false),
+        ],
+    );
+
+    ((value_symbol, value), (email_symbol, email), exposed_types)
+}
+
+#[test]
+fn platform_opaques_with_custom_encoders_use_them() {
+    let mut subs = Subs::new();
+    let ((value_symbol, value), (_, email), exposed_types) = json_platform_opaques(&mut subs);
+    // The type `Value` wraps.
+    let json = v!([ Null, String v!(STR) ])(&mut subs);
+
+    let derive = |var| {
+        let mut cache = ClassificationCache::with_custom_implementations(&exposed_types);
+        Derived::builtin_with_cache(ToEncoder, &subs, var, Default::default(), &mut cache)
+    };
+
+    assert_eq!(derive(value), Ok(Derived::Opaque(value_symbol)));
+    assert_eq!(derive(email), Ok(Derived::Immediate(Symbol::ENCODE_STRING)));
+    // Without the platform's implementations, `Value` is unwrapped like any other opaque.
+    assert_eq!(
+        Derived::builtin(ToEncoder, &subs, value),
+        Derived::builtin(ToEncoder, &subs, json)
+    );
+}
+
+#[test]
+fn record_fields_with_custom_encoders_are_keyed() {
+    let mut subs = Subs::new();
+    let ((value_symbol, value), (_, email), exposed_types) = json_platform_opaques(&mut subs);
+
+    // { name : Str, payload : Value }
+    let payload = v!({ name: v!(STR), payload: |_: &mut Subs| value, })(&mut subs);
+    // { name : Str, contact : Email }
+    let contact = v!({ name: v!(STR), contact: |_: &mut Subs| email, })(&mut subs);
+
+    let derive = |var| {
+        let mut cache = ClassificationCache::with_custom_implementations(&exposed_types);
+        Derived::builtin_with_cache(ToEncoder, &subs, var, Default::default(), &mut cache)
+    };
+
+    assert_eq!(
+        derive(payload),
+        Ok(Derived::Key(DeriveKey::ToEncoder(
+            FlatEncodableKey::RecordWithCustomEncoders(vec![
+                ("name".into(), None),
+                ("payload".into(), Some(value_symbol)),
+            ])
+        )))
+    );
+    // Opaques that derive `toEncoder` don't change the key.
+    assert_eq!(
+        derive(contact),
+        Ok(Derived::Key(DeriveKey::ToEncoder(
+            FlatEncodableKey::Record(vec!["contact".into(), "name".into()])
+        )))
+    );
+    // Nor does any opaque, without the platform's implementations.
+    assert_eq!(
+        Derived::builtin(ToEncoder, &subs, payload),
+        Ok(Derived::Key(DeriveKey::ToEncoder(
+            FlatEncodableKey::Record(vec!["name".into(), "payload".into()])
+        )))
+    );
+}
+
+#[test]
+fn record_with_a_numeric_field_is_not_a_tuple() {
+    use roc_types::subs::{Content, FlatType, RecordFields};
+    use roc_types::types::RecordField;
+
+    let mut subs = Subs::new();
+    // { 0 : U8, name : Str }
+    let fields = RecordFields::insert_into_subs(
+        &mut subs,
+        [
+            ("0".into(), RecordField::Required(Variable::U8)),
+            ("name".into(), RecordField::Required(Variable::STR)),
+        ],
+    );
+    let var = synth_var(
+        &mut subs,
+        Content::Structure(FlatType::Record(fields, Variable::EMPTY_RECORD)),
+    );
+
+    assert_eq!(
+        Derived::builtin(ToEncoder, &subs, var),
+        Ok(Derived::Key(DeriveKey::ToEncoder(
+            FlatEncodableKey::Record(vec!["0".into(), "name".into()])
+        )))
+    );
 }
 
 #[test]
@@ -212,16 +456,294 @@ fn two_field_record() {
 }
 
 #[test]
-#[ignore = "NOTE: this would never actually happen, because [] is uninhabited, and hence toEncoder can never be called with a value of []!
-Rightfully it induces broken assertions in other parts of the compiler, so we ignore it."]
 fn empty_tag_union() {
-    derive_test(ToEncoder, v!(EMPTY_TAG_UNION), |golden| {
-        assert_snapshot!(
-            golden,
-            @r#"
-            "#
-        )
-    })
+    let mut subs = Subs::new();
+    let explicit = v!(EMPTY_TAG_UNION)(&mut subs);
+    let implicit = v!([])(&mut subs);
+
+    // [] is uninhabited, so toEncoder can never be called with a value of [].
+    assert_eq!(
+        Derived::builtin(ToEncoder, &subs, explicit),
+        Err(DeriveError::UninhabitedType)
+    );
+    assert_eq!(
+        Derived::builtin(ToEncoder, &subs, implicit),
+        Err(DeriveError::UninhabitedType)
+    );
+}
+
+#[test]
+fn flex_and_rigid_variables() {
+    let mut subs = Subs::new();
+    let flex = v!(*)(&mut subs);
+    let rigid = v!(*)(&mut subs);
+    subs.rigid_var(rigid, "a".into());
+    let able_rigid = v!(*)(&mut subs);
+    subs.rigid_able_var(able_rigid, "b".into(), Symbol::ENCODE_ENCODING);
+
+    // A flex variable may still be resolved to something derivable...
+    assert_eq!(
+        Derived::builtin(ToEncoder, &subs, flex),
+        Err(DeriveError::UnboundVar)
+    );
+    // ...but a rigid one, bound to an ability or not, never will be.
+    for var in [rigid, able_rigid] {
+        assert_eq!(
+            Derived::builtin(ToEncoder, &subs, var),
+            Err(DeriveError::CannotDeriveForPolymorphic)
+        );
+    }
+}
+
+#[test]
+fn rigids_bound_to_encoding_defer_to_their_instantiation() {
+    let mut subs = Subs::new();
+    let a = v!(*)(&mut subs);
+    subs.rigid_var(a, "a".into());
+    // Wrapper a := a
+    let (_, wrapper) = user_alias(&mut subs, "Wrapper", a, AliasKind::Opaque);
+
+    let derive = |cache: &mut ClassificationCache| {
+        Derived::builtin_with_cache(ToEncoder, &subs, wrapper, Default::default(), cache)
+    };
+
+    assert_eq!(
+        derive(&mut ClassificationCache::default()),
+        Err(DeriveError::CannotDeriveForPolymorphic)
+    );
+
+    // Under `a has Encoding`, `a` is encoded with whatever it turns out to be.
+    let mut cache = ClassificationCache::default();
+    cache.assume_encoding(a);
+    assert_eq!(
+        derive(&mut cache),
+        Ok(Derived::Immediate(Symbol::ENCODE_TO_ENCODER))
+    );
+}
+
+#[test]
+fn encoding_bounds_are_found_in_the_type() {
+    let mut subs = Subs::new();
+    let a = v!(*)(&mut subs);
+    subs.rigid_able_var(a, "a".into(), Symbol::ENCODE_ENCODING);
+    let b = v!(*)(&mut subs);
+    subs.rigid_able_var(b, "b".into(), Symbol::DECODE_DECODING);
+    // Wrapper a := a
+    let (_, wrapper_a) = user_alias(&mut subs, "Wrapper", a, AliasKind::Opaque);
+    // Wrapper b := b
+    let (_, wrapper_b) = user_alias(&mut subs, "Wrapper", b, AliasKind::Opaque);
+
+    let derive = |var| {
+        let mut cache = ClassificationCache::default();
+        cache.assume_encoding_bounds(&subs, var);
+        Derived::builtin_with_cache(ToEncoder, &subs, var, Default::default(), &mut cache)
+    };
+
+    assert_eq!(
+        derive(wrapper_a),
+        Ok(Derived::Immediate(Symbol::ENCODE_TO_ENCODER))
+    );
+    // Bound to another ability, `b` is as polymorphic as ever.
+    assert_eq!(
+        derive(wrapper_b),
+        Err(DeriveError::CannotDeriveForPolymorphic)
+    );
+}
+
+#[test]
+fn batch_shares_keys_of_identical_shapes() {
+    let mut subs = Subs::new();
+    let point = v!({ x: v!(U8), y: v!(U8), })(&mut subs);
+    let other_point = v!({ y: v!(STR), x: v!(STR), })(&mut subs);
+    let size = v!({ w: v!(U8), h: v!(U8), })(&mut subs);
+    let string = v!(STR)(&mut subs);
+    let never = v!([])(&mut subs);
+
+    let vars = [point, other_point, size, string, point, never];
+    let batch = Derived::builtin_for_all(ToEncoder, &subs, &vars, DeriveOptions::default());
+
+    assert_eq!(
+        batch.derived,
+        vec![
+            Derived::Key(DeriveKey::ToEncoder(FlatEncodableKey::Record(vec![
+                "x".into(),
+                "y".into()
+            ]))),
+            Derived::Key(DeriveKey::ToEncoder(FlatEncodableKey::Record(vec![
+                "h".into(),
+                "w".into()
+            ]))),
+            Derived::Immediate(Symbol::ENCODE_STRING),
+        ]
+    );
+
+    let index_of = |var| *batch.by_var.get(&var).unwrap();
+    assert_eq!(index_of(point), Ok(0));
+    assert_eq!(index_of(other_point), Ok(0));
+    assert_eq!(index_of(size), Ok(1));
+    assert_eq!(index_of(string), Ok(2));
+    assert_eq!(index_of(never), Err(DeriveError::UninhabitedType));
+    assert_eq!(batch.by_var.len(), 5);
+
+    assert_eq!(batch.get(size), Some(Ok(&batch.derived[1])));
+}
+
+#[test]
+fn dict_keys_must_be_encodable() {
+    use roc_types::subs::{Content, FlatType, VariableSubsSlice};
+
+    let mut subs = Subs::new();
+    let str_keys = v!(Symbol::DICT_DICT v!(STR) v!(U8))(&mut subs);
+    let tag_keys = v!(Symbol::DICT_DICT v!([A, B]) v!(U8))(&mut subs);
+
+    let dict_key = Ok(Derived::Key(DeriveKey::ToEncoder(FlatEncodableKey::Dict())));
+    assert_eq!(Derived::builtin(ToEncoder, &subs, str_keys), dict_key);
+    assert_eq!(Derived::builtin(ToEncoder, &subs, tag_keys), dict_key);
+
+    let args = VariableSubsSlice::insert_into_subs(&mut subs, [Variable::STR]);
+    let closure = synth_var(&mut subs, Content::FlexVar(None));
+    let function = synth_var(
+        &mut subs,
+        Content::Structure(FlatType::Func(args, closure, Variable::STR)),
+    );
+    let dict = v!(Symbol::DICT_DICT v!(^function) v!(U8))(&mut subs);
+
+    assert_eq!(
+        Derived::builtin(ToEncoder, &subs, dict),
+        Err(DeriveError::Underivable)
+    );
+}
+
+#[test]
+fn explain_every_underivable_part() {
+    use roc_derive_key::encoding::{explain_underivable, TypePosition, UnderivableReason};
+    use roc_types::subs::{Content, FlatType, VariableSubsSlice};
+
+    let mut subs = Subs::new();
+    let args = VariableSubsSlice::insert_into_subs(&mut subs, [Variable::STR]);
+    let closure = synth_var(&mut subs, Content::FlexVar(None));
+    let function = synth_var(
+        &mut subs,
+        Content::Structure(FlatType::Func(args, closure, Variable::STR)),
+    );
+    // { name : Str, cb : Str -> Str, xs : List (Str -> Str), tags : [A (Str -> Str), B Str] }
+    let record = v!({
+        name: v!(STR),
+        cb: v!(^function),
+        xs: v!(Symbol::LIST_LIST v!(^function)),
+        tags: v!([ A v!(^function), B v!(STR) ]),
+    })(&mut subs);
+    let function_reason = UnderivableReason::Type(DeriveError::Underivable);
+
+    assert_eq!(
+        explain_underivable(&subs, record),
+        Some(UnderivableReason::Parts(vec![
+            (TypePosition::Field("cb".into()), function_reason.clone()),
+            (
+                TypePosition::Field("tags".into()),
+                UnderivableReason::Parts(vec![(
+                    TypePosition::TagPayload(TagName("A".into()), 0),
+                    function_reason.clone()
+                )])
+            ),
+            (
+                TypePosition::Field("xs".into()),
+                UnderivableReason::Parts(vec![(TypePosition::ListElement, function_reason)])
+            ),
+        ]))
+    );
+    // Deriving itself only looks at the surface of the record.
+    assert!(Derived::builtin(ToEncoder, &subs, record).is_ok());
+
+    // Recursive types are looked at once.
+    let linked_list = v!([ Nil, Cons v!(STR) v!(^lst) ] as lst)(&mut subs);
+    assert_eq!(explain_underivable(&subs, linked_list), None);
+}
+
+#[test]
+fn debug_keys() {
+    use roc_derive_key::encoding::debug_key_for;
+
+    let mut subs = Subs::new();
+    let record = v!({ b: v!(STR), a: v!(U8), })(&mut subs);
+    let list = v!(Symbol::LIST_LIST v!(STR))(&mut subs);
+    let list_of_record = v!(Symbol::LIST_LIST v!({ a: v!(U8), }))(&mut subs);
+
+    assert_eq!(debug_key_for(&subs, record), Ok("{a,b}".to_string()));
+    assert_eq!(debug_key_for(&subs, list), Ok("list".to_string()));
+    assert_eq!(
+        debug_key_for(&subs, list_of_record),
+        Ok("list_of_{a}".to_string())
+    );
+}
+
+#[test]
+fn classifications_are_cached_by_root() {
+    let mut subs = Subs::new();
+    let point = v!({ x: v!(U8), y: v!(U8), })(&mut subs);
+    // A separate variable for the same type, as left behind by unification.
+    let same_point = v!({ x: v!(U8), y: v!(U8), })(&mut subs);
+    let point_desc = subs.get_without_compacting(point);
+    subs.union(same_point, point, point_desc);
+    // { from : Point, to : Point }
+    let line = v!({ from: v!(^point), to: v!(^same_point), })(&mut subs);
+
+    let mut cache = ClassificationCache::default();
+    let mut derive =
+        |var| Derived::builtin_with_cache(ToEncoder, &subs, var, Default::default(), &mut cache);
+
+    assert_eq!(
+        derive(line),
+        Ok(Derived::Key(DeriveKey::ToEncoder(
+            FlatEncodableKey::Record(vec!["from".into(), "to".into()])
+        )))
+    );
+    assert_eq!(derive(point), derive(same_point));
+    // `Point` was classified once for both fields, as was the `U8` of both of its own fields.
+    assert_eq!(cache.len(), 3);
+}
+
+#[test]
+fn opaque_chains_are_classified_once() {
+    let mut subs = Subs::new();
+    // A := B, B := C, C := U8
+    let (_, c) = user_alias(&mut subs, "C", Variable::U8, AliasKind::Opaque);
+    let (_, b) = user_alias(&mut subs, "B", c, AliasKind::Opaque);
+    let (_, a) = user_alias(&mut subs, "A", b, AliasKind::Opaque);
+
+    let mut cache = ClassificationCache::default();
+    assert_eq!(
+        Derived::builtin_with_cache(ToEncoder, &subs, a, Default::default(), &mut cache),
+        Ok(Derived::Immediate(Symbol::ENCODE_U8))
+    );
+    // Only `A` and `U8` were classified; `B` and `C` were skipped over.
+    assert_eq!(cache.len(), 2);
+}
+
+#[test]
+fn keys_are_stable_across_subs() {
+    fn check<S: Fn(&mut Subs) -> Variable>(synth: S) {
+        let mut subs1 = Subs::new();
+        let mut subs2 = Subs::new();
+        // Shift the second `Subs` so that the same shape lands on different variables.
+        for _ in 0..10 {
+            v!({ unrelated: v!(I64), })(&mut subs2);
+        }
+
+        let var1 = synth(&mut subs1);
+        let var2 = synth(&mut subs2);
+
+        assert_eq!(
+            Derived::builtin(ToEncoder, &subs1, var1),
+            Derived::builtin(ToEncoder, &subs2, var2)
+        );
+    }
+
+    check(v!({ a: v!(U8), b: v!(STR), }));
+    check(v!([ Nil, Cons v!(U8) ]));
+    check(v!(Symbol::LIST_LIST v!(STR)));
+    check(v!(Symbol::STR_STR));
+    check(v!(U8));
 }
 
 #[test]
@@ -247,6 +769,30 @@ fn tag_one_label_zero_args() {
     })
 }
 
+#[test]
+fn tag_one_label_empty_record_arg() {
+    derive_test(ToEncoder, v!([A v!(EMPTY_RECORD)]), |golden| {
+        assert_snapshot!(golden, @r###"
+        # derived for [A {}]
+        # [A val] -[[toEncoder_[A 1](0)]]-> Encoder fmt | fmt has EncoderFormatting, val has Encoding
+        # [A val] -[[toEncoder_[A 1](0)]]-> (List U8, fmt -[[custom(3) [A val]]]-> List U8) | fmt has EncoderFormatting, val has Encoding
+        # Specialization lambda sets:
+        #   @<1>: [[toEncoder_[A 1](0)]]
+        #   @<2>: [[custom(3) [A val]]] | val has Encoding
+        #Derived.toEncoder_[A 1] =
+          \#Derived.tag ->
+            Encode.custom
+              \#Derived.bytes, #Derived.fmt ->
+                Encode.appendWith
+                  #Derived.bytes
+                  (when #Derived.tag is
+                    A #Derived.2 -> Encode.tag "A" [Encode.toEncoder #Derived.2])
+                  #Derived.fmt
+        "###
+        )
+    })
+}
+
 #[test]
 fn tag_one_label_two_args() {
     derive_test(ToEncoder, v!([A v!(U8) v!(STR)]), |golden| {
@@ -348,6 +894,89 @@ fn recursive_tag_union() {
     )
 }
 
+#[test]
+fn recursive_linked_list_key_is_finite() {
+    let mut subs = Subs::new();
+    let linked_list = v!([ Nil, Cons v!(U8) v!(^lst) ] as lst)(&mut subs);
+
+    // Only the surface of the union is in the key, so the recursion var never has to be
+    // unrolled; the derived encoder reaches the tail through `Encode.toEncoder`.
+    assert_eq!(
+        Derived::builtin(ToEncoder, &subs, linked_list),
+        Ok(Derived::Key(DeriveKey::ToEncoder(
+            FlatEncodableKey::TagUnion(vec![
+                (TagName("Cons".into()), 2),
+                (TagName("Nil".into()), 0)
+            ])
+        )))
+    );
+}
+
+#[test]
+fn recursion_points_of_recursive_tag_unions() {
+    use roc_derive_key::encoding::{recursion_points, TypePosition};
+
+    let mut subs = Subs::new();
+    let linked_list = v!([ Nil, Cons v!(U8) v!(^lst) ] as lst)(&mut subs);
+    let tree = v!([ Leaf, Node v!(^tree) v!(U8) v!(^tree) ] as tree)(&mut subs);
+    let maybe = v!([ Nothing, Just v!(U8) ])(&mut subs);
+
+    let payload = |tag: &str, i| TypePosition::TagPayload(TagName(tag.into()), i);
+
+    assert_eq!(
+        recursion_points(&subs, linked_list),
+        vec![payload("Cons", 1)]
+    );
+    assert_eq!(
+        recursion_points(&subs, tree),
+        vec![payload("Node", 0), payload("Node", 2)]
+    );
+    assert_eq!(recursion_points(&subs, maybe), Vec::new());
+}
+
+#[test]
+fn record_with_most_fields_subs_can_hold() {
+    use roc_module::ident::Lowercase;
+    use roc_types::subs::{Content, FlatType, RecordFields};
+    use roc_types::types::RecordField;
+
+    let mut subs = Subs::new();
+    let field_names: Vec<Lowercase> = (0..u16::MAX).map(|i| format!("f{}", i).into()).collect();
+    let fields = RecordFields::insert_into_subs(
+        &mut subs,
+        (field_names.iter()).map(|name| (name.clone(), RecordField::Required(Variable::STR))),
+    );
+    let record = synth_var(
+        &mut subs,
+        Content::Structure(FlatType::Record(fields, Variable::EMPTY_RECORD)),
+    );
+
+    let mut sorted_field_names = field_names;
+    sorted_field_names.sort();
+
+    // Every field is kept in the key; none is lost to a narrow field count.
+    assert_eq!(
+        Derived::builtin(ToEncoder, &subs, record),
+        Ok(Derived::Key(DeriveKey::ToEncoder(
+            FlatEncodableKey::Record(sorted_field_names)
+        )))
+    );
+}
+
+#[test]
+fn recursive_types_are_recognized() {
+    let mut subs = Subs::new();
+    let linked_list = v!([ Nil, Cons v!(U8) v!(^lst) ] as lst)(&mut subs);
+    let holds_list = v!({ head: v!([ Nil, Cons v!(U8) v!(^lst) ] as lst), })(&mut subs);
+    let point = v!({ x: v!(U8), y: v!(U8), })(&mut subs);
+    let maybe_point = v!([ Nothing, Just v!({ x: v!(U8), y: v!(U8), }) ])(&mut subs);
+
+    assert!(is_recursive(&subs, linked_list));
+    assert!(is_recursive(&subs, holds_list));
+    assert!(!is_recursive(&subs, point));
+    assert!(!is_recursive(&subs, maybe_point));
+}
+
 #[test]
 fn list() {
     derive_test(ToEncoder, v!(Symbol::LIST_LIST v!(STR)), |golden| {
@@ -373,4 +1002,71 @@ fn list() {
     })
 }
 
+#[test]
+fn list_of_record() {
+    derive_test(
+        ToEncoder,
+        v!(Symbol::LIST_LIST v!({ a: v!(U8), b: v!(STR), })),
+        |golden| {
+            assert_snapshot!(golden, @r###"
+            # derived for List { a : U8, b : Str }
+            # List { a : val, b : val1 } -[[toEncoder_list_of_{a,b}(0)]]-> Encoder fmt | fmt has EncoderFormatting, val has Encoding, val1 has Encoding
+            # List { a : val, b : val1 } -[[toEncoder_list_of_{a,b}(0)]]-> (List U8, fmt -[[custom2(7) (List { a : val, b : val1 })]]-> List U8) | fmt has EncoderFormatting, val has Encoding, val1 has Encoding
+            # Specialization lambda sets:
+            #   @<1>: [[toEncoder_list_of_{a,b}(0)]]
+            #   @<2>: [[custom2(7) (List { a : val, b : val1 })]] | val has Encoding, val1 has Encoding
+            #Derived.toEncoder_list_of_{a,b} =
+              \#Derived.lst ->
+                Encode.custom
+                  \#Derived.bytes2, #Derived.fmt2 ->
+                    Encode.appendWith
+                      #Derived.bytes2
+                      (Encode.list
+                        #Derived.lst
+                        \#Derived.rcd ->
+                          Encode.custom
+                            \#Derived.bytes, #Derived.fmt ->
+                              Encode.appendWith
+                                #Derived.bytes
+                                (Encode.record
+                                  [
+                                    { value: Encode.toEncoder #Derived.rcd.a, key: "a", },
+                                    { value: Encode.toEncoder #Derived.rcd.b, key: "b", },
+                                  ])
+                                #Derived.fmt)
+                      #Derived.fmt2
+            "###
+            )
+        },
+    )
+}
+
+#[test]
+fn two_tuple() {
+    derive_test(ToEncoder, v!((v!(U8), v!(STR),)), |golden| {
+        assert_snapshot!(golden, @r###"
+            # derived for { 0 : U8, 1 : Str }
+            # { 0 : val, 1 : val1 } -[[toEncoder_(2)(0)]]-> Encoder fmt | fmt has EncoderFormatting, val has Encoding, val1 has Encoding
+            # { 0 : val, 1 : val1 } -[[toEncoder_(2)(0)]]-> (List U8, fmt -[[custom(4) { 0 : val, 1 : val1 }]]-> List U8) | fmt has EncoderFormatting, val has Encoding, val1 has Encoding
+            # Specialization lambda sets:
+            #   @<1>: [[toEncoder_(2)(0)]]
+            #   @<2>: [[custom(4) { 0 : val, 1 : val1 }]] | val has Encoding, val1 has Encoding
+            #Derived.toEncoder_(2) =
+              \#Derived.tup ->
+                Encode.custom
+                  \#Derived.bytes, #Derived.fmt ->
+                    Encode.appendWith
+                      #Derived.bytes
+                      (Encode.list
+                        [
+                          Encode.toEncoder #Derived.tup.0,
+                          Encode.toEncoder #Derived.tup.1,
+                        ]
+                        \#Derived.enc -> #Derived.enc)
+                      #Derived.fmt
+        "###
+        )
+    })
+}
+
 // }}} deriver tests
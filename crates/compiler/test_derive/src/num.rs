@@ -0,0 +1,60 @@
+#![cfg(test)]
+
+use roc_derive_key::num::{num_info, IntOrFrac, NumInfo};
+use roc_module::symbol::{IdentIds, ModuleId, Symbol};
+
+fn int(signed: bool, bits: u8) -> Option<NumInfo> {
+    Some(NumInfo {
+        signed,
+        bits,
+        kind: IntOrFrac::Int,
+    })
+}
+
+fn frac(bits: u8) -> Option<NumInfo> {
+    Some(NumInfo {
+        signed: true,
+        bits,
+        kind: IntOrFrac::Frac,
+    })
+}
+
+#[test]
+fn classifies_each_numeric_alias() {
+    let cases = [
+        (Symbol::NUM_U8, Symbol::NUM_UNSIGNED8, int(false, 8)),
+        (Symbol::NUM_U16, Symbol::NUM_UNSIGNED16, int(false, 16)),
+        (Symbol::NUM_U32, Symbol::NUM_UNSIGNED32, int(false, 32)),
+        (Symbol::NUM_U64, Symbol::NUM_UNSIGNED64, int(false, 64)),
+        (Symbol::NUM_U128, Symbol::NUM_UNSIGNED128, int(false, 128)),
+        (Symbol::NUM_NAT, Symbol::NUM_NATURAL, int(false, 64)),
+        (Symbol::NUM_I8, Symbol::NUM_SIGNED8, int(true, 8)),
+        (Symbol::NUM_I16, Symbol::NUM_SIGNED16, int(true, 16)),
+        (Symbol::NUM_I32, Symbol::NUM_SIGNED32, int(true, 32)),
+        (Symbol::NUM_I64, Symbol::NUM_SIGNED64, int(true, 64)),
+        (Symbol::NUM_I128, Symbol::NUM_SIGNED128, int(true, 128)),
+        (Symbol::NUM_DEC, Symbol::NUM_DECIMAL, frac(128)),
+        (Symbol::NUM_F32, Symbol::NUM_BINARY32, frac(32)),
+        (Symbol::NUM_F64, Symbol::NUM_BINARY64, frac(64)),
+    ];
+
+    for (alias, storage, expected) in cases {
+        assert_eq!(num_info(alias), expected, "{:?}", alias);
+        assert_eq!(num_info(storage), expected, "{:?}", storage);
+    }
+
+    // Nothing else in the `Num` module is a number alias.
+    let num_idents = IdentIds::exposed_builtins(0);
+    let classified = (num_idents.get(&ModuleId::NUM).unwrap().ident_strs())
+        .map(|(ident_id, _)| Symbol::new(ModuleId::NUM, ident_id))
+        .filter(|symbol| num_info(*symbol).is_some())
+        .count();
+    assert_eq!(classified, cases.len() * 2);
+}
+
+#[test]
+fn non_numbers_are_not_classified() {
+    assert_eq!(num_info(Symbol::STR_STR), None);
+    assert_eq!(num_info(Symbol::BOOL_BOOL), None);
+    assert_eq!(num_info(Symbol::NUM_NUM), None);
+}
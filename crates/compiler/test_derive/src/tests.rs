@@ -2,6 +2,7 @@
 
 mod decoding;
 mod encoding;
+mod num;
 
 mod pretty_print;
 mod util;
@@ -1,11 +1,15 @@
 use std::path::PathBuf;
+use std::sync::Mutex;
 
 use bumpalo::Bump;
+use lazy_static::lazy_static;
 use ven_pretty::DocAllocator;
 
 use crate::pretty_print::{pretty_print_def, Ctx};
 use roc_can::{
-    abilities::{AbilitiesStore, SpecializationLambdaSets},
+    abilities::{
+        AbilitiesStore, ImplKey, MemberSpecializationInfo, ResolvedImpl, SpecializationLambdaSets,
+    },
     constraint::Constraints,
     def::Def,
     expr::Declarations,
@@ -14,18 +18,19 @@ use roc_can::{
         RigidVariables,
     },
 };
-use roc_collections::VecSet;
+use roc_collections::{VecMap, VecSet};
 use roc_constrain::expr::constrain_decls;
 use roc_debug_flags::dbg_do;
-use roc_derive::DerivedModule;
+use roc_derive::{synth_var, DerivedModule};
 use roc_derive_key::{DeriveBuiltin, DeriveKey, Derived};
 use roc_load_internal::file::{add_imports, default_aliases, LoadedModule, Threading};
-use roc_module::symbol::{IdentIds, Interns, ModuleId, Symbol};
+use roc_module::symbol::{IdentIds, Interns, ModuleId, ModuleIds, Symbol};
 use roc_region::all::LineInfo;
 use roc_reporting::report::{type_problem, RocDocAllocator};
 use roc_types::{
     pretty_print::{name_and_print_var, DebugPrint},
-    subs::{ExposedTypesStorageSubs, Subs, Variable},
+    subs::{AliasVariables, Content, ExposedTypesStorageSubs, StorageSubs, Subs, Variable},
+    types::AliasKind,
 };
 
 const DERIVED_MODULE: ModuleId = ModuleId::DERIVED_SYNTH;
@@ -71,6 +76,21 @@ macro_rules! v {
              roc_derive::synth_var(subs, Content::Structure(FlatType::Record(fields, Variable::EMPTY_RECORD)))
          }
      }};
+     (( $($make_v:expr,)* )) => {{
+         #[allow(unused)]
+         use roc_types::types::RecordField;
+         use roc_types::subs::{Subs, RecordFields, Content, FlatType, Variable};
+         |subs: &mut Subs| {
+             let elems = vec![ $( $make_v(subs), )* ];
+             let fields = elems
+                 .into_iter()
+                 .enumerate()
+                 .map(|(i, v)| (i.to_string().into(), RecordField::Required(v)))
+                 .collect::<Vec<_>>();
+             let fields = RecordFields::insert_into_subs(subs, fields);
+             roc_derive::synth_var(subs, Content::Structure(FlatType::Record(fields, Variable::EMPTY_RECORD)))
+         }
+     }};
      ([ $($tag:ident $($payload:expr)*),* ]$( $ext:tt )?) => {{
          #[allow(unused)]
          use roc_types::subs::{Subs, UnionTags, Content, FlatType, Variable};
@@ -197,6 +217,74 @@ macro_rules! test_hash_neq {
     )*};
 }
 
+lazy_static! {
+    /// The identifiers of [`user_alias`]es, shared so that aliases with different names get
+    /// different symbols, whichever test synthesizes them.
+    static ref USER_ALIAS_IDENT_IDS: Mutex<IdentIds> = Mutex::new(IdentIds::default());
+}
+
+/// Synthesizes an alias named `name` of `real_var`, defined in a user module so that it is never
+/// mistaken for a builtin.
+pub(crate) fn user_alias(
+    subs: &mut Subs,
+    name: &str,
+    real_var: Variable,
+    kind: AliasKind,
+) -> (Symbol, Variable) {
+    let mut module_ids = ModuleIds::default();
+    let home = module_ids.get_or_insert(&"Test".into());
+    let ident_id = USER_ALIAS_IDENT_IDS.lock().unwrap().get_or_insert(name);
+    let symbol = Symbol::new(home, ident_id);
+
+    let alias_variables = AliasVariables::insert_into_subs::<Vec<_>, Vec<_>>(subs, vec![], vec![]);
+    let alias = synth_var(
+        subs,
+        Content::Alias(symbol, alias_variables, real_var, kind),
+    );
+
+    (symbol, alias)
+}
+
+/// The types exposed by `module`, which has nothing but the implementations of `members` for
+/// each opaque; those that are `custom` are written by hand, the rest derived.
+pub(crate) fn exposed_implementations(
+    module: ModuleId,
+    members: &[(Symbol, Symbol, bool)],
+) -> ExposedByModule {
+    let mut resolved_implementations = VecMap::default();
+    for &(opaque, ability_member, custom) in members {
+        let resolved = if custom {
+            ResolvedImpl::Impl(MemberSpecializationInfo::new(
+                ability_member,
+                VecMap::default(),
+            ))
+        } else {
+            ResolvedImpl::Derived
+        };
+        let impl_key = ImplKey {
+            opaque,
+            ability_member,
+        };
+        resolved_implementations.insert(impl_key, resolved);
+    }
+
+    let mut exposed_types = ExposedByModule::default();
+    exposed_types.insert(
+        module,
+        ExposedModuleTypes {
+            exposed_types_storage_subs: ExposedTypesStorageSubs {
+                storage_subs: StorageSubs::new(Subs::new()),
+                stored_vars_by_symbol: VecMap::default(),
+                stored_specialization_lambda_set_vars: VecMap::default(),
+                stored_ability_member_vars: VecMap::default(),
+            },
+            resolved_implementations,
+        },
+    );
+
+    exposed_types
+}
+
 pub(crate) fn check_immediate<S>(builtin: DeriveBuiltin, synth: S, immediate: Symbol)
 where
     S: FnOnce(&mut Subs) -> Variable,
@@ -2,7 +2,7 @@ use crate::subs::Variable;
 
 /// A bound placed on a number because of its literal value.
 /// e.g. `-5` cannot be unsigned, and 300 does not fit in a U8
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum NumericRange {
     IntAtLeastSigned(IntLitWidth),
     IntAtLeastEitherSign(IntLitWidth),
@@ -129,7 +129,7 @@ enum IntSignedness {
     Signed,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
 pub enum IntLitWidth {
     U8,
     U16,
@@ -1045,8 +1045,30 @@ impl VarStore {
 
         Variable(answer)
     }
+
+    /// Remembers which variable will be handed out next, so that every variable made after this
+    /// point can be handed out again with [`VarStore::rollback`].
+    pub fn checkpoint(&self) -> VarStoreCheckpoint {
+        VarStoreCheckpoint(self.next)
+    }
+
+    /// Hands out the variables made since `checkpoint` again. Only do this once nothing refers to
+    /// them anymore, like after an annotation canonicalized with them was consumed and dropped;
+    /// otherwise unrelated types would share variables.
+    pub fn rollback(&mut self, checkpoint: VarStoreCheckpoint) {
+        debug_assert!(
+            checkpoint.0 <= self.next,
+            "Rolling back to a checkpoint that is ahead of the var store"
+        );
+
+        self.next = checkpoint.0;
+    }
 }
 
+/// A point to roll a [`VarStore`] back to; see [`VarStore::checkpoint`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VarStoreCheckpoint(u32);
+
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub struct OptVariable(u32);
 
@@ -2948,7 +2970,7 @@ impl RecordFields {
         subs.field_names.reserve(size_hint);
         subs.record_fields.reserve(size_hint);
 
-        let mut length = 0;
+        let mut length: u16 = 0;
         for (k, v) in it {
             let var = *v.as_inner();
             let record_field = v.map(|_| ());
@@ -2957,7 +2979,13 @@ impl RecordFields {
             subs.variables.push(var);
             subs.record_fields.push(record_field);
 
-            length += 1;
+            // Never wrap around, or the record would silently lose its fields.
+            length = length.checked_add(1).unwrap_or_else(|| {
+                internal_error!(
+                    "records with more than {} fields are not supported",
+                    u16::MAX
+                )
+            });
         }
 
         RecordFields {
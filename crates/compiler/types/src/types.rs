@@ -12,6 +12,8 @@ use roc_module::symbol::{Interns, ModuleId, Symbol};
 use roc_region::all::{Loc, Region};
 use std::fmt;
 use std::fmt::Write;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 
 pub const TYPE_NUM: &str = "Num";
 pub const TYPE_INTEGER: &str = "Integer";
@@ -173,7 +175,7 @@ impl RecordField<Type> {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub struct LambdaSet(pub Type);
 
 impl LambdaSet {
@@ -199,7 +201,7 @@ impl LambdaSet {
     }
 }
 
-#[derive(PartialEq, Eq, Clone)]
+#[derive(PartialEq, Eq, Clone, Hash)]
 pub struct AliasCommon {
     pub symbol: Symbol,
     pub type_arguments: Vec<Type>,
@@ -221,7 +223,7 @@ impl OptAbleVar {
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Hash)]
 pub struct OptAbleType {
     pub typ: Type,
     pub opt_ability: Option<Symbol>,
@@ -290,7 +292,7 @@ pub enum Type {
 /// usage site. Unspecialized lambda sets aid us in recovering those lambda sets; when we
 /// instantiate `a` with a proper type `T`, we'll know to resolve the lambda set by extracting
 /// it at region "1" from the specialization of "default" for `T`.
-#[derive(PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
+#[derive(PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash)]
 pub struct Uls(pub Variable, pub Symbol, pub u8);
 
 impl std::fmt::Debug for Uls {
@@ -388,7 +390,7 @@ impl Clone for OptAbleType {
     }
 }
 
-#[derive(PartialEq, Eq, Clone)]
+#[derive(PartialEq, Eq, Clone, Hash)]
 pub enum TypeExtension {
     Open(Box<Type>),
     Closed,
@@ -455,6 +457,89 @@ fn write_tags<'a>(
     write!(f, "]")
 }
 
+/// Hashes the structure of a type. Regions are left out, so that a type hashes the same wherever
+/// it is written; so is the order of record fields, which does not matter for equality either.
+/// Types that are equal still hash the same.
+#[allow(clippy::derive_hash_xor_eq)]
+impl Hash for Type {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+
+        match self {
+            Type::EmptyRec | Type::EmptyTagUnion => {}
+            Type::Function(args, closure, ret) => {
+                args.hash(state);
+                closure.hash(state);
+                ret.hash(state);
+            }
+            Type::Record(fields, ext) => {
+                let mut fields: Vec<_> = fields.iter().collect();
+                fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+                fields.hash(state);
+                ext.hash(state);
+            }
+            Type::TagUnion(tags, ext) => {
+                tags.hash(state);
+                ext.hash(state);
+            }
+            Type::FunctionOrTagUnion(tag_name, symbol, ext) => {
+                tag_name.hash(state);
+                symbol.hash(state);
+                ext.hash(state);
+            }
+            Type::ClosureTag {
+                name,
+                captures,
+                ambient_function,
+            } => {
+                name.hash(state);
+                captures.hash(state);
+                ambient_function.hash(state);
+            }
+            Type::UnspecializedLambdaSet { unspecialized } => unspecialized.hash(state),
+            Type::DelayedAlias(common) => common.hash(state),
+            Type::Alias {
+                symbol,
+                type_arguments,
+                lambda_set_variables,
+                actual,
+                kind,
+            } => {
+                symbol.hash(state);
+                type_arguments.hash(state);
+                lambda_set_variables.hash(state);
+                actual.hash(state);
+                kind.hash(state);
+            }
+            Type::HostExposedAlias {
+                name,
+                type_arguments,
+                lambda_set_variables,
+                actual_var,
+                actual,
+            } => {
+                name.hash(state);
+                type_arguments.hash(state);
+                lambda_set_variables.hash(state);
+                actual_var.hash(state);
+                actual.hash(state);
+            }
+            Type::RecursiveTagUnion(rec_var, tags, ext) => {
+                rec_var.hash(state);
+                tags.hash(state);
+                ext.hash(state);
+            }
+            Type::Apply(symbol, args, _region) => {
+                symbol.hash(state);
+                args.hash(state);
+            }
+            Type::Variable(var) => var.hash(state),
+            Type::RangedNumber(range) => range.hash(state),
+            Type::Erroneous(problem) => problem.hash(state),
+        }
+    }
+}
+
 impl fmt::Debug for Type {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -541,7 +626,32 @@ impl fmt::Debug for Type {
                 }
 
                 // Sometimes it's useful to see the expansion of the alias
-                write!(f, "[ but actually {:?} ]", _actual)?;
+                match _actual.as_ref() {
+                    Type::RecursiveTagUnion(rec_var, _, _) => {
+                        // Name the recursion after the alias, rather than printing the recursion
+                        // variable wherever the alias recurs.
+                        let name = Type::Apply(
+                            *symbol,
+                            type_arguments.iter().map(|arg| arg.typ.clone()).collect(),
+                            Region::zero(),
+                        );
+                        let mut substitutions = ImMap::default();
+                        substitutions.insert(*rec_var, name);
+
+                        let mut actual = _actual.as_ref().clone();
+                        actual.substitute(&substitutions);
+
+                        if let Type::RecursiveTagUnion(_, tags, ext) = actual {
+                            write!(f, "[ but actually ")?;
+                            write_tags(f, tags.iter())?;
+                            if let TypeExtension::Open(other) = ext {
+                                other.fmt(f)?;
+                            }
+                            write!(f, " as {:?} ]", symbol)?;
+                        }
+                    }
+                    _ => write!(f, "[ but actually {:?} ]", _actual)?,
+                }
 
                 write!(f, ")")?;
 
@@ -712,6 +822,42 @@ impl Type {
         matches!(self, Type::EmptyRec)
     }
 
+    /// Whether no value can ever have this type, like `[]`, `[A []]`, or `{ a : [] }`.
+    ///
+    /// This is conservative: variables, functions, and aliases that have not been expanded yet
+    /// are all assumed to be inhabited. So are opaque types, which are often defined as `[]` just
+    /// to be used as phantom type arguments, like `Signed64 := []`.
+    pub fn is_uninhabited(&self) -> bool {
+        match self {
+            Type::EmptyTagUnion => true,
+            // Every tag needs a value of each of its payloads.
+            Type::TagUnion(tags, TypeExtension::Closed) => tags
+                .iter()
+                .all(|(_, args)| args.iter().any(|arg| arg.is_uninhabited())),
+            // Optional fields can be left out, but the others need a value.
+            Type::Record(fields, _) => fields.values().any(|field| match field {
+                RecordField::Required(typ) | RecordField::Demanded(typ) => typ.is_uninhabited(),
+                RecordField::Optional(_) | RecordField::RigidOptional(_) => false,
+            }),
+            Type::Alias { kind, actual, .. } => {
+                *kind != AliasKind::Opaque && actual.is_uninhabited()
+            }
+            Type::HostExposedAlias { actual, .. } => actual.is_uninhabited(),
+            _ => false,
+        }
+    }
+
+    /// Whether this is a function type, looking through aliases but not into other types.
+    pub fn is_function(&self) -> bool {
+        match self {
+            Type::Function(..) => true,
+            Type::Alias { actual, .. } | Type::HostExposedAlias { actual, .. } => {
+                actual.is_function()
+            }
+            _ => false,
+        }
+    }
+
     pub fn variables(&self) -> ImSet<Variable> {
         let mut result = ImSet::default();
         variables_help(self, &mut result);
@@ -719,6 +865,24 @@ impl Type {
         result
     }
 
+    /// Returns a copy of this type whose variables are renumbered in the order they first occur,
+    /// taking new variables from `var_store`. Reserved variables keep their numbers.
+    ///
+    /// `renaming` records the new number of every renamed variable, and may already hold some,
+    /// so that variables kept alongside this type can be renamed consistently with it. Starting
+    /// from an empty `renaming` and a fresh `var_store` gives the same result however the
+    /// variables were originally numbered, which makes types comparable in tests.
+    pub fn rename_all_variables(
+        &self,
+        renaming: &mut MutMap<Variable, Variable>,
+        var_store: &mut VarStore,
+    ) -> Type {
+        let mut renamed = self.clone();
+        rename_variables_help(&mut renamed, renaming, var_store);
+
+        renamed
+    }
+
     pub fn variables_detail(&self) -> VariableDetail {
         let mut result = Default::default();
         variables_help_detailed(self, &mut result);
@@ -1173,10 +1337,191 @@ impl Type {
         }
     }
 
+    /// Whether a function type appears anywhere in this type, including in its type arguments.
+    pub fn contains_function(&self) -> bool {
+        use Type::*;
+
+        let ext_contains_function = |ext: &TypeExtension| match ext {
+            TypeExtension::Open(ext) => ext.contains_function(),
+            TypeExtension::Closed => false,
+        };
+
+        match self {
+            Function(..) => true,
+            FunctionOrTagUnion(_, _, ext) => ext_contains_function(ext),
+            RecursiveTagUnion(_, tags, ext) | TagUnion(tags, ext) => {
+                ext_contains_function(ext)
+                    || tags
+                        .iter()
+                        .flat_map(|v| v.1.iter())
+                        .any(|arg| arg.contains_function())
+            }
+            Record(fields, ext) => {
+                ext_contains_function(ext)
+                    || fields
+                        .values()
+                        .any(|field| field.as_inner().contains_function())
+            }
+            DelayedAlias(AliasCommon { type_arguments, .. }) => {
+                type_arguments.iter().any(|arg| arg.contains_function())
+            }
+            Alias { actual, .. } | HostExposedAlias { actual, .. } => actual.contains_function(),
+            Apply(_, args, _) => args.iter().any(|arg| arg.contains_function()),
+            EmptyRec
+            | EmptyTagUnion
+            | ClosureTag { .. }
+            | UnspecializedLambdaSet { .. }
+            | Variable(_)
+            | RangedNumber(_)
+            | Erroneous(_) => false,
+        }
+    }
+
+    /// Whether an erroneous type appears anywhere in this type, including in its type arguments.
+    pub fn contains_erroneous(&self) -> bool {
+        use Type::*;
+
+        let ext_contains_erroneous = |ext: &TypeExtension| match ext {
+            TypeExtension::Open(ext) => ext.contains_erroneous(),
+            TypeExtension::Closed => false,
+        };
+
+        match self {
+            Erroneous(_) => true,
+            Function(args, closure, ret) => {
+                ret.contains_erroneous()
+                    || closure.contains_erroneous()
+                    || args.iter().any(|arg| arg.contains_erroneous())
+            }
+            FunctionOrTagUnion(_, _, ext) => ext_contains_erroneous(ext),
+            RecursiveTagUnion(_, tags, ext) | TagUnion(tags, ext) => {
+                ext_contains_erroneous(ext)
+                    || tags
+                        .iter()
+                        .flat_map(|v| v.1.iter())
+                        .any(|arg| arg.contains_erroneous())
+            }
+            Record(fields, ext) => {
+                ext_contains_erroneous(ext)
+                    || fields
+                        .values()
+                        .any(|field| field.as_inner().contains_erroneous())
+            }
+            ClosureTag { captures, .. } => captures.iter().any(|t| t.contains_erroneous()),
+            DelayedAlias(AliasCommon { type_arguments, .. }) => {
+                type_arguments.iter().any(|arg| arg.contains_erroneous())
+            }
+            Alias {
+                type_arguments,
+                actual,
+                ..
+            } => {
+                actual.contains_erroneous()
+                    || type_arguments
+                        .iter()
+                        .any(|arg| arg.typ.contains_erroneous())
+            }
+            HostExposedAlias {
+                type_arguments,
+                actual,
+                ..
+            } => {
+                actual.contains_erroneous()
+                    || type_arguments.iter().any(|arg| arg.contains_erroneous())
+            }
+            Apply(_, args, _) => args.iter().any(|arg| arg.contains_erroneous()),
+            EmptyRec
+            | EmptyTagUnion
+            | UnspecializedLambdaSet { .. }
+            | Variable(_)
+            | RangedNumber(_) => false,
+        }
+    }
+
     pub fn symbols(&self) -> Vec<Symbol> {
         symbols_help(self)
     }
 
+    /// The number of type constructors in this type, including the type itself. Aliases count
+    /// their arguments, lambda sets, and actual type. Useful to budget alias expansion.
+    pub fn count_nodes(&self) -> usize {
+        use Type::*;
+
+        fn count_ext(ext: &TypeExtension) -> usize {
+            match ext {
+                TypeExtension::Open(ext) => ext.count_nodes(),
+                TypeExtension::Closed => 0,
+            }
+        }
+
+        fn count_tags(tags: &[(TagName, Vec<Type>)]) -> usize {
+            tags.iter()
+                .flat_map(|(_, args)| args.iter())
+                .map(Type::count_nodes)
+                .sum()
+        }
+
+        fn count_lambda_sets(lambda_sets: &[LambdaSet]) -> usize {
+            lambda_sets.iter().map(|ls| ls.0.count_nodes()).sum()
+        }
+
+        let children = match self {
+            EmptyRec | EmptyTagUnion | Variable(_) | RangedNumber(_) | Erroneous(_) => 0,
+            UnspecializedLambdaSet { .. } => 0,
+            Function(args, closure, ret) => {
+                args.iter().map(Type::count_nodes).sum::<usize>()
+                    + closure.count_nodes()
+                    + ret.count_nodes()
+            }
+            Record(fields, ext) => {
+                fields
+                    .values()
+                    .map(|field| field.as_inner().count_nodes())
+                    .sum::<usize>()
+                    + count_ext(ext)
+            }
+            TagUnion(tags, ext) | RecursiveTagUnion(_, tags, ext) => {
+                count_tags(tags) + count_ext(ext)
+            }
+            FunctionOrTagUnion(_, _, ext) => count_ext(ext),
+            ClosureTag { captures, .. } => captures.iter().map(Type::count_nodes).sum(),
+            DelayedAlias(AliasCommon {
+                type_arguments,
+                lambda_set_variables,
+                ..
+            }) => {
+                type_arguments.iter().map(Type::count_nodes).sum::<usize>()
+                    + count_lambda_sets(lambda_set_variables)
+            }
+            Alias {
+                type_arguments,
+                lambda_set_variables,
+                actual,
+                ..
+            } => {
+                type_arguments
+                    .iter()
+                    .map(|arg| arg.typ.count_nodes())
+                    .sum::<usize>()
+                    + count_lambda_sets(lambda_set_variables)
+                    + actual.count_nodes()
+            }
+            HostExposedAlias {
+                type_arguments,
+                lambda_set_variables,
+                actual,
+                ..
+            } => {
+                type_arguments.iter().map(Type::count_nodes).sum::<usize>()
+                    + count_lambda_sets(lambda_set_variables)
+                    + actual.count_nodes()
+            }
+            Apply(_, args, _) => args.iter().map(Type::count_nodes).sum(),
+        };
+
+        1 + children
+    }
+
     /// a shallow dealias, continue until the first constructor is not an alias.
     pub fn shallow_dealias(&self) -> &Self {
         let mut result = self;
@@ -1336,8 +1681,8 @@ impl Type {
                             *self = Type::Erroneous(Problem::BadTypeArguments {
                                 symbol: *symbol,
                                 region,
-                                type_got: args.len() as u8,
-                                alias_needs: alias.type_variables.len() as u8,
+                                type_got: args.len(),
+                                alias_needs: alias.type_variables.len(),
                                 alias_kind: AliasKind::Structural,
                             });
                             return;
@@ -1586,6 +1931,125 @@ fn symbols_help(initial: &Type) -> Vec<Symbol> {
     output
 }
 
+/// Renames `var` to the next variable of `var_store`, unless it was renamed already or is reserved.
+pub fn rename_variable(
+    var: Variable,
+    renaming: &mut MutMap<Variable, Variable>,
+    var_store: &mut VarStore,
+) -> Variable {
+    if var.index() < Variable::NUM_RESERVED_VARS as u32 {
+        return var;
+    }
+
+    *renaming.entry(var).or_insert_with(|| var_store.fresh())
+}
+
+fn rename_variables_help(
+    tipe: &mut Type,
+    renaming: &mut MutMap<Variable, Variable>,
+    var_store: &mut VarStore,
+) {
+    use Type::*;
+
+    if let RecursiveTagUnion(rec, ..) = tipe {
+        *rec = rename_variable(*rec, renaming, var_store);
+    }
+
+    match tipe {
+        EmptyRec | EmptyTagUnion | Erroneous(_) | RangedNumber(_) => {}
+        // Unspecialized lambda sets are only introduced during solving.
+        UnspecializedLambdaSet { .. } => {}
+
+        Variable(v) => *v = rename_variable(*v, renaming, var_store),
+        Function(args, closure, ret) => {
+            for arg in args {
+                rename_variables_help(arg, renaming, var_store);
+            }
+            rename_variables_help(closure, renaming, var_store);
+            rename_variables_help(ret, renaming, var_store);
+        }
+        Record(fields, ext) => {
+            // Visit fields in a fixed order, whatever order the map keeps them in.
+            let mut fields: Vec<_> = fields.iter_mut().collect();
+            fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            for (_, field) in fields {
+                rename_variables_help(field.as_inner_mut(), renaming, var_store);
+            }
+            if let TypeExtension::Open(ext) = ext {
+                rename_variables_help(ext, renaming, var_store);
+            }
+        }
+        ClosureTag { captures, .. } => {
+            for capture in captures {
+                rename_variables_help(capture, renaming, var_store);
+            }
+        }
+        TagUnion(tags, ext) | RecursiveTagUnion(_, tags, ext) => {
+            for (_, args) in tags.iter_mut() {
+                for arg in args {
+                    rename_variables_help(arg, renaming, var_store);
+                }
+            }
+            if let TypeExtension::Open(ext) = ext {
+                rename_variables_help(ext, renaming, var_store);
+            }
+        }
+        FunctionOrTagUnion(_, _, ext) => {
+            if let TypeExtension::Open(ext) = ext {
+                rename_variables_help(ext, renaming, var_store);
+            }
+        }
+        DelayedAlias(AliasCommon {
+            type_arguments,
+            lambda_set_variables,
+            ..
+        }) => {
+            for arg in type_arguments {
+                rename_variables_help(arg, renaming, var_store);
+            }
+            for lambda_set in lambda_set_variables {
+                rename_variables_help(lambda_set.as_inner_mut(), renaming, var_store);
+            }
+        }
+        Alias {
+            type_arguments,
+            lambda_set_variables,
+            actual,
+            ..
+        } => {
+            for arg in type_arguments {
+                rename_variables_help(&mut arg.typ, renaming, var_store);
+            }
+            for lambda_set in lambda_set_variables {
+                rename_variables_help(lambda_set.as_inner_mut(), renaming, var_store);
+            }
+            rename_variables_help(actual, renaming, var_store);
+        }
+        HostExposedAlias {
+            type_arguments,
+            lambda_set_variables,
+            actual_var,
+            actual,
+            ..
+        } => {
+            for arg in type_arguments {
+                rename_variables_help(arg, renaming, var_store);
+            }
+            for lambda_set in lambda_set_variables {
+                rename_variables_help(lambda_set.as_inner_mut(), renaming, var_store);
+            }
+            *actual_var = rename_variable(*actual_var, renaming, var_store);
+            rename_variables_help(actual, renaming, var_store);
+        }
+        Apply(_, args, _) => {
+            for arg in args {
+                rename_variables_help(arg, renaming, var_store);
+            }
+        }
+    }
+}
+
 fn variables_help(tipe: &Type, accum: &mut ImSet<Variable>) {
     use Type::*;
 
@@ -2047,6 +2511,18 @@ pub enum AliasKind {
     Opaque,
 }
 
+impl Alias {
+    /// The type variables of this alias that don't appear in its body, like `a` in
+    /// `Id a := U64`.
+    pub fn phantom_variables(&self) -> impl Iterator<Item = &Loc<AliasVar>> {
+        let used = self.typ.variables();
+
+        self.type_variables
+            .iter()
+            .filter(move |loc_var| !used.contains(&loc_var.value.var))
+    }
+}
+
 impl AliasKind {
     pub fn as_str(&self) -> &'static str {
         match self {
@@ -2062,6 +2538,30 @@ pub struct AliasVar {
     pub var: Variable,
     /// `Some` if this variable is bound to an ability; `None` otherwise.
     pub opt_bound_ability: Option<Symbol>,
+    /// `Some` if this variable may be left out when the alias is applied, in which case it is
+    /// filled in with the given type. Only trailing variables can be left out.
+    pub opt_default: Option<Type>,
+    /// How the alias may be used when this variable is replaced with a subtype or supertype.
+    pub variance: Variance,
+}
+
+/// The variance declared for a type variable of an alias, like the `+` in `Producer +a`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Variance {
+    /// No variance was declared, so it is left to be inferred from the alias's body.
+    Inferred,
+    /// `+a`: the alias only produces values of the variable's type.
+    Covariant,
+    /// `-a`: the alias only consumes values of the variable's type.
+    Contravariant,
+    /// `=a`: the alias both produces and consumes values of the variable's type.
+    Invariant,
+}
+
+impl Default for Variance {
+    fn default() -> Self {
+        Variance::Inferred
+    }
 }
 
 impl AliasVar {
@@ -2070,6 +2570,8 @@ impl AliasVar {
             name,
             var,
             opt_bound_ability: None,
+            opt_default: None,
+            variance: Variance::Inferred,
         }
     }
 }
@@ -2111,6 +2613,35 @@ pub struct Alias {
 }
 
 impl Alias {
+    /// Fills in the defaults of the trailing type variables left out of `args`. Returns `false`,
+    /// leaving `args` untouched, if one of them has no default.
+    pub fn fill_default_type_arguments(&self, args: &mut Vec<Type>) -> bool {
+        let missing = match self.type_variables.get(args.len()..) {
+            Some(missing) => missing,
+            None => return false,
+        };
+
+        let defaults: Option<Vec<_>> = missing
+            .iter()
+            .map(|var| var.value.opt_default.clone())
+            .collect();
+
+        match defaults {
+            Some(defaults) => {
+                args.extend(defaults);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The variance declared for each of the alias's type variables, in order.
+    pub fn variances(&self) -> Vec<Variance> {
+        (self.type_variables.iter())
+            .map(|var| var.value.variance)
+            .collect()
+    }
+
     pub fn header_region(&self) -> Region {
         Region::across_all(
             [self.region]
@@ -2120,18 +2651,76 @@ impl Alias {
     }
 }
 
+/// The body of an alias, shared between all of its instantiations. Instantiating an alias only
+/// changes the variables in its body, so rather than copying the whole body each time, an
+/// [`InstantiatedAlias`] keeps a handle to this one along with what its variables stand for.
+#[derive(Clone, Debug)]
+pub struct SharedAliasBody(Arc<Type>);
+
+impl SharedAliasBody {
+    pub fn new(typ: Type) -> Self {
+        Self(Arc::new(typ))
+    }
+
+    pub fn as_type(&self) -> &Type {
+        &self.0
+    }
+
+    /// Pairs the body with the types its variables stand for in one instantiation, and the
+    /// fresh variable standing for the body if it is a recursive tag union.
+    pub fn instantiate(
+        &self,
+        substitutions: ImMap<Variable, Type>,
+        recursion_var: Option<Variable>,
+    ) -> InstantiatedAlias {
+        InstantiatedAlias {
+            body: self.clone(),
+            substitutions,
+            recursion_var,
+        }
+    }
+}
+
+/// One instantiation of a [`SharedAliasBody`]. The body is only copied when
+/// [`InstantiatedAlias::to_type`] is called.
+#[derive(Clone, Debug)]
+pub struct InstantiatedAlias {
+    body: SharedAliasBody,
+    substitutions: ImMap<Variable, Type>,
+    recursion_var: Option<Variable>,
+}
+
+impl InstantiatedAlias {
+    pub fn body(&self) -> &SharedAliasBody {
+        &self.body
+    }
+
+    pub fn to_type(&self) -> Type {
+        let mut typ = self.body.as_type().clone();
+
+        if let (Type::RecursiveTagUnion(rvar, _, _), Some(new)) = (&mut typ, self.recursion_var) {
+            *rvar = new;
+        }
+
+        typ.substitute(&self.substitutions);
+
+        typ
+    }
+}
+
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
 pub enum Problem {
     CanonicalizationProblem,
     CircularType(Symbol, Box<ErrorType>, Region),
     CyclicAlias(Symbol, Region, Vec<Symbol>),
-    UnrecognizedIdent(Ident),
+    /// The type name that was not in scope, at the application that failed.
+    UnrecognizedIdent(Loc<Ident>),
     Shadowed(Region, Loc<Ident>),
     BadTypeArguments {
         symbol: Symbol,
         region: Region,
-        type_got: u8,
-        alias_needs: u8,
+        type_got: usize,
+        alias_needs: usize,
         alias_kind: AliasKind,
     },
     InvalidModule,
@@ -2965,6 +3554,85 @@ fn instantiate_lambda_sets_as_unspecialized(
 mod test {
     use super::*;
 
+    #[test]
+    fn debug_recursive_alias_is_named() {
+        // Registers the names of builtin modules, so that their symbols can be debug-printed.
+        let _module_ids = roc_module::symbol::ModuleIds::default();
+
+        let mut var_store = VarStore::default();
+        let a = var_store.fresh();
+        let rec_var = var_store.fresh();
+        let symbol = Symbol::LIST_LIST;
+
+        // ConsList a : [Cons a (ConsList a), Nil] as ConsList a
+        let typ = Type::Alias {
+            symbol,
+            type_arguments: vec![OptAbleType {
+                typ: Type::Variable(a),
+                opt_ability: None,
+            }],
+            lambda_set_variables: vec![],
+            actual: Box::new(Type::RecursiveTagUnion(
+                rec_var,
+                vec![
+                    (
+                        TagName("Cons".into()),
+                        vec![Type::Variable(a), Type::Variable(rec_var)],
+                    ),
+                    (TagName("Nil".into()), vec![]),
+                ],
+                TypeExtension::Closed,
+            )),
+            kind: AliasKind::Structural,
+        };
+
+        assert_eq!(
+            format!("{:?}", typ),
+            format!(
+                "(Alias {sym:?} <{a:?}>[ but actually [TagName('Cons') <{a:?}> ({sym:?} <{a:?}>), TagName('Nil')] as {sym:?} ])",
+                sym = symbol,
+                a = a,
+            )
+        );
+    }
+
+    #[test]
+    fn function_predicates() {
+        let mut var_store = VarStore::default();
+        let closure = Box::new(Type::Variable(var_store.fresh()));
+        let function = Type::Function(
+            vec![Type::Variable(var_store.fresh())],
+            closure,
+            Box::new(Type::EmptyRec),
+        );
+
+        // Handler : {} -> {}
+        let alias = Type::Alias {
+            symbol: Symbol::UNDERSCORE,
+            type_arguments: vec![],
+            lambda_set_variables: vec![],
+            actual: Box::new(function.clone()),
+            kind: AliasKind::Structural,
+        };
+        // { f : {} -> {} }
+        let record = Type::Record(
+            [("f".into(), RecordField::Required(function.clone()))]
+                .into_iter()
+                .collect(),
+            TypeExtension::Closed,
+        );
+        // List ({} -> {})
+        let list = Type::Apply(Symbol::LIST_LIST, vec![function.clone()], Region::zero());
+        let str = Type::Apply(Symbol::STR_STR, vec![], Region::zero());
+
+        assert!(function.is_function() && function.contains_function());
+        assert!(alias.is_function() && alias.contains_function());
+        assert!(!record.is_function() && record.contains_function());
+        assert!(!list.is_function() && list.contains_function());
+        assert!(!str.is_function() && !str.contains_function());
+        assert!(!Type::EmptyRec.is_function() && !Type::EmptyRec.contains_function());
+    }
+
     #[test]
     fn instantiate_lambda_sets_as_unspecialized() {
         let mut var_store = VarStore::default();
@@ -3034,4 +3702,69 @@ mod test {
             _ => panic!(),
         }
     }
+
+    #[test]
+    fn count_nodes_function() {
+        let mut var_store = VarStore::default();
+        let closure = Box::new(Type::Variable(var_store.fresh()));
+
+        // Str, {} -[closure]-> a
+        let typ = Type::Function(
+            vec![
+                Type::Apply(Symbol::STR_STR, vec![], Region::zero()),
+                Type::EmptyRec,
+            ],
+            closure,
+            Box::new(Type::Variable(var_store.fresh())),
+        );
+
+        assert_eq!(typ.count_nodes(), 5);
+    }
+
+    #[test]
+    fn count_nodes_nested_record() {
+        let mut var_store = VarStore::default();
+
+        // { a : { b : Str }, c ? x }*
+        let inner = Type::Record(
+            SendMap::from_iter([(
+                "b".into(),
+                RecordField::Required(Type::Apply(Symbol::STR_STR, vec![], Region::zero())),
+            )]),
+            TypeExtension::Closed,
+        );
+        let typ = Type::Record(
+            SendMap::from_iter([
+                ("a".into(), RecordField::Required(inner)),
+                (
+                    "c".into(),
+                    RecordField::RigidOptional(Type::Variable(var_store.fresh())),
+                ),
+            ]),
+            TypeExtension::Open(Box::new(Type::Variable(var_store.fresh()))),
+        );
+
+        assert_eq!(typ.count_nodes(), 5);
+    }
+
+    #[test]
+    fn count_nodes_recursive_tag_union() {
+        let mut var_store = VarStore::default();
+        let rec_var = var_store.fresh();
+
+        // [Nil, Cons a rec] as rec
+        let typ = Type::RecursiveTagUnion(
+            rec_var,
+            vec![
+                (TagName("Nil".into()), vec![]),
+                (
+                    TagName("Cons".into()),
+                    vec![Type::Variable(var_store.fresh()), Type::Variable(rec_var)],
+                ),
+            ],
+            TypeExtension::Closed,
+        );
+
+        assert_eq!(typ.count_nodes(), 3);
+    }
 }
@@ -3068,3 +3068,43 @@ fn unify_function_or_tag_union_and_func<M: MetaCollector>(
 
     outcome
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use roc_module::symbol::{IdentIds, ModuleIds};
+    use roc_types::subs::{Subs, VariableSubsSlice};
+
+    #[test]
+    fn types_without_bodies_unify_by_name() {
+        let mut subs = Subs::new();
+        let platform_module = ModuleIds::default().get_or_insert(&"Platform".into());
+        let mut ident_ids = IdentIds::default();
+        let handle = Symbol::new(platform_module, ident_ids.add_str("Handle"));
+        let socket = Symbol::new(platform_module, ident_ids.add_str("Socket"));
+
+        // Handle Str, Handle Str, Handle U8, and Socket Str
+        let apply = |subs: &mut Subs, symbol, arg| {
+            let args = VariableSubsSlice::insert_into_subs(subs, [arg]);
+            let var = subs.fresh_unnamed_flex_var();
+            subs.set_content(var, Structure(FlatType::Apply(symbol, args)));
+            var
+        };
+        let handle_str = apply(&mut subs, handle, Variable::STR);
+        let other_handle_str = apply(&mut subs, handle, Variable::STR);
+        let handle_u8 = apply(&mut subs, handle, Variable::U8);
+        let socket_str = apply(&mut subs, socket, Variable::STR);
+
+        let mut unifies = |left, right| {
+            let snapshot = subs.snapshot();
+            let unified = unify(&mut Env::new(&mut subs), left, right, Mode::EQ);
+            subs.rollback_to(snapshot);
+            matches!(unified, Unified::Success { .. })
+        };
+
+        assert!(unifies(handle_str, other_handle_str));
+        assert!(!unifies(handle_str, handle_u8));
+        assert!(!unifies(handle_str, socket_str));
+    }
+}
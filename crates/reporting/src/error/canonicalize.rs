@@ -31,6 +31,17 @@ const DUPLICATE_NAME: &str = "DUPLICATE NAME";
 const VALUE_NOT_EXPOSED: &str = "NOT EXPOSED";
 const MODULE_NOT_IMPORTED: &str = "MODULE NOT IMPORTED";
 const NESTED_DATATYPE: &str = "NESTED DATATYPE";
+const REDUNDANT_ALIAS: &str = "REDUNDANT ALIAS";
+const INFINITE_TYPE: &str = "INFINITE TYPE";
+const TOO_MANY_INFERRED_VARIABLES: &str = "TOO MANY INFERRED VARIABLES";
+const CLOSED_ARGUMENT: &str = "CLOSED ARGUMENT";
+const UNINHABITED_TYPE: &str = "UNINHABITED TYPE";
+const UNUSED_HAS_CLAUSE: &str = "UNUSED HAS CLAUSE";
+const PHANTOM_MARKER_SHADOWED: &str = "PHANTOM MARKER SHADOWED";
+const DISALLOWED_TYPE_REFERENCE: &str = "DISALLOWED TYPE REFERENCE";
+const TYPE_NOT_ALLOWED: &str = "TYPE NOT ALLOWED";
+const OPAQUE_NOT_EXPORTED: &str = "OPAQUE TYPE NOT EXPORTED";
+const FIELD_NOT_IN_RECORD: &str = "FIELD NOT IN RECORD";
 const CONFLICTING_NUMBER_SUFFIX: &str = "CONFLICTING NUMBER SUFFIX";
 const NUMBER_OVERFLOWS_SUFFIX: &str = "NUMBER OVERFLOWS SUFFIX";
 const NUMBER_UNDERFLOWS_SUFFIX: &str = "NUMBER UNDERFLOWS SUFFIX";
@@ -43,6 +54,7 @@ const ABILITY_HAS_TYPE_VARIABLES: &str = "ABILITY HAS TYPE VARIABLES";
 const HAS_CLAUSE_IS_NOT_AN_ABILITY: &str = "HAS CLAUSE IS NOT AN ABILITY";
 const ILLEGAL_HAS_CLAUSE: &str = "ILLEGAL HAS CLAUSE";
 const ABILITY_MEMBER_MISSING_HAS_CLAUSE: &str = "ABILITY MEMBER MISSING HAS CLAUSE";
+const ABILITY_MEMBER_MISSING_SELF: &str = "ABILITY MEMBER MISSING SELF";
 const ABILITY_MEMBER_BINDS_MULTIPLE_VARIABLES: &str = "ABILITY MEMBER BINDS MULTIPLE VARIABLES";
 const ABILITY_NOT_ON_TOPLEVEL: &str = "ABILITY NOT ON TOP-LEVEL";
 const SPECIALIZATION_NOT_ON_TOPLEVEL: &str = "SPECIALIZATION NOT ON TOP-LEVEL";
@@ -558,6 +570,216 @@ pub fn can_problem<'b>(
             title = NESTED_DATATYPE.to_string();
             severity = Severity::RuntimeError;
         }
+        Problem::RedundantAsAlias {
+            alias,
+            existing,
+            region,
+        } => {
+            doc = alloc.stack([
+                alloc.concat([
+                    alloc.reflow("This "),
+                    alloc.keyword("as"),
+                    alloc.reflow(" just gives "),
+                    alloc.symbol_unqualified(existing),
+                    alloc.reflow(" another name:"),
+                ]),
+                alloc.region(lines.convert_region(region)),
+                alloc.concat([
+                    alloc.hint("Consider using "),
+                    alloc.symbol_unqualified(existing),
+                    alloc.reflow(" directly instead of introducing "),
+                    alloc.symbol_unqualified(alias),
+                    alloc.text("."),
+                ]),
+            ]);
+
+            title = REDUNDANT_ALIAS.to_string();
+            severity = Severity::Warning;
+        }
+        Problem::InfiniteType { alias, region } => {
+            doc = alloc.stack([
+                alloc.concat([
+                    alloc.symbol_unqualified(alias),
+                    alloc.reflow(" refers to itself outside of a tag payload:"),
+                ]),
+                alloc.region(lines.convert_region(region)),
+                alloc.reflow("A value of this type would have to contain itself forever."),
+                alloc.concat([
+                    alloc.hint("Recursive types must recurse through a tag, like "),
+                    alloc.type_str("[ Nil, Cons a (LinkedList a) ] as LinkedList a"),
+                    alloc.text("."),
+                ]),
+            ]);
+
+            title = INFINITE_TYPE.to_string();
+            severity = Severity::RuntimeError;
+        }
+        Problem::UnusedHasClauseVariable { name, region } => {
+            doc = alloc.stack([
+                alloc.concat([
+                    alloc.reflow("This "),
+                    alloc.keyword("has"),
+                    alloc.reflow(" clause constrains "),
+                    alloc.type_variable(name),
+                    alloc.reflow(", but the annotation never uses it:"),
+                ]),
+                alloc.region(lines.convert_region(region)),
+                alloc.reflow(
+                    "So the constraint can never apply to anything. Maybe the variable is misspelled?",
+                ),
+            ]);
+
+            title = UNUSED_HAS_CLAUSE.to_string();
+            severity = Severity::Warning;
+        }
+        Problem::UninhabitedType { region } => {
+            doc = alloc.stack([
+                alloc.reflow("No value can ever have this type:"),
+                alloc.region(lines.convert_region(region)),
+                alloc.concat([
+                    alloc.reflow("For example, "),
+                    alloc.type_str("[]"),
+                    alloc.reflow(
+                        " is a tag union without any tags, so there is no way to build one.",
+                    ),
+                ]),
+            ]);
+
+            title = UNINHABITED_TYPE.to_string();
+            severity = Severity::Warning;
+        }
+        Problem::PhantomMarkerShadowed { symbol, region } => {
+            doc = alloc.stack([
+                alloc.concat([
+                    alloc.reflow("This "),
+                    alloc.keyword("has"),
+                    alloc.reflow(" clause refers to the "),
+                    alloc.symbol_unqualified(symbol),
+                    alloc.reflow(" in scope, not the phantom marker:"),
+                ]),
+                alloc.region(lines.convert_region(region)),
+                alloc.concat([
+                    alloc.reflow("So its variable is bound to "),
+                    alloc.symbol_unqualified(symbol),
+                    alloc.reflow(" rather than marked as intentionally unused. Maybe "),
+                    alloc.symbol_unqualified(symbol),
+                    alloc.reflow(" should be renamed?"),
+                ]),
+            ]);
+
+            title = PHANTOM_MARKER_SHADOWED.to_string();
+            severity = Severity::Warning;
+        }
+        Problem::ClosedArgumentExtension { region, kind } => {
+            let (kind_str, members, closing) = match kind {
+                ExtensionTypeKind::Record => ("record", "fields", "}"),
+                ExtensionTypeKind::TagUnion => ("tag union", "tags", "]"),
+            };
+
+            doc = alloc.stack([
+                alloc.concat([
+                    alloc.reflow("This argument is a closed "),
+                    alloc.reflow(kind_str),
+                    alloc.reflow(":"),
+                ]),
+                alloc.region(lines.convert_region(region)),
+                alloc.concat([
+                    alloc.reflow("So this function only accepts a "),
+                    alloc.reflow(kind_str),
+                    alloc.reflow(" with exactly these "),
+                    alloc.reflow(members),
+                    alloc.reflow("."),
+                ]),
+                alloc.concat([
+                    alloc.hint("If it should also accept ones with more "),
+                    alloc.reflow(members),
+                    alloc.reflow(", add a "),
+                    alloc.keyword("*"),
+                    alloc.reflow(" right after the closing "),
+                    alloc.keyword(closing),
+                    alloc.reflow("."),
+                ]),
+            ]);
+
+            title = CLOSED_ARGUMENT.to_string();
+            severity = Severity::Warning;
+        }
+        Problem::DisallowedTypeReference { symbol, region } => {
+            doc = alloc.stack([
+                alloc.concat([
+                    alloc.reflow("This annotation refers to "),
+                    alloc.symbol_qualified(symbol),
+                    alloc.reflow(", which is not allowed here:"),
+                ]),
+                alloc.region(lines.convert_region(region)),
+            ]);
+
+            title = DISALLOWED_TYPE_REFERENCE.to_string();
+            severity = Severity::RuntimeError;
+        }
+        Problem::TypeNotAllowedInContext { symbol, region } => {
+            doc = alloc.stack([
+                alloc.concat([
+                    alloc.reflow("This annotation uses the builtin "),
+                    alloc.symbol_qualified(symbol),
+                    alloc.reflow(" type, which is not available here:"),
+                ]),
+                alloc.region(lines.convert_region(region)),
+            ]);
+
+            title = TYPE_NOT_ALLOWED.to_string();
+            severity = Severity::RuntimeError;
+        }
+        Problem::FieldNotInRecord { field, region } => {
+            doc = alloc.stack([
+                alloc.concat([
+                    alloc.reflow("This record type does not have a "),
+                    alloc.record_field(field),
+                    alloc.reflow(" field:"),
+                ]),
+                alloc.region(lines.convert_region(region)),
+            ]);
+
+            title = FIELD_NOT_IN_RECORD.to_string();
+            severity = Severity::RuntimeError;
+        }
+        Problem::OpaqueNotExported { opaque, region } => {
+            doc = alloc.stack([
+                alloc.concat([
+                    alloc.reflow("This annotation refers to the opaque type "),
+                    alloc.symbol_qualified(opaque),
+                    alloc.reflow(", which is not re-exported:"),
+                ]),
+                alloc.region(lines.convert_region(region)),
+                alloc.reflow(
+                    "Only opaque types that are re-exported can be used outside of their module here.",
+                ),
+            ]);
+
+            title = OPAQUE_NOT_EXPORTED.to_string();
+            severity = Severity::RuntimeError;
+        }
+        Problem::TooManyInferredVariables { limit, region } => {
+            doc = alloc.stack([
+                alloc.concat([
+                    alloc.reflow("This annotation has more than "),
+                    alloc.text(limit.to_string()),
+                    alloc.reflow(" inferred or wildcard type variables:"),
+                ]),
+                alloc.region(lines.convert_region(region)),
+                alloc.reflow(
+                    "I stopped making new type variables for this annotation past that limit.",
+                ),
+                alloc.concat([
+                    alloc.hint("Try giving some of these types explicit names, like "),
+                    alloc.type_str("a"),
+                    alloc.reflow(", or spelling them out in full."),
+                ]),
+            ]);
+
+            title = TOO_MANY_INFERRED_VARIABLES.to_string();
+            severity = Severity::RuntimeError;
+        }
 
         Problem::InvalidExtensionType { region, kind } => {
             let (kind_str, can_only_contain) = match kind {
@@ -671,6 +893,34 @@ pub fn can_problem<'b>(
             severity = Severity::RuntimeError;
         }
 
+        Problem::AbilityMemberMissingSelf {
+            member,
+            ability,
+            var_name,
+            region,
+        } => {
+            doc = alloc.stack([
+                alloc.concat([
+                    alloc.reflow("The signature of the ability member "),
+                    alloc.symbol_unqualified(member),
+                    alloc.reflow(" binds "),
+                    alloc.type_variable(var_name.clone()),
+                    alloc.reflow(" to the ability "),
+                    alloc.symbol_unqualified(ability),
+                    alloc.reflow(", but never uses "),
+                    alloc.type_variable(var_name),
+                    alloc.reflow(":"),
+                ]),
+                alloc.region(lines.convert_region(region)),
+                alloc.reflow(
+                    "The type implementing an ability is found from where this variable appears \
+                    in the signature, so an ability member must mention it.",
+                ),
+            ]);
+            title = ABILITY_MEMBER_MISSING_SELF.to_string();
+            severity = Severity::RuntimeError;
+        }
+
         Problem::AbilityMemberMultipleBoundVars {
             member,
             ability,
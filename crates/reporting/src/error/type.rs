@@ -4,9 +4,9 @@ use roc_can::expected::{Expected, PExpected};
 use roc_collections::all::{HumanIndex, MutSet, SendMap};
 use roc_exhaustive::CtorName;
 use roc_module::called_via::{BinOp, CalledVia};
-use roc_module::ident::{Ident, IdentStr, Lowercase, TagName};
+use roc_module::ident::{IdentStr, Lowercase, TagName};
 use roc_module::symbol::Symbol;
-use roc_region::all::{LineInfo, Loc, Region};
+use roc_region::all::{LineInfo, Region};
 use roc_solve_problem::{TypeError, UnderivableReason, Unfulfilled};
 use roc_std::RocDec;
 use roc_types::pretty_print::{Parens, WILDCARD};
@@ -16,7 +16,6 @@ use roc_types::types::{
 use std::path::PathBuf;
 use ven_pretty::DocAllocator;
 
-const DUPLICATE_NAME: &str = "DUPLICATE NAME";
 const ADD_ANNOTATIONS: &str = r#"Can more type annotations be added? Type annotations always help me give more specific messages, and I think they could help a lot in this case"#;
 
 const OPAQUE_NUM_SYMBOLS: &[Symbol] = &[
@@ -113,18 +112,12 @@ pub fn type_problem<'b>(
 
                     report(title, doc, filename)
                 }
-                Shadowed(original_region, shadow) => {
-                    let doc = report_shadowing(alloc, lines, original_region, shadow);
-                    let title = DUPLICATE_NAME.to_string();
-
-                    report(title, doc, filename)
-                }
-
                 SolvedTypeError => None, // Don't re-report cascading errors - see https://github.com/rtfeldman/roc/pull/1711
 
                 // We'll also report these as a canonicalization problem, no need to re-report them.
                 CyclicAlias(..) => None,
                 UnrecognizedIdent(..) => None,
+                Shadowed(..) => None,
 
                 other => panic!("unhandled bad type: {:?}", other),
             }
@@ -410,26 +403,6 @@ fn underivable_hint<'b>(
     }
 }
 
-fn report_shadowing<'b>(
-    alloc: &'b RocDocAllocator<'b>,
-    lines: &LineInfo,
-    original_region: Region,
-    shadow: Loc<Ident>,
-) -> RocDocBuilder<'b> {
-    let line = r#"Since these types have the same name, it's easy to use the wrong one on accident. Give one of them a new name."#;
-
-    alloc.stack([
-        alloc
-            .text("The ")
-            .append(alloc.ident(shadow.value))
-            .append(alloc.reflow(" name is first defined here:")),
-        alloc.region(lines.convert_region(original_region)),
-        alloc.reflow("But then it's defined a second time here:"),
-        alloc.region(lines.convert_region(shadow.region)),
-        alloc.reflow(line),
-    ])
-}
-
 pub fn cyclic_alias<'b>(
     alloc: &'b RocDocAllocator<'b>,
     lines: &LineInfo,
@@ -526,6 +526,86 @@ mod test_reporting {
     "###
     );
 
+    test_report!(
+        report_as_alias_shadowing_opaque,
+        indoc!(
+            r#"
+            Age := U32
+
+            x : [Nil, Cons Str Age] as Age
+            x = Nil
+
+            x
+       "#
+        ),
+        @r###"
+    ── DUPLICATE NAME ──────────────────────────────────────── /code/proj/Main.roc ─
+
+    The `Age` name is first defined here:
+
+    4│      Age := U32
+            ^^^^^^^^^^
+
+    But then it's defined a second time here:
+
+    6│      x : [Nil, Cons Str Age] as Age
+                                       ^^^
+
+    Since these opaques have the same name, it's easy to use the wrong one
+    on accident. Give one of them a new name.
+
+    ── UNUSED DEFINITION ───────────────────────────────────── /code/proj/Main.roc ─
+
+    `Age` is not used anywhere in your code.
+
+    4│      Age := U32
+            ^^^^^^^^^^
+
+    If you didn't intend on using `Age` then remove it so future readers of
+    your code don't wonder why it is there.
+    "###
+    );
+
+    test_report!(
+        report_as_alias_shadowing_alias,
+        indoc!(
+            r#"
+            Age : U32
+
+            x : [Nil, Cons Str Age] as Age
+            x = Nil
+
+            x
+       "#
+        ),
+        @r###"
+    ── DUPLICATE NAME ──────────────────────────────────────── /code/proj/Main.roc ─
+
+    The `Age` name is first defined here:
+
+    4│      Age : U32
+            ^^^^^^^^^
+
+    But then it's defined a second time here:
+
+    6│      x : [Nil, Cons Str Age] as Age
+                                       ^^^
+
+    Since these aliases have the same name, it's easy to use the wrong one
+    on accident. Give one of them a new name.
+
+    ── UNUSED DEFINITION ───────────────────────────────────── /code/proj/Main.roc ─
+
+    `Age` is not used anywhere in your code.
+
+    4│      Age : U32
+            ^^^^^^^^^
+
+    If you didn't intend on using `Age` then remove it so future readers of
+    your code don't wonder why it is there.
+    "###
+    );
+
     test_report!(
         report_precedence_problem_single_line,
         indoc!(
@@ -6718,6 +6798,56 @@ All branches in an `if` must have the same type!
     "###
     );
 
+    test_report!(
+        redundant_as_alias,
+        indoc!(
+            r#"
+            Id : Str
+
+            f : {} -> Id as Name
+            f = \{} -> "roc"
+
+            f
+            "#
+        ),
+        @r###"
+    ── REDUNDANT ALIAS ─────────────────────────────────────── /code/proj/Main.roc ─
+
+    This `as` just gives `Id` another name:
+
+    6│      f : {} -> Id as Name
+                      ^^^^^^^^^^
+
+    Hint: Consider using `Id` directly instead of introducing `Name`.
+    "###
+    );
+
+    test_report!(
+        phantom_marker_shadowed,
+        indoc!(
+            r#"
+            app "test" provides [tagged] to "./platform"
+
+            Phantom has tag : a -> Str | a has Phantom
+
+            tagged : a -> Str | a has Phantom
+            tagged = \x -> tag x
+            "#
+        ),
+        @r###"
+    ── PHANTOM MARKER SHADOWED ─────────────────────────────── /code/proj/Main.roc ─
+
+    This `has` clause refers to the `Phantom` in scope, not the phantom
+    marker:
+
+    5│  tagged : a -> Str | a has Phantom
+                                  ^^^^^^^
+
+    So its variable is bound to `Phantom` rather than marked as
+    intentionally unused. Maybe `Phantom` should be renamed?
+    "###
+    );
+
     macro_rules! mismatched_suffix_tests {
         ($($number:expr, $suffix:expr, $name:ident)*) => {$(
             test_report!(
@@ -8359,6 +8489,29 @@ All branches in an `if` must have the same type!
         "#
     );
 
+    test_report!(
+        ability_member_does_not_use_bound_variable,
+        indoc!(
+            r#"
+            app "test" provides [] to "./platform"
+
+            Ability has ab : {} -> {} | a has Ability
+            "#
+        ),
+        @r#"
+        ── ABILITY MEMBER MISSING SELF ─────────────────────────── /code/proj/Main.roc ─
+
+        The signature of the ability member `ab` binds `a` to the ability `Ability`,
+        but never uses `a`:
+
+        3│  Ability has ab : {} -> {} | a has Ability
+                        ^^
+
+        The type implementing an ability is found from where this variable
+        appears in the signature, so an ability member must mention it.
+        "#
+    );
+
     test_report!(
         has_clause_not_on_toplevel,
         indoc!(